@@ -0,0 +1,37 @@
+use std::fmt;
+
+const ERR_IA: &str = "Invalid argument";
+
+pub struct InvalidArgumentError {
+    pub argument: String,
+    pub reason: String,
+}
+
+impl InvalidArgumentError {
+    pub fn new(argument: &str, reason: &str) -> InvalidArgumentError {
+        InvalidArgumentError {
+            argument: argument.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for InvalidArgumentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} \"{}\": {}", ERR_IA, self.argument, self.reason)
+    }
+}
+
+impl fmt::Debug for InvalidArgumentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{{ message: {} \"{}\": {}, file: {}, line: {} }}",
+            ERR_IA,
+            self.argument,
+            self.reason,
+            file!(),
+            line!()
+        )
+    }
+}
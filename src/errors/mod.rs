@@ -1 +1,3 @@
+pub mod invalid_argument;
 pub mod missing_source_directory;
+pub mod params_error;
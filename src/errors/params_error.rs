@@ -0,0 +1,39 @@
+use crate::errors::invalid_argument::InvalidArgumentError;
+use crate::errors::missing_source_directory::MissingSourceDirectoryError;
+use std::fmt;
+
+/// # Command line parameters errors
+pub enum ParamsError {
+    MissingSourceDirectory(MissingSourceDirectoryError),
+    InvalidArgument(InvalidArgumentError),
+}
+
+impl From<MissingSourceDirectoryError> for ParamsError {
+    fn from(error: MissingSourceDirectoryError) -> Self {
+        ParamsError::MissingSourceDirectory(error)
+    }
+}
+
+impl From<InvalidArgumentError> for ParamsError {
+    fn from(error: InvalidArgumentError) -> Self {
+        ParamsError::InvalidArgument(error)
+    }
+}
+
+impl fmt::Display for ParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParamsError::MissingSourceDirectory(error) => fmt::Display::fmt(error, f),
+            ParamsError::InvalidArgument(error) => fmt::Display::fmt(error, f),
+        }
+    }
+}
+
+impl fmt::Debug for ParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParamsError::MissingSourceDirectory(error) => fmt::Debug::fmt(error, f),
+            ParamsError::InvalidArgument(error) => fmt::Debug::fmt(error, f),
+        }
+    }
+}
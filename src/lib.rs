@@ -1,16 +1,21 @@
 mod errors;
-mod traits;
+pub mod traits;
 pub mod utils;
 
-use crate::utils::app;
+use crate::utils::{app, signed_url};
 use std::env;
 use std::process::exit;
+use std::sync::Arc;
+use utils::cache_control::CacheMode;
 use utils::http_server;
+use utils::http_server::ServerContext;
+use utils::rate_limit::RateLimiter;
+use utils::stdio_stream::StdioStream;
 
 /// # Using http-mini library:
 ///
 /// **File main.rs**
-/// ```
+/// ```no_run
 /// extern crate http_mini_lib;
 ///
 /// fn main() {
@@ -19,44 +24,167 @@ use utils::http_server;
 /// ```
 // grcov-excl-start
 pub fn start() {
-    let get_params_result = app::get_params();
-    if get_params_result.is_err() {
-        println!("{}", get_params_result.err().unwrap());
-        exit(1);
+    if env::args().nth(1).as_deref() == Some("sign") {
+        sign();
+        return;
     }
-    let (address, port, source_dir) = get_params_result.unwrap();
+
+    let params = match app::get_params() {
+        Ok(params) => params,
+        Err(err) => {
+            eprintln!("{}", err);
+            exit(1);
+        }
+    };
 
     let executable_path = env::current_exe().unwrap();
     let executable_name = executable_path.file_name().unwrap();
     if executable_name.is_empty() {
-        println!("Invalid executable");
+        eprintln!("Invalid executable");
         exit(1);
     }
 
-    let result = http_server::run(address.as_str(), port);
-    if result.is_err() {
-        println!("Http Server Error: {:?}", result.unwrap());
-        exit(1);
+    if params.inetd {
+        // stdout is the connection, there is no access log
+        let context = ServerContext::from_params(params, executable_name);
+        http_server::handle_connection(StdioStream::new(), &context, "");
+        return;
     }
 
-    let listener = result.unwrap();
+    let listeners = match http_server::run(&params.listeners) {
+        Ok(listeners) => listeners,
+        Err(err) => {
+            eprintln!("Http Server Error: {:?}", err);
+            exit(1);
+        }
+    };
 
-    let llv_link_addr: String = format!(
-        "http://{}:{}",
-        if address == "::" {
-            "localhost"
-        } else {
-            address.as_str()
-        },
-        port
-    );
-    let link_addr = llv_link_addr.as_str();
-    let source_dir_path = source_dir.as_path();
+    #[cfg(all(unix, feature = "tls"))]
+    if listeners.iter().any(|listener| listener.tls.is_some()) {
+        utils::tls::install_reload_handler();
+    }
 
-    for stream in listener.incoming() {
-        let stream = stream.unwrap();
-        http_server::handle_connection(stream, source_dir_path, executable_name, link_addr);
+    println!("Serving {}", params.source_dir.display());
+    for virtual_host in &params.virtual_hosts {
+        println!(
+            "Serving {} for {}{}",
+            virtual_host.root.display(),
+            virtual_host.pattern,
+            if virtual_host.default {
+                " (default)"
+            } else {
+                ""
+            }
+        );
+    }
+    for mount in &params.mounts {
+        println!("Serving {} at {}/", mount.root.display(), mount.prefix);
+    }
+    if params.rule_files {
+        println!("Applying the _headers and _redirects files");
+    }
+    for rule in &params.access_rules {
+        println!(
+            "{} {} for {}/",
+            if rule.allow { "Allow" } else { "Deny" },
+            rule.networks
+                .iter()
+                .map(|network| network.to_string())
+                .collect::<Vec<String>>()
+                .join(", "),
+            rule.prefix
+        );
+    }
+    for auth in &params.basic_auth {
+        println!(
+            "Basic authentication for {}/ (realm {})",
+            auth.prefix, auth.realm
+        );
+    }
+    for auth in &params.token_auth {
+        println!("Bearer tokens for {}/", auth.prefix);
+    }
+    for signed in &params.signed_urls {
+        println!("Signed URLs for {}/", signed.prefix);
+    }
+    for policy in &params.cors {
+        println!("CORS for {}/", policy.prefix);
     }
+    if let Some(rate) = &params.request_rate {
+        println!(
+            "Limiting clients to {} requests per second (burst {})",
+            rate.per_second, rate.burst
+        );
+    }
+    if params.chaos.enabled {
+        println!(
+            "Injecting faults ({} rules, X-Chaos header and chaos parameter)",
+            params.chaos.rules.len()
+        );
+    }
+    if let Some(mode) = params.cache.mode {
+        println!(
+            "Caching in {} mode",
+            match mode {
+                CacheMode::Dev => "dev",
+                CacheMode::Production => "production",
+            }
+        );
+    }
+    if let Some(base_url) = &params.base_url {
+        println!(
+            "Public URL {}{}/",
+            base_url.origin.as_deref().unwrap_or(""),
+            base_url.path
+        );
+    }
+    for listener in &listeners {
+        println!("  {}", listener.url());
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &listener.tls {
+            println!("    certificate SHA-256 fingerprint {}", tls.fingerprint);
+            if let Some(ca_cert) = &tls.ca_cert {
+                println!("    signed by the local CA {}", ca_cert.display());
+            }
+        }
+    }
+
+    let rate_limiter = RateLimiter::new(
+        params.request_rate,
+        params.max_connections,
+        params.max_connections_per_ip,
+    );
+    let mut context = ServerContext::from_params(params, executable_name);
+    context.access_log = true;
+    context.rate_limiter = Arc::new(rate_limiter);
+    let context = Arc::new(context);
+
+    http_server::serve(listeners, context);
+}
+
+/// # Print a signed link
+fn sign() {
+    let params = match app::get_sign_params() {
+        Ok(params) => params,
+        Err(err) => {
+            eprintln!("{}", err);
+            exit(1);
+        }
+    };
+
+    let (origin, base_path) = match &params.base_url {
+        Some(base_url) => (
+            base_url.origin.as_deref().unwrap_or(""),
+            base_url.path.as_str(),
+        ),
+        None => ("", ""),
+    };
+    println!(
+        "{}{}{}",
+        origin,
+        base_path,
+        signed_url::signed_path(&params.secret, &params.path, params.expires, params.ip)
+    );
 }
 // grcov-excl-stop
 
@@ -78,6 +206,6 @@ mod tests {
         sleep(Duration::new(1, 0));
         drop(t);
 
-        assert_eq!(no_panic, true);
+        assert!(no_panic);
     }
 }
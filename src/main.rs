@@ -12,6 +12,9 @@
 /// Features:
 /// * directory listing
 /// * content type detection
+///
+/// Multiple listeners:
+/// > http-mini /path/to/target/directory 127.0.0.1:8080 [::1]:8081 --listen=192.168.1.23:8090
 // grcov-excl-start
 fn main() {
     http_mini_lib::start();
@@ -0,0 +1,126 @@
+use crate::utils::x509::ClientIdentity;
+use std::io::{Read, Result, Write};
+use std::net::{SocketAddr, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+/// # Client connection
+///
+/// Any bidirectional byte stream a request can be read from and a response written to.
+pub trait Connection: Read + Write {
+    /// # Address of the remote peer, when the transport has one
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    /// # Identity of a verified client certificate
+    fn client_identity(&self) -> Option<ClientIdentity> {
+        None
+    }
+
+    /// # Application protocol negotiated with ALPN, i.e. `h2`
+    fn alpn_protocol(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// # Whether the connection is encrypted with TLS
+    fn is_tls(&self) -> bool {
+        false
+    }
+
+    /// # Fail reads blocking longer than `timeout`, `None` blocks forever
+    fn set_read_timeout(&self, _timeout: Option<Duration>) -> Result<()> {
+        Ok(())
+    }
+
+    /// # Fail writes blocking longer than `timeout`, `None` blocks forever
+    fn set_write_timeout(&self, _timeout: Option<Duration>) -> Result<()> {
+        Ok(())
+    }
+
+    /// # Make closing the connection reset it, when the transport supports it
+    fn abort(&self) {}
+}
+
+impl Connection for TcpStream {
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        TcpStream::peer_addr(self).ok()
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+
+    /// With the `chaos-reset` feature a zero linger time sends a reset on close, otherwise the
+    /// connection is shut down.
+    fn abort(&self) {
+        #[cfg(all(unix, feature = "chaos-reset"))]
+        {
+            use std::os::fd::AsRawFd;
+            let linger = libc::linger {
+                l_onoff: 1,
+                l_linger: 0,
+            };
+            // SAFETY: the descriptor is open for the lifetime of `self`
+            let result = unsafe {
+                libc::setsockopt(
+                    self.as_raw_fd(),
+                    libc::SOL_SOCKET,
+                    libc::SO_LINGER,
+                    &linger as *const libc::linger as *const libc::c_void,
+                    std::mem::size_of::<libc::linger>() as libc::socklen_t,
+                )
+            };
+            if result == 0 {
+                return;
+            }
+        }
+        let _ = self.shutdown(std::net::Shutdown::Both);
+    }
+}
+
+#[cfg(unix)]
+impl Connection for UnixStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        UnixStream::set_write_timeout(self, timeout)
+    }
+}
+
+impl<C: Connection + ?Sized> Connection for &mut C {
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        (**self).peer_addr()
+    }
+
+    fn client_identity(&self) -> Option<ClientIdentity> {
+        (**self).client_identity()
+    }
+
+    fn alpn_protocol(&self) -> Option<Vec<u8>> {
+        (**self).alpn_protocol()
+    }
+
+    fn is_tls(&self) -> bool {
+        (**self).is_tls()
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        (**self).set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        (**self).set_write_timeout(timeout)
+    }
+
+    fn abort(&self) {
+        (**self).abort()
+    }
+}
@@ -1 +1,2 @@
+pub mod connection;
 pub mod stream_trait;
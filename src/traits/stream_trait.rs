@@ -1,5 +1,10 @@
-use crate::utils::http_request::{HttpRequest, ParseHttpRequestError};
+use crate::utils::http_request::{HttpRequest, ParseHttpRequestError, RequestLimits};
 
 pub trait StreamTrait {
-    fn parse(&self) -> Result<HttpRequest, ParseHttpRequestError>;
+    fn parse(&mut self) -> Result<HttpRequest, ParseHttpRequestError>;
+
+    fn parse_with_limits(
+        &mut self,
+        limits: &RequestLimits,
+    ) -> Result<HttpRequest, ParseHttpRequestError>;
 }
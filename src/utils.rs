@@ -1,6 +1,41 @@
+pub mod access_control;
+pub mod access_log;
 pub mod app;
+pub mod base64;
+pub mod base_url;
+pub mod basic_auth;
+pub mod cache_control;
+pub mod chaos;
+pub mod cidr;
+pub mod cors;
+pub mod date;
+pub mod digest;
+pub mod forwarded;
 pub mod fs;
+pub mod glob;
+pub mod hpack;
+pub mod htpasswd;
+pub mod http2;
 pub mod http_request;
 pub mod http_response;
 pub mod http_server;
+pub mod listener;
 pub mod mimes;
+pub mod mock_connection;
+pub mod mount;
+pub mod proxy_protocol;
+pub mod rate_limit;
+pub mod security_headers;
+#[cfg(feature = "tls")]
+pub mod self_signed;
+pub mod sha_crypt;
+pub mod signed_url;
+pub mod site_rules;
+pub mod stdio_stream;
+pub mod throttle;
+pub mod timeouts;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod token_auth;
+pub mod virtual_host;
+pub mod x509;
@@ -0,0 +1,250 @@
+use crate::errors::invalid_argument::InvalidArgumentError;
+use crate::utils::cidr::{self, Cidr};
+use crate::utils::mount;
+use crate::utils::x509::ClientIdentity;
+use std::cmp::Reverse;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// # Allow or deny rule for client addresses and certificates
+#[derive(Debug)]
+pub struct AccessRule {
+    /// URL path prefix without a trailing slash, empty for the whole site
+    pub prefix: String,
+    pub allow: bool,
+    /// Client networks, any address when empty
+    pub networks: Vec<Cidr>,
+    /// Client certificate common name patterns
+    pub subjects: Vec<String>,
+    /// Client certificate alternative name patterns
+    pub alt_names: Vec<String>,
+}
+
+impl AccessRule {
+    /// # Parse an `--allow` or `--deny` rule
+    ///
+    /// Format: `CIDR[,CIDR...][,subject=PATTERN][,san=PATTERN][,path=/PREFIX]`, i.e.
+    /// `127.0.0.1,::1,path=/admin`. `all` stands for every IPv4 and IPv6 address. With
+    /// `subject` or `san`, the rule only covers clients whose TLS certificate matches one of the
+    /// patterns, from any address when no network is given.
+    pub fn parse(spec: &str, allow: bool) -> Result<AccessRule, InvalidArgumentError> {
+        let mut prefix = String::new();
+        let mut networks = vec![];
+        let mut subjects = vec![];
+        let mut alt_names = vec![];
+        for part in spec.split(',') {
+            match part.split_once('=') {
+                Some(("path", value)) if value.starts_with('/') => {
+                    prefix = value.trim_end_matches('/').to_string()
+                }
+                Some(("subject", value)) if !value.is_empty() => subjects.push(value.to_string()),
+                Some(("san", value)) if !value.is_empty() => alt_names.push(value.to_string()),
+                Some(_) => {
+                    return Err(InvalidArgumentError::new(
+                        spec,
+                        format!("invalid access rule option {}", part).as_str(),
+                    ));
+                }
+                None if part == "all" => {
+                    networks.push(Cidr::parse("0.0.0.0/0")?);
+                    networks.push(Cidr::parse("::/0")?);
+                }
+                None => networks.push(Cidr::parse(part)?),
+            }
+        }
+        if networks.is_empty() && subjects.is_empty() && alt_names.is_empty() {
+            return Err(InvalidArgumentError::new(
+                spec,
+                "missing network or client certificate pattern",
+            ));
+        }
+
+        Ok(AccessRule {
+            prefix,
+            allow,
+            networks,
+            subjects,
+            alt_names,
+        })
+    }
+
+    /// # Whether the networks contain every IPv4 and IPv6 address, as `all` does
+    fn covers_all(&self) -> bool {
+        let contains_all = |address: IpAddr| {
+            self.networks
+                .iter()
+                .any(|network| network.contains_all() && network.contains(address))
+        };
+        contains_all(Ipv4Addr::UNSPECIFIED.into()) && contains_all(Ipv6Addr::UNSPECIFIED.into())
+    }
+
+    /// # Whether the rule covers a client
+    ///
+    /// Clients without an address only match rules covering every address.
+    fn matches(&self, client: Option<IpAddr>, identity: Option<&ClientIdentity>) -> bool {
+        let address_matches = self.networks.is_empty()
+            || match client {
+                Some(client) => cidr::any_contains(&self.networks, client),
+                None => self.covers_all(),
+            };
+        let identity_matches = (self.subjects.is_empty() && self.alt_names.is_empty())
+            || identity
+                .is_some_and(|identity| identity.is_allowed(&self.subjects, &self.alt_names));
+
+        address_matches && identity_matches
+    }
+}
+
+/// # Whether a client may access a URL path
+///
+/// The rules of the longest prefix matching the path apply first, in command line order, and
+/// the first rule covering the client address and certificate decides. When none covers the
+/// client, the rules of the next shorter prefix apply, down to the rules of the whole site. A
+/// client matching no rule is allowed. Clients without an IP address, on Unix sockets or
+/// hidden by a proxy, only match rules covering `all`.
+pub fn is_allowed(
+    rules: &[AccessRule],
+    path: &str,
+    client: Option<IpAddr>,
+    identity: Option<&ClientIdentity>,
+) -> bool {
+    let mut prefixes: Vec<&str> = rules
+        .iter()
+        .map(|rule| rule.prefix.as_str())
+        .filter(|prefix| mount::strip_path_prefix(path, prefix).is_some())
+        .collect();
+    prefixes.sort_by_key(|prefix| Reverse(prefix.len()));
+    prefixes.dedup();
+
+    prefixes
+        .iter()
+        .find_map(|prefix| {
+            rules
+                .iter()
+                .filter(|rule| rule.prefix == *prefix)
+                .find(|rule| rule.matches(client, identity))
+        })
+        .is_none_or(|rule| rule.allow)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::access_control::{is_allowed, AccessRule};
+    use crate::utils::x509::ClientIdentity;
+    use std::net::IpAddr;
+
+    fn ip(address: &str) -> Option<IpAddr> {
+        Option::from(address.parse::<IpAddr>().unwrap())
+    }
+
+    #[test]
+    fn test_parse() {
+        let rule = AccessRule::parse("127.0.0.1,::1,path=/admin/", true).unwrap();
+        assert_eq!(rule.prefix, "/admin");
+        assert_eq!(rule.networks.len(), 2);
+        assert_eq!(AccessRule::parse("all", false).unwrap().networks.len(), 2);
+        assert!(AccessRule::parse("path=/admin", false).is_err());
+        assert!(AccessRule::parse("10.0.0.0/33", false).is_err());
+        assert!(AccessRule::parse("10.0.0.0/8,path=admin", false).is_err());
+        let rule = AccessRule::parse("subject=ci-*,san=*.example.com,path=/deploy", true).unwrap();
+        assert!(rule.networks.is_empty());
+        assert_eq!(rule.subjects, vec!["ci-*"]);
+        assert_eq!(rule.alt_names, vec!["*.example.com"]);
+    }
+
+    #[test]
+    fn test_is_allowed() {
+        let rules = vec![
+            AccessRule::parse("192.168.0.0/16", true).unwrap(),
+            AccessRule::parse("all", false).unwrap(),
+            AccessRule::parse("127.0.0.1,::1,path=/private", true).unwrap(),
+            AccessRule::parse("all,path=/private", false).unwrap(),
+        ];
+        assert!(is_allowed(&rules, "/index.html", ip("192.168.1.20"), None));
+        assert!(is_allowed(
+            &rules,
+            "/index.html",
+            ip("::ffff:192.168.1.20"),
+            None
+        ));
+        assert!(!is_allowed(&rules, "/index.html", ip("203.0.113.7"), None));
+        assert!(!is_allowed(&rules, "/index.html", ip("2001:db8::1"), None));
+        assert!(!is_allowed(&rules, "/private/a", ip("192.168.1.20"), None));
+        assert!(is_allowed(
+            &rules,
+            "/private/a",
+            ip("::ffff:127.0.0.1"),
+            None
+        ));
+        assert!(is_allowed(&rules, "/private", ip("::1"), None));
+        assert!(!is_allowed(&rules, "/private2", ip("127.0.0.1"), None));
+        assert!(!is_allowed(&rules, "/private", None, None));
+        assert!(!is_allowed(&rules, "/index.html", None, None));
+        assert!(is_allowed(&rules[..1], "/index.html", None, None));
+        assert!(is_allowed(
+            &rules[2..],
+            "/index.html",
+            ip("203.0.113.7"),
+            None
+        ));
+    }
+
+    #[test]
+    fn test_is_allowed_certificate() {
+        let rules = vec![
+            AccessRule::parse("10.0.0.0/8,subject=ci-*,path=/deploy", true).unwrap(),
+            AccessRule::parse("all,path=/deploy", false).unwrap(),
+        ];
+        let identity = ClientIdentity {
+            common_name: Option::from("ci-runner".to_string()),
+            alt_names: vec![],
+        };
+        let other = ClientIdentity {
+            common_name: Option::from("alice".to_string()),
+            alt_names: vec![],
+        };
+        assert!(is_allowed(
+            &rules,
+            "/deploy/a",
+            ip("10.0.0.2"),
+            Some(&identity)
+        ));
+        assert!(!is_allowed(
+            &rules,
+            "/deploy/a",
+            ip("10.0.0.2"),
+            Some(&other)
+        ));
+        assert!(!is_allowed(&rules, "/deploy/a", ip("10.0.0.2"), None));
+        assert!(!is_allowed(
+            &rules,
+            "/deploy/a",
+            ip("203.0.113.7"),
+            Some(&identity)
+        ));
+    }
+
+    #[test]
+    fn test_is_allowed_fallback() {
+        let rules = vec![
+            AccessRule::parse("all", false).unwrap(),
+            AccessRule::parse("10.0.0.0/8,path=/public", true).unwrap(),
+            AccessRule::parse("10.0.0.5,path=/public/secret", false).unwrap(),
+        ];
+        assert!(is_allowed(&rules, "/public/a", ip("10.0.0.2"), None));
+        // no rule of /public covers the client, the global deny does
+        assert!(!is_allowed(&rules, "/public/a", ip("203.0.113.7"), None));
+        assert!(!is_allowed(
+            &rules,
+            "/public/secret/a",
+            ip("10.0.0.5"),
+            None
+        ));
+        assert!(is_allowed(&rules, "/public/secret/a", ip("10.0.0.6"), None));
+        assert!(!is_allowed(
+            &rules,
+            "/public/secret/a",
+            ip("203.0.113.7"),
+            None
+        ));
+    }
+}
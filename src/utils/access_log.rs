@@ -0,0 +1,36 @@
+use crate::utils::date;
+use std::net::IpAddr;
+
+/// # Access log entry
+///
+/// Written in the Common Log Format, with the authenticated user in the `authuser` field:
+/// `127.0.0.1 - alice [10/Oct/2000:13:55:36 +0000] "GET /index.html HTTP/1.1" 200 2326`
+pub struct AccessLogEntry<'a> {
+    pub client: Option<IpAddr>,
+    pub user: Option<String>,
+    pub request_line: &'a str,
+    pub status: u16,
+    pub bytes: usize,
+}
+
+impl AccessLogEntry<'_> {
+    /// # Format the entry
+    pub fn format(&self) -> String {
+        format!(
+            "{} - {} [{}] \"{}\" {} {}",
+            self.client
+                .map(|addr| addr.to_string())
+                .unwrap_or("-".to_string()),
+            self.user.as_deref().unwrap_or("-"),
+            date::format_clf(date::now()),
+            self.request_line,
+            self.status,
+            self.bytes
+        )
+    }
+}
+
+/// # Write an access log entry
+pub fn log(entry: &AccessLogEntry) {
+    println!("{}", entry.format());
+}
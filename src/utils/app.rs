@@ -1,25 +1,550 @@
+use crate::errors::invalid_argument::InvalidArgumentError;
 use crate::errors::missing_source_directory::MissingSourceDirectoryError;
+use crate::errors::params_error::ParamsError;
+use crate::utils::access_control::AccessRule;
+use crate::utils::base_url::BaseUrl;
+use crate::utils::basic_auth::BasicAuth;
+use crate::utils::cache_control::{CacheMode, CachePolicy, CacheRule};
+use crate::utils::chaos::{Chaos, ChaosRule};
+use crate::utils::cidr::Cidr;
+use crate::utils::cors::CorsPolicy;
+use crate::utils::date;
+use crate::utils::http_request::RequestLimits;
+use crate::utils::mount::Mount;
+use crate::utils::proxy_protocol::ProxyProtocol;
+use crate::utils::rate_limit::RequestRate;
+use crate::utils::security_headers::SecurityHeaders;
+use crate::utils::signed_url::{read_secret, SignedUrls};
+use crate::utils::throttle::{self, Bandwidth, PathRate};
+use crate::utils::timeouts::{self, Timeouts};
+use crate::utils::token_auth::TokenAuth;
+use crate::utils::virtual_host::VirtualHost;
 
 use std::convert::Infallible;
-use std::net::{AddrParseError, IpAddr};
+use std::fmt;
+use std::net::{AddrParseError, IpAddr, SocketAddr};
 use std::num::ParseIntError;
 use std::path::PathBuf;
 use std::{env, fs};
 
-/// # Get source directory, port and IP address from command line arguments
+const DEFAULT_ADDRESS: &str = "::";
+const DEFAULT_PORT: i32 = 8080;
+const UNIX_PREFIX: &str = "unix:";
+const DEFAULT_SIGNED_URL_TTL: i64 = 3600;
+
+/// # Listener bind target
+#[derive(Debug, Clone, PartialEq)]
+pub enum BindTarget {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+    /// Listening socket passed by the service manager (systemd socket activation)
+    Inherited(i32),
+}
+
+/// # TLS parameters
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TlsParams {
+    /// PEM certificate chain
+    pub cert: Option<PathBuf>,
+    /// PEM private key
+    pub key: Option<PathBuf>,
+    /// Refuse TLS 1.2 clients
+    pub tls13_only: bool,
+    /// Generate a development certificate instead of `cert` and `key`
+    pub self_signed: bool,
+    /// Sign the development certificate with a local CA
+    pub local_ca: bool,
+    /// File mapping server names to certificates, the certificate above is the default
+    pub sni_map: Option<PathBuf>,
+    /// PEM bundle of the CAs which sign client certificates
+    pub client_ca: Option<PathBuf>,
+    /// Accept clients without a certificate
+    pub client_auth_optional: bool,
+    /// Allowed client certificate common names, `*` and `?` wildcards are supported
+    pub client_subjects: Vec<String>,
+    /// Allowed client certificate alternative names, `*` and `?` wildcards are supported
+    pub client_sans: Vec<String>,
+}
+
+impl TlsParams {
+    /// # Fill in the settings missing from `self` with `defaults`
+    fn merge(&mut self, defaults: &TlsParams) {
+        if self.cert.is_none() {
+            self.cert = defaults.cert.clone();
+        }
+        if self.key.is_none() {
+            self.key = defaults.key.clone();
+        }
+        self.tls13_only = self.tls13_only || defaults.tls13_only;
+        self.self_signed = self.self_signed || defaults.self_signed;
+        self.local_ca = self.local_ca || defaults.local_ca;
+        if self.sni_map.is_none() {
+            self.sni_map = defaults.sni_map.clone();
+        }
+        if self.client_ca.is_none() {
+            self.client_ca = defaults.client_ca.clone();
+        }
+        self.client_auth_optional = self.client_auth_optional || defaults.client_auth_optional;
+        if self.client_subjects.is_empty() {
+            self.client_subjects = defaults.client_subjects.clone();
+        }
+        if self.client_sans.is_empty() {
+            self.client_sans = defaults.client_sans.clone();
+        }
+    }
+
+    /// # Whether a certificate is available
+    fn has_certificate(&self) -> bool {
+        self.self_signed || self.cert.is_some()
+    }
+}
+
+impl fmt::Display for BindTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BindTarget::Tcp(addr) => write!(f, "{}", addr),
+            BindTarget::Unix(path) => write!(f, "{}{}", UNIX_PREFIX, path.display()),
+            BindTarget::Inherited(fd) => write!(f, "fd:{}", fd),
+        }
+    }
+}
+
+/// # Listener parameters
+///
+/// A bind target together with the options which apply only to this listener.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListenerParams {
+    pub target: BindTarget,
+    /// Permissions of a Unix socket file, i.e. `0o660`
+    pub unix_mode: Option<u32>,
+    /// Serve HTTPS
+    pub tls: Option<TlsParams>,
+    /// Redirect every request to HTTPS on this port
+    pub redirect_https: Option<u16>,
+    /// Read a PROXY protocol header before anything else
+    pub proxy_protocol: Option<ProxyProtocol>,
+}
+
+impl ListenerParams {
+    /// # Listener parameters with default options
+    pub fn new(target: BindTarget) -> ListenerParams {
+        ListenerParams {
+            target,
+            unix_mode: None,
+            tls: None,
+            redirect_https: None,
+            proxy_protocol: None,
+        }
+    }
+
+    /// # Parse a listener specification
+    ///
+    /// Format: `TARGET[,option[=value]...]` where `TARGET` is either `ADDRESS:PORT`
+    /// i.e. `127.0.0.1:8080`, `[::1]:8090`, or `unix:PATH` i.e. `unix:/run/http-mini.sock`.
+    ///
+    /// Options:
+    /// * `mode=OCTAL`: permissions of a Unix socket file, i.e. `mode=660`
+    /// * `tls`: serve HTTPS (requires the `tls` feature)
+    /// * `cert=PATH`, `key=PATH`: PEM certificate chain and private key of this listener
+    /// * `min-version=1.3`: refuse TLS 1.2 clients
+    /// * `self-signed`: generate a development certificate for this machine
+    /// * `local-ca`: sign the development certificate with a local CA
+    /// * `sni-map=PATH`: file of `SERVER-NAME CERT KEY` lines selecting the certificate by
+    ///   the server name requested by clients
+    /// * `client-ca=PATH`: verify client certificates signed by the CAs of this PEM bundle
+    /// * `client-auth=required|optional`: whether clients must present a certificate
+    /// * `client-subject=PATTERN`, `client-san=PATTERN`: allow only client certificates with
+    ///   a matching common name or alternative name, may be repeated
+    /// * `redirect-https[=PORT]`: redirect all requests to HTTPS, by default on the port
+    ///   of the first TLS listener
+    /// * `proxy-protocol[=required|optional]`: read the client address from a PROXY protocol
+    ///   v1 or v2 header, connections without it are refused unless optional
+    pub fn parse(spec: &str) -> Result<ListenerParams, InvalidArgumentError> {
+        let mut parts = spec.split(',');
+        let target = parts.next().unwrap_or_default();
+        let target = match target.strip_prefix(UNIX_PREFIX) {
+            Some(path) => {
+                if !cfg!(unix) {
+                    return Err(InvalidArgumentError::new(
+                        spec,
+                        "Unix sockets are not supported on this platform",
+                    ));
+                }
+                if path.is_empty() {
+                    return Err(InvalidArgumentError::new(spec, "missing socket path"));
+                }
+                BindTarget::Unix(PathBuf::from(path))
+            }
+            None => match target.parse() {
+                Ok(addr) => BindTarget::Tcp(addr),
+                Err(_) => {
+                    return Err(InvalidArgumentError::new(
+                        spec,
+                        "expected ADDRESS:PORT or unix:PATH",
+                    ));
+                }
+            },
+        };
+
+        let mut params = ListenerParams::new(target);
+        for option in parts {
+            let (name, value) = option.split_once('=').unwrap_or((option, ""));
+            match name {
+                "mode" => {
+                    if !matches!(params.target, BindTarget::Unix(_)) {
+                        return Err(InvalidArgumentError::new(
+                            spec,
+                            "mode applies only to Unix sockets",
+                        ));
+                    }
+                    match u32::from_str_radix(value, 8) {
+                        Ok(mode) if mode <= 0o777 => params.unix_mode = Option::from(mode),
+                        _ => {
+                            return Err(InvalidArgumentError::new(
+                                spec,
+                                "mode must be octal permissions, i.e. mode=660",
+                            ));
+                        }
+                    }
+                }
+                "tls" => {
+                    check_tls_support(spec)?;
+                    params.tls.get_or_insert_with(TlsParams::default);
+                }
+                "cert" | "key" | "min-version" | "self-signed" | "local-ca" | "sni-map"
+                | "client-ca" | "client-auth" | "client-subject" | "client-san" => {
+                    check_tls_support(spec)?;
+                    let tls = params.tls.get_or_insert_with(TlsParams::default);
+                    parse_tls_option(tls, name, value)
+                        .map_err(|reason| InvalidArgumentError::new(spec, reason))?;
+                }
+                "redirect-https" => {
+                    if value.is_empty() {
+                        params.redirect_https = Option::from(0);
+                    } else {
+                        match value.parse::<u16>() {
+                            Ok(port) if port > 0 => params.redirect_https = Option::from(port),
+                            _ => {
+                                return Err(InvalidArgumentError::new(
+                                    spec,
+                                    "redirect-https expects a port number",
+                                ));
+                            }
+                        }
+                    }
+                }
+                "proxy-protocol" => {
+                    params.proxy_protocol = match value {
+                        "" | "required" => Option::from(ProxyProtocol::Required),
+                        "optional" => Option::from(ProxyProtocol::Optional),
+                        _ => {
+                            return Err(InvalidArgumentError::new(
+                                spec,
+                                "proxy-protocol must be required or optional",
+                            ));
+                        }
+                    }
+                }
+                _ => {
+                    return Err(InvalidArgumentError::new(
+                        spec,
+                        format!("unknown listener option {}", option).as_str(),
+                    ));
+                }
+            }
+        }
+        if params.tls.is_some() && params.redirect_https.is_some() {
+            return Err(InvalidArgumentError::new(
+                spec,
+                "a TLS listener cannot redirect to HTTPS",
+            ));
+        }
+
+        Ok(params)
+    }
+}
+
+/// # Fail when the TLS feature is not available
+fn check_tls_support(argument: &str) -> Result<(), InvalidArgumentError> {
+    if cfg!(feature = "tls") {
+        Ok(())
+    } else {
+        Err(InvalidArgumentError::new(
+            argument,
+            "http-mini was built without the tls feature",
+        ))
+    }
+}
+
+/// # Parse a TLS setting shared by `--tls-NAME=VALUE` and listener `NAME=VALUE` options
+fn parse_tls_option(tls: &mut TlsParams, name: &str, value: &str) -> Result<(), &'static str> {
+    match name {
+        "cert" | "key" | "sni-map" | "client-ca" if value.is_empty() => {
+            return Err("missing file path")
+        }
+        "client-subject" | "client-san" if value.is_empty() => return Err("missing pattern"),
+        "cert" => tls.cert = Option::from(PathBuf::from(value)),
+        "key" => tls.key = Option::from(PathBuf::from(value)),
+        "min-version" => match value {
+            "1.2" => tls.tls13_only = false,
+            "1.3" => tls.tls13_only = true,
+            _ => return Err("supported TLS versions are 1.2 and 1.3"),
+        },
+        "self-signed" => tls.self_signed = true,
+        "local-ca" => {
+            tls.self_signed = true;
+            tls.local_ca = true;
+        }
+        "sni-map" => tls.sni_map = Option::from(PathBuf::from(value)),
+        "client-ca" => tls.client_ca = Option::from(PathBuf::from(value)),
+        "client-auth" => match value {
+            "required" => tls.client_auth_optional = false,
+            "optional" => tls.client_auth_optional = true,
+            _ => return Err("client-auth must be required or optional"),
+        },
+        "client-subject" => tls.client_subjects.push(value.to_string()),
+        "client-san" => tls.client_sans.push(value.to_string()),
+        _ => return Err("unknown TLS option"),
+    }
+
+    Ok(())
+}
+
+/// # Complete TLS settings of listeners
+///
+/// Applies the global TLS settings and resolves the target port of HTTPS redirects.
+/// Without explicit TLS listeners, a global certificate enables TLS on all listeners
+/// which do not redirect to HTTPS.
+fn finalize_tls(
+    listeners: &mut [ListenerParams],
+    tls_defaults: &TlsParams,
+) -> Result<(), InvalidArgumentError> {
+    if tls_defaults.has_certificate() && listeners.iter().all(|listener| listener.tls.is_none()) {
+        for listener in listeners.iter_mut() {
+            if listener.redirect_https.is_none() {
+                listener.tls = Option::from(TlsParams::default());
+            }
+        }
+    }
+
+    let https_port = listeners
+        .iter()
+        .filter(|listener| listener.tls.is_some())
+        .find_map(|listener| match listener.target {
+            BindTarget::Tcp(addr) => Some(addr.port()),
+            _ => None,
+        })
+        .unwrap_or(443);
+
+    for listener in listeners.iter_mut() {
+        if listener.redirect_https == Some(0) {
+            listener.redirect_https = Option::from(https_port);
+        }
+        if let Some(tls) = listener.tls.as_mut() {
+            tls.merge(tls_defaults);
+            if tls.self_signed && (tls.cert.is_some() || tls.key.is_some()) {
+                return Err(InvalidArgumentError::new(
+                    listener.target.to_string().as_str(),
+                    "a self-signed certificate cannot be combined with a certificate file",
+                ));
+            }
+            if !tls.self_signed && (tls.cert.is_none() || tls.key.is_none()) {
+                return Err(InvalidArgumentError::new(
+                    listener.target.to_string().as_str(),
+                    "TLS listeners require --tls-cert and --tls-key, --tls-self-signed, or the matching listener options",
+                ));
+            }
+            if tls.client_ca.is_none()
+                && (tls.client_auth_optional
+                    || !tls.client_subjects.is_empty()
+                    || !tls.client_sans.is_empty())
+            {
+                return Err(InvalidArgumentError::new(
+                    listener.target.to_string().as_str(),
+                    "client certificate options require --tls-client-ca or the client-ca listener option",
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// # Application parameters
+pub struct Params {
+    pub listeners: Vec<ListenerParams>,
+    pub source_dir: PathBuf,
+    /// Serve a single connection on stdin/stdout, then exit
+    pub inetd: bool,
+    pub virtual_hosts: Vec<VirtualHost>,
+    pub mounts: Vec<Mount>,
+    pub base_url: Option<BaseUrl>,
+    /// Listings link to entries with relative URLs
+    pub relative_links: bool,
+    /// Apply the `_headers` and `_redirects` files of the source directory
+    pub rule_files: bool,
+    pub trusted_proxies: Vec<Cidr>,
+    /// Allow and deny rules, in command line order
+    pub access_rules: Vec<AccessRule>,
+    pub basic_auth: Vec<BasicAuth>,
+    pub token_auth: Vec<TokenAuth>,
+    pub signed_urls: Vec<SignedUrls>,
+    /// Requests per client address
+    pub request_rate: Option<RequestRate>,
+    pub max_connections: Option<usize>,
+    pub max_connections_per_ip: Option<usize>,
+    pub timeouts: Timeouts,
+    pub request_limits: RequestLimits,
+    pub bandwidth: Bandwidth,
+    pub chaos: Chaos,
+    pub cors: Vec<CorsPolicy>,
+    pub security_headers: Vec<SecurityHeaders>,
+    pub cache: CachePolicy,
+}
+
+/// # Parameters of the `sign` command
+pub struct SignParams {
+    pub secret: Vec<u8>,
+    /// URL path on the site, below the base URL
+    pub path: String,
+    /// Unix time
+    pub expires: i64,
+    /// Only this client may use the link
+    pub ip: Option<IpAddr>,
+    pub base_url: Option<BaseUrl>,
+}
+
+/// # Listening sockets passed by systemd
+///
+/// Implements the `LISTEN_PID`/`LISTEN_FDS` protocol of systemd socket activation. The sockets
+/// start at file descriptor 3. The variables are removed so that child processes do not
+/// inherit them.
+pub fn get_systemd_listeners() -> Vec<ListenerParams> {
+    const SD_LISTEN_FDS_START: i32 = 3;
+
+    let listen_pid = env::var("LISTEN_PID")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok());
+    if listen_pid != Some(std::process::id()) {
+        return vec![];
+    }
+    let listen_fds = env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|v| v.parse::<i32>().ok())
+        .unwrap_or(0);
+
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+
+    (SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + listen_fds.max(0))
+        .map(|fd| ListenerParams::new(BindTarget::Inherited(fd)))
+        .collect()
+}
+
+/// # Split a command line option
+///
+/// `--name=value` results in `("name", "value")` and `--name` results in `("name", "")`.
+pub fn split_option(argument: &str) -> Option<(&str, &str)> {
+    let option = argument.strip_prefix("--")?;
+    match option.split_once('=') {
+        Some((name, value)) => Some((name, value)),
+        None => Some((option, "")),
+    }
+}
+
+/// # Get source directory, listeners and options from command line arguments
 ///
 /// Retrieve command line arguments which can be used as source directory,
-/// startup port and IP address.
+/// startup port and IP addresses.
 ///
 /// Source directory is mandatory.
 ///
+/// Every IP address argument is bound on the port argument. Socket address arguments
+/// (`127.0.0.1:8090`, `[::1]:8091`), Unix socket arguments (`unix:/run/http-mini.sock`)
+/// and `--listen=TARGET[,option...]` options add more listeners. Sockets passed by systemd
+/// socket activation are used as well.
+///
+/// `--inetd` serves a single connection on stdin/stdout instead of listening.
+///
+/// `--vhost=HOST=DIRECTORY[,option...]` serves another directory for requests to `HOST`.
+///
+/// `--mount=/PREFIX=DIRECTORY[,option...]` serves a directory under a URL prefix, for all hosts.
+///
+/// `--base-url=URL` is the public URL behind a reverse proxy, its path is stripped from requests
+/// and prepended to links. `--relative-links` makes listings link with relative URLs.
+/// `--rule-files` applies the Netlify-style `_headers` and `_redirects` files of the source
+/// directory.
+///
+/// `--trusted-proxy=CIDR[,CIDR...]` trusts the forwarding headers of peers in these networks.
+///
+/// `--allow=CIDR[,CIDR...][,subject=PATTERN][,san=PATTERN][,path=/PREFIX]` and `--deny=...`
+/// accept or refuse clients by address and TLS client certificate, the first matching rule of
+/// the longest prefix decides, then the rules of shorter prefixes.
+///
+/// `--basic-auth=HTPASSWD[,realm=NAME][,path=/PREFIX]` requires a user of the htpasswd file.
+///
+/// `--bearer-tokens=SOURCE[,path=/PREFIX]` requires a token of a file or an `env:VARIABLE`.
+///
+/// `--signed-urls=SECRET[,path=/PREFIX]` requires a link signed with the secret of a file or an
+/// `env:VARIABLE`, see [`get_sign_params`].
+///
+/// `--rate-limit=RATE[,burst=N]` limits the requests per second of every client address,
+/// `--max-connections=N` and `--max-connections-per-ip=N` the concurrent connections.
+///
+/// `--idle-timeout=SECONDS` waits for a request, `--read-timeout=SECONDS` for the whole request
+/// head and `--write-timeout=SECONDS` for every write, `0` waits forever.
+/// `--max-request-line=BYTES`, `--max-headers=N`, `--max-header-size=BYTES` and
+/// `--max-body-size=BYTES` limit the size of requests.
+///
+/// `--throttle=RATE[,path=/PREFIX]` limits the bytes per second of every response, for a URL
+/// prefix when given. `--throttle-per-ip=RATE` and `--throttle-total=RATE` limit the responses
+/// of every client address and of the server.
+///
+/// `--chaos` lets requests select faults with the `X-Chaos` header or the `chaos` query
+/// parameter, `--chaos=FAULTS[,path=GLOB][,probability=P]` also injects them into the matching
+/// responses, see [`ChaosRule::parse`].
+///
+/// `--cors=ORIGIN[,ORIGIN...][,OPTION...][,path=/PREFIX]` lets scripts of other origins read
+/// responses, see [`CorsPolicy::parse`].
+///
+/// `--security-headers=PRESET[,OPTION...][,path=/PREFIX]` adds security headers to responses,
+/// see [`SecurityHeaders::parse`].
+///
+/// `--cache=POLICY[,path=GLOB][,type=MIME][,fingerprinted]` sets the `Cache-Control` header of
+/// the matching responses, see [`CacheRule::parse`]. `--cache-mode=dev|production` sets the
+/// policy of the other responses, see [`CacheMode`].
+///
 /// Defaults:
 /// * IP address: "::"
 /// * port      : 8080
-pub fn get_params() -> Result<(String, i32, PathBuf), MissingSourceDirectoryError> {
-    let mut address: Option<String> = None;
+pub fn get_params() -> Result<Params, ParamsError> {
+    let mut addresses: Vec<String> = vec![];
     let mut port: Option<i32> = None;
     let mut source_dir: Option<PathBuf> = None;
+    let mut listeners: Vec<ListenerParams> = get_systemd_listeners();
+    let mut inetd = false;
+    let mut tls_defaults = TlsParams::default();
+    let mut virtual_hosts: Vec<VirtualHost> = vec![];
+    let mut mounts: Vec<Mount> = vec![];
+    let mut base_url: Option<BaseUrl> = None;
+    let mut relative_links = false;
+    let mut rule_files = false;
+    let mut trusted_proxies: Vec<Cidr> = vec![];
+    let mut access_rules: Vec<AccessRule> = vec![];
+    let mut basic_auth: Vec<BasicAuth> = vec![];
+    let mut token_auth: Vec<TokenAuth> = vec![];
+    let mut signed_urls: Vec<SignedUrls> = vec![];
+    let mut request_rate: Option<RequestRate> = None;
+    let mut max_connections: Option<usize> = None;
+    let mut max_connections_per_ip: Option<usize> = None;
+    let mut timeouts = Timeouts::default();
+    let mut request_limits = RequestLimits::default();
+    let mut bandwidth = Bandwidth::default();
+    let mut chaos = Chaos::default();
+    let mut cors: Vec<CorsPolicy> = vec![];
+    let mut security_headers: Vec<SecurityHeaders> = vec![];
+    let mut cache = CachePolicy::default();
 
     let mut first_argument = true;
 
@@ -28,34 +553,180 @@ pub fn get_params() -> Result<(String, i32, PathBuf), MissingSourceDirectoryErro
             first_argument = false;
             continue;
         }
+        if let Some((name, value)) = split_option(argument.as_str()) {
+            match name {
+                "listen" => listeners.push(ListenerParams::parse(value)?),
+                "inetd" => inetd = true,
+                "vhost" => {
+                    let virtual_host = VirtualHost::parse(value)?;
+                    if virtual_host.default && virtual_hosts.iter().any(|vhost| vhost.default) {
+                        return Err(InvalidArgumentError::new(
+                            argument.as_str(),
+                            "only one virtual host can be the default",
+                        )
+                        .into());
+                    }
+                    virtual_hosts.push(virtual_host);
+                }
+                "mount" => {
+                    let mount = Mount::parse(value)?;
+                    if mounts.iter().any(|other| other.prefix == mount.prefix) {
+                        return Err(InvalidArgumentError::new(
+                            argument.as_str(),
+                            "prefix is already mounted",
+                        )
+                        .into());
+                    }
+                    mounts.push(mount);
+                }
+                "base-url" => base_url = Option::from(BaseUrl::parse(value)?),
+                "relative-links" => relative_links = true,
+                "rule-files" => rule_files = true,
+                "allow" | "deny" => access_rules.push(AccessRule::parse(value, name == "allow")?),
+                "basic-auth" => {
+                    let auth = BasicAuth::parse(value)?;
+                    if basic_auth.iter().any(|other| other.prefix == auth.prefix) {
+                        return Err(InvalidArgumentError::new(
+                            argument.as_str(),
+                            "path is already protected",
+                        )
+                        .into());
+                    }
+                    basic_auth.push(auth);
+                }
+                "bearer-tokens" => {
+                    let auth = TokenAuth::parse(value)?;
+                    if token_auth.iter().any(|other| other.prefix == auth.prefix) {
+                        return Err(InvalidArgumentError::new(
+                            argument.as_str(),
+                            "path is already protected",
+                        )
+                        .into());
+                    }
+                    token_auth.push(auth);
+                }
+                "signed-urls" => {
+                    let signed = SignedUrls::parse(value)?;
+                    if signed_urls
+                        .iter()
+                        .any(|other| other.prefix == signed.prefix)
+                    {
+                        return Err(InvalidArgumentError::new(
+                            argument.as_str(),
+                            "path is already protected",
+                        )
+                        .into());
+                    }
+                    signed_urls.push(signed);
+                }
+                "rate-limit" => request_rate = Option::from(RequestRate::parse(value)?),
+                "max-connections" => max_connections = Option::from(parse_count(&argument, value)?),
+                "max-connections-per-ip" => {
+                    max_connections_per_ip = Option::from(parse_count(&argument, value)?)
+                }
+                "idle-timeout" => timeouts.idle = timeouts::parse_seconds(value)?,
+                "read-timeout" => timeouts.read = timeouts::parse_seconds(value)?,
+                "write-timeout" => timeouts.write = timeouts::parse_seconds(value)?,
+                "max-request-line" => {
+                    request_limits.max_request_line = parse_count(&argument, value)?
+                }
+                "max-headers" => request_limits.max_headers = parse_count(&argument, value)?,
+                "max-header-size" => {
+                    request_limits.max_header_size = parse_count(&argument, value)?
+                }
+                "max-body-size" => {
+                    request_limits.max_body_size =
+                        Option::from(value.parse::<u64>().map_err(|_| {
+                            InvalidArgumentError::new(argument.as_str(), "invalid number of bytes")
+                        })?)
+                }
+                "throttle" => match throttle::parse_throttle(value)? {
+                    (rate, Some(prefix)) => bandwidth.paths.push(PathRate { prefix, rate }),
+                    (rate, None) => bandwidth.response_rate = rate,
+                },
+                "throttle-per-ip" => bandwidth.client_rate = throttle::parse_rate(value)?,
+                "throttle-total" => bandwidth.total_rate = throttle::parse_rate(value)?,
+                "chaos" => {
+                    chaos.enabled = true;
+                    if !value.is_empty() {
+                        chaos.rules.push(ChaosRule::parse(value)?);
+                    }
+                }
+                "cors" => {
+                    let policy = CorsPolicy::parse(value)?;
+                    if cors.iter().any(|other| other.prefix == policy.prefix) {
+                        return Err(InvalidArgumentError::new(
+                            argument.as_str(),
+                            "path already has a CORS policy",
+                        )
+                        .into());
+                    }
+                    cors.push(policy);
+                }
+                "security-headers" => {
+                    let headers = SecurityHeaders::parse(value)?;
+                    if security_headers
+                        .iter()
+                        .any(|other| other.prefix == headers.prefix)
+                    {
+                        return Err(InvalidArgumentError::new(
+                            argument.as_str(),
+                            "path already has security headers",
+                        )
+                        .into());
+                    }
+                    security_headers.push(headers);
+                }
+                "cache" => cache.rules.push(CacheRule::parse(value)?),
+                "cache-mode" => cache.mode = Option::from(CacheMode::parse(value)?),
+                "trusted-proxy" => {
+                    for network in value.split(',') {
+                        trusted_proxies.push(Cidr::parse(network)?);
+                    }
+                }
+                "tls-cert" | "tls-key" | "tls-min-version" | "tls-self-signed" | "tls-local-ca"
+                | "tls-sni-map" | "tls-client-ca" | "tls-client-auth" | "tls-client-subject"
+                | "tls-client-san" => {
+                    check_tls_support(argument.as_str())?;
+                    parse_tls_option(&mut tls_defaults, &name[4..], value)
+                        .map_err(|reason| InvalidArgumentError::new(argument.as_str(), reason))?;
+                }
+                _ => eprintln!("Ignoring unknown option {}", argument),
+            }
+            continue;
+        }
         if argument.starts_with("-") {
             continue;
         }
+        if argument.starts_with(UNIX_PREFIX) {
+            listeners.push(ListenerParams::parse(argument.as_str())?);
+            continue;
+        }
 
         if source_dir.is_none() {
-            let x_source_dir: Result<PathBuf, Infallible> = argument.parse();
-            if x_source_dir.is_ok() {
-                let x_source_dir_pathbuf: PathBuf = x_source_dir.unwrap();
-                let canonical = fs::canonicalize(x_source_dir_pathbuf);
-                if canonical.is_ok() {
-                    source_dir = Option::from(canonical.unwrap()); // grcov-excl-line
-                    continue; // grcov-excl-line
-                }
+            let Ok(x_source_dir_pathbuf): Result<PathBuf, Infallible> = argument.parse();
+            let canonical = fs::canonicalize(x_source_dir_pathbuf);
+            if let Ok(canonical) = canonical {
+                source_dir = Option::from(canonical); // grcov-excl-line
+                continue; // grcov-excl-line
             }
         }
         if port.is_none() {
             let x_port: Result<i32, ParseIntError> = argument.parse();
-            if x_port.is_ok() {
-                port = Option::from(x_port.unwrap()); // grcov-excl-line
+            if let Ok(x_port) = x_port {
+                port = Option::from(x_port); // grcov-excl-line
                 continue; // grcov-excl-line
             }
         }
-        if address.is_none() {
-            let x_addr: Result<IpAddr, AddrParseError> = argument.parse();
-            if x_addr.is_ok() {
-                address = Option::from(argument); // grcov-excl-line
-                continue; // grcov-excl-line
-            }
+        let x_addr: Result<IpAddr, AddrParseError> = argument.parse();
+        if x_addr.is_ok() {
+            addresses.push(argument); // grcov-excl-line
+            continue; // grcov-excl-line
+        }
+        let x_socket_addr: Result<SocketAddr, AddrParseError> = argument.parse();
+        if let Ok(addr) = x_socket_addr {
+            listeners.push(ListenerParams::new(BindTarget::Tcp(addr))); // grcov-excl-line
+            continue; // grcov-excl-line
         }
     }
 
@@ -64,13 +735,180 @@ pub fn get_params() -> Result<(String, i32, PathBuf), MissingSourceDirectoryErro
         if env::var_os("TEST").is_some() || cfg!(test) {
             source_dir = Option::from(PathBuf::from("./"));
         } else {
-            return Err(MissingSourceDirectoryError); // grcov-excl-line
+            return Err(MissingSourceDirectoryError.into()); // grcov-excl-line
+        }
+    }
+
+    // plain address and port arguments, or no listeners at all, use the defaults
+    if !addresses.is_empty() || port.is_some() || listeners.is_empty() {
+        let mut port = port.unwrap_or(DEFAULT_PORT);
+        if !(1..=65535).contains(&port) {
+            eprintln!("Invalid port number {}. Using port {}", port, DEFAULT_PORT);
+            port = DEFAULT_PORT;
+        }
+        if addresses.is_empty() {
+            addresses.push(DEFAULT_ADDRESS.to_string());
         }
+        for address in addresses {
+            let ip: IpAddr = address.parse().unwrap();
+            listeners.push(ListenerParams::new(BindTarget::Tcp(SocketAddr::new(
+                ip,
+                port as u16,
+            ))));
+        }
+    }
+
+    finalize_tls(&mut listeners, &tls_defaults)?;
+
+    Ok(Params {
+        listeners,
+        source_dir: source_dir.unwrap(),
+        inetd,
+        virtual_hosts,
+        mounts,
+        base_url,
+        relative_links,
+        rule_files,
+        trusted_proxies,
+        access_rules,
+        basic_auth,
+        token_auth,
+        signed_urls,
+        request_rate,
+        max_connections,
+        max_connections_per_ip,
+        timeouts,
+        request_limits,
+        bandwidth,
+        chaos,
+        cors,
+        security_headers,
+        cache,
+    })
+}
+
+/// # Parse a positive count option
+fn parse_count(argument: &str, value: &str) -> Result<usize, InvalidArgumentError> {
+    match value.parse::<usize>() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(InvalidArgumentError::new(
+            argument,
+            "expected a positive number",
+        )),
     }
+}
 
-    Ok((
-        address.unwrap_or("::".to_string()),
-        port.unwrap_or(8080),
-        source_dir.unwrap(),
-    ))
+/// # Get the parameters of the `sign` command
+///
+/// `http-mini sign --secret=SECRET [--ttl=SECONDS] [--ip=IP] [--base-url=URL] /PATH` prints a
+/// link to `/PATH` which expires after `SECONDS` (default one hour). The secret is a file or an
+/// `env:VARIABLE`, as for `--signed-urls`.
+pub fn get_sign_params() -> Result<SignParams, ParamsError> {
+    let mut secret: Option<Vec<u8>> = None;
+    let mut ttl: i64 = DEFAULT_SIGNED_URL_TTL;
+    let mut ip: Option<IpAddr> = None;
+    let mut base_url: Option<BaseUrl> = None;
+    let mut path: Option<String> = None;
+
+    for argument in env::args().skip(2) {
+        match split_option(argument.as_str()) {
+            Some(("secret", value)) => secret = Option::from(read_secret(value)?),
+            Some(("ttl", value)) => {
+                ttl = value
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|ttl| *ttl > 0)
+                    .ok_or_else(|| {
+                        InvalidArgumentError::new(argument.as_str(), "invalid number of seconds")
+                    })?
+            }
+            Some(("ip", value)) => {
+                ip = Option::from(value.parse::<IpAddr>().map_err(|_| {
+                    InvalidArgumentError::new(argument.as_str(), "invalid IP address")
+                })?)
+            }
+            Some(("base-url", value)) => base_url = Option::from(BaseUrl::parse(value)?),
+            Some(_) => eprintln!("Ignoring unknown option {}", argument),
+            None if argument.starts_with('/') && path.is_none() => path = Option::from(argument),
+            None => {
+                return Err(InvalidArgumentError::new(argument.as_str(), "expected a /PATH").into())
+            }
+        }
+    }
+
+    Ok(SignParams {
+        secret: secret.ok_or_else(|| InvalidArgumentError::new("sign", "missing --secret"))?,
+        path: path.ok_or_else(|| InvalidArgumentError::new("sign", "missing /PATH"))?,
+        expires: date::now() + ttl,
+        ip,
+        base_url,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::app::{split_option, BindTarget, ListenerParams};
+    use crate::utils::proxy_protocol::ProxyProtocol;
+    use std::net::SocketAddr;
+    #[cfg(any(unix, feature = "tls"))]
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_listener_params_parse() {
+        let params = ListenerParams::parse("[::1]:8090").unwrap();
+        assert_eq!(
+            params.target,
+            BindTarget::Tcp("[::1]:8090".parse::<SocketAddr>().unwrap())
+        );
+
+        assert!(ListenerParams::parse("127.0.0.1").is_err());
+        assert!(ListenerParams::parse("127.0.0.1:8080,unknown").is_err());
+        assert!(ListenerParams::parse("127.0.0.1:8080,mode=660").is_err());
+
+        let params = ListenerParams::parse("127.0.0.1:8080,proxy-protocol=optional").unwrap();
+        assert_eq!(params.proxy_protocol, Some(ProxyProtocol::Optional));
+        let params = ListenerParams::parse("127.0.0.1:8080,proxy-protocol").unwrap();
+        assert_eq!(params.proxy_protocol, Some(ProxyProtocol::Required));
+        assert!(ListenerParams::parse("127.0.0.1:8080,proxy-protocol=v3").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_listener_params_parse_unix() {
+        let params = ListenerParams::parse("unix:/run/http-mini.sock,mode=660").unwrap();
+        assert_eq!(
+            params.target,
+            BindTarget::Unix(PathBuf::from("/run/http-mini.sock"))
+        );
+        assert_eq!(params.unix_mode, Some(0o660));
+
+        assert!(ListenerParams::parse("unix:").is_err());
+        assert!(ListenerParams::parse("unix:/tmp/a.sock,mode=999").is_err());
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn test_listener_params_parse_client_auth() {
+        let params = ListenerParams::parse(
+            "127.0.0.1:8443,self-signed,client-ca=ca.pem,client-auth=optional,client-san=*.ci,client-san=qa",
+        )
+        .unwrap();
+        let tls = params.tls.unwrap();
+        assert_eq!(tls.client_ca, Some(PathBuf::from("ca.pem")));
+        assert!(tls.client_auth_optional);
+        assert_eq!(tls.client_sans, vec!["*.ci", "qa"]);
+
+        assert!(ListenerParams::parse("127.0.0.1:8443,client-auth=maybe").is_err());
+        assert!(ListenerParams::parse("127.0.0.1:8443,client-ca=").is_err());
+    }
+
+    #[test]
+    fn test_split_option() {
+        assert_eq!(
+            split_option("--listen=[::1]:80"),
+            Some(("listen", "[::1]:80"))
+        );
+        assert_eq!(split_option("--nocapture"), Some(("nocapture", "")));
+        assert_eq!(split_option("./"), None);
+    }
 }
@@ -0,0 +1,92 @@
+use crate::errors::invalid_argument::InvalidArgumentError;
+
+/// # Public URL of the server behind a reverse proxy
+#[derive(Debug, Clone, PartialEq)]
+pub struct BaseUrl {
+    /// Scheme and authority, i.e. `https://tools.internal`, links use the listener address
+    /// when missing
+    pub origin: Option<String>,
+    /// Path prefix without a trailing slash, i.e. `/share`, empty for the root
+    pub path: String,
+}
+
+impl BaseUrl {
+    /// # Parse a base URL
+    ///
+    /// Either a path prefix (`/share`) or an absolute URL (`https://tools.internal/share/`).
+    pub fn parse(value: &str) -> Result<BaseUrl, InvalidArgumentError> {
+        let (origin, path) = match value.split_once("://") {
+            Some((scheme, rest)) => {
+                if scheme != "http" && scheme != "https" {
+                    return Err(InvalidArgumentError::new(value, "expected an http(s) URL"));
+                }
+                let (authority, path) = match rest.find('/') {
+                    Some(index) => rest.split_at(index),
+                    None => (rest, ""),
+                };
+                if authority.is_empty() {
+                    return Err(InvalidArgumentError::new(value, "missing host"));
+                }
+                (Option::from(format!("{}://{}", scheme, authority)), path)
+            }
+            None => (None, value),
+        };
+        if !path.is_empty() && !path.starts_with('/') {
+            return Err(InvalidArgumentError::new(value, "path must start with /"));
+        }
+        if path.contains(['?', '#']) {
+            return Err(InvalidArgumentError::new(
+                value,
+                "query and fragment are not supported",
+            ));
+        }
+
+        Ok(BaseUrl {
+            origin,
+            path: path.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// # Request path without the base path
+    ///
+    /// Returns `None` for paths outside of the base path, the base path itself results in `/`.
+    pub fn strip<'a>(&self, path: &'a str) -> Option<&'a str> {
+        let rest = path.strip_prefix(self.path.as_str())?;
+        match rest {
+            "" => Option::from("/"),
+            _ if rest.starts_with('/') => Option::from(rest),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::base_url::BaseUrl;
+
+    #[test]
+    fn test_parse() {
+        let base_url = BaseUrl::parse("https://tools.internal/share/").unwrap();
+        assert_eq!(base_url.origin.as_deref(), Some("https://tools.internal"));
+        assert_eq!(base_url.path, "/share");
+
+        let base_url = BaseUrl::parse("/share").unwrap();
+        assert_eq!(base_url.origin, None);
+        assert_eq!(base_url.path, "/share");
+
+        assert_eq!(BaseUrl::parse("http://a.test").unwrap().path, "");
+        assert!(BaseUrl::parse("share").is_err());
+        assert!(BaseUrl::parse("ftp://a.test/share").is_err());
+        assert!(BaseUrl::parse("https:///share").is_err());
+    }
+
+    #[test]
+    fn test_strip() {
+        let base_url = BaseUrl::parse("/share").unwrap();
+        assert_eq!(base_url.strip("/share"), Some("/"));
+        assert_eq!(base_url.strip("/share/"), Some("/"));
+        assert_eq!(base_url.strip("/share/a/b.txt"), Some("/a/b.txt"));
+        assert_eq!(base_url.strip("/shared/a"), None);
+        assert_eq!(base_url.strip("/a"), None);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::errors::invalid_argument::InvalidArgumentError;
+use crate::utils::htpasswd::Htpasswd;
+use crate::utils::http_request::HttpRequest;
+use crate::utils::{base64, mount};
+use std::path::Path;
+
+const DEFAULT_REALM: &str = "http-mini";
+
+/// # HTTP Basic authentication of a URL prefix (RFC 7617)
+pub struct BasicAuth {
+    /// URL path prefix without a trailing slash, empty for the whole site
+    pub prefix: String,
+    pub realm: String,
+    pub users: Htpasswd,
+}
+
+impl BasicAuth {
+    /// # Parse a Basic authentication specification
+    ///
+    /// Format: `HTPASSWD[,realm=NAME][,path=/PREFIX]`, i.e.
+    /// `/etc/http-mini/htpasswd,realm=Builds,path=/builds`. Without `path` the whole site is
+    /// protected.
+    pub fn parse(spec: &str) -> Result<BasicAuth, InvalidArgumentError> {
+        let mut parts = spec.split(',');
+        let file = parts.next().unwrap_or_default();
+        if file.is_empty() {
+            return Err(InvalidArgumentError::new(spec, "missing htpasswd file"));
+        }
+
+        let mut prefix = String::new();
+        let mut realm = DEFAULT_REALM.to_string();
+        for option in parts {
+            match option.split_once('=') {
+                Some(("realm", value)) if !value.is_empty() => realm = value.to_string(),
+                Some(("path", value)) if value.starts_with('/') => {
+                    prefix = value.trim_end_matches('/').to_string()
+                }
+                _ => {
+                    return Err(InvalidArgumentError::new(
+                        spec,
+                        format!("invalid basic auth option {}", option).as_str(),
+                    ));
+                }
+            }
+        }
+
+        Ok(BasicAuth {
+            prefix,
+            realm,
+            users: Htpasswd::load(Path::new(file))?,
+        })
+    }
+
+    /// # Authenticated user of a request
+    pub fn authenticate(&self, request: &HttpRequest) -> Option<String> {
+        let (user, password) = credentials(request)?;
+        if self.users.verify(&user, &password) {
+            Some(user)
+        } else {
+            None
+        }
+    }
+
+    /// # `WWW-Authenticate` challenge asking for credentials
+    pub fn challenge(&self) -> String {
+        let realm = self.realm.replace('\\', "\\\\").replace('"', "\\\"");
+        format!("Basic realm=\"{}\", charset=\"UTF-8\"", realm)
+    }
+}
+
+/// # Authentication protecting a URL path
+///
+/// The longest prefix matching whole path segments wins.
+pub fn select<'a>(rules: &'a [BasicAuth], path: &str) -> Option<&'a BasicAuth> {
+    rules
+        .iter()
+        .filter(|rule| mount::strip_path_prefix(path, &rule.prefix).is_some())
+        .max_by_key(|rule| rule.prefix.len())
+}
+
+/// # User and password of the `Authorization` header
+pub fn credentials(request: &HttpRequest) -> Option<(String, String)> {
+    let (scheme, encoded) = request.header("Authorization")?.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("Basic") {
+        return None;
+    }
+    let decoded = String::from_utf8(base64::decode(encoded.trim())?).ok()?;
+    let (user, password) = decoded.split_once(':')?;
+
+    Some((user.to_string(), password.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::traits::stream_trait::StreamTrait;
+    use crate::utils::basic_auth::{credentials, select, BasicAuth};
+    use crate::utils::http_request::HttpRequest;
+    use std::io::Cursor;
+    use std::{env, fs, process};
+
+    fn request(authorization: &str) -> HttpRequest {
+        Cursor::new(format!(
+            "GET / HTTP/1.1\r\nHost: a\r\nAuthorization: {}\r\n\r\n",
+            authorization
+        ))
+        .parse()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_credentials() {
+        assert_eq!(
+            credentials(&request("Basic YWxpY2U6c2VjcmV0OjE=")),
+            Some(("alice".to_string(), "secret:1".to_string()))
+        );
+        assert_eq!(
+            credentials(&request("basic YWxpY2U6c2VjcmV0OjE="))
+                .unwrap()
+                .0,
+            "alice"
+        );
+        assert_eq!(credentials(&request("Bearer YWxpY2U6c2VjcmV0OjE=")), None);
+        assert_eq!(credentials(&request("Basic YWxpY2U")), None);
+    }
+
+    #[test]
+    fn test_parse_and_select() {
+        let path = env::temp_dir().join(format!("http-mini-htpasswd-{}", process::id()));
+        fs::write(&path, "alice:{SHA}5en6G6MezRroT3XKqkdPOmY/BfQ=\n").unwrap();
+        let path = path.to_string_lossy().to_string();
+        let rules = vec![
+            BasicAuth::parse(&path).unwrap(),
+            BasicAuth::parse(format!("{},realm=Builds,path=/builds/", path).as_str()).unwrap(),
+        ];
+        assert_eq!(rules[1].prefix, "/builds");
+        assert_eq!(rules[1].realm, "Builds");
+
+        assert_eq!(select(&rules, "/builds/a.zip").unwrap().realm, "Builds");
+        assert_eq!(select(&rules, "/builds2").unwrap().realm, "http-mini");
+        assert!(select(&rules[1..], "/").is_none());
+
+        assert!(BasicAuth::parse("").is_err());
+        assert!(BasicAuth::parse(format!("{},path=builds", path).as_str()).is_err());
+        assert!(BasicAuth::parse("/nonexistent/htpasswd").is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,405 @@
+use crate::errors::invalid_argument::InvalidArgumentError;
+use crate::utils::http_response::HttpResponse;
+use crate::utils::{date, glob};
+
+const ONE_YEAR: u64 = 31_536_000;
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+/// Shortest fingerprint of a file name, i.e. `app.1a2b3c4d.js`
+const MIN_FINGERPRINT_LENGTH: usize = 8;
+
+/// # Default caching of responses matching no rule
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CacheMode {
+    /// Every response is revalidated
+    Dev,
+    /// Fingerprinted files are cached for a year, HTML is revalidated and other files are
+    /// cached for an hour
+    Production,
+}
+
+impl CacheMode {
+    pub fn parse(value: &str) -> Result<CacheMode, InvalidArgumentError> {
+        match value {
+            "dev" => Ok(CacheMode::Dev),
+            "production" => Ok(CacheMode::Production),
+            _ => Err(InvalidArgumentError::new(value, "unknown cache mode")),
+        }
+    }
+}
+
+/// # `Cache-Control` value of the responses matching all conditions
+#[derive(Debug, PartialEq)]
+pub struct CacheRule {
+    pub cache_control: String,
+    /// Glob matched against the URL path
+    pub pattern: Option<String>,
+    /// Glob matched against the content type, i.e. `image/*`
+    pub mime: Option<String>,
+    /// Only files with a content hash in their name
+    pub fingerprinted: bool,
+}
+
+impl CacheRule {
+    /// # Parse a `--cache` rule
+    ///
+    /// Format: `POLICY[,path=GLOB][,type=MIME][,fingerprinted]`, i.e. `immutable,fingerprinted`
+    /// or `600,type=image/*`. The policy is `no-cache`, `no-store`, `immutable` (a year), a
+    /// number of seconds or `Cache-Control` directives separated by `;`.
+    pub fn parse(spec: &str) -> Result<CacheRule, InvalidArgumentError> {
+        let mut parts = spec.split(',');
+        let cache_control = match parts.next().unwrap_or_default() {
+            "" => return Err(InvalidArgumentError::new(spec, "missing cache policy")),
+            "immutable" => IMMUTABLE.to_string(),
+            policy if policy.bytes().all(|byte| byte.is_ascii_digit()) => {
+                format!("public, max-age={}", policy)
+            }
+            policy => policy
+                .split(';')
+                .map(str::trim)
+                .collect::<Vec<&str>>()
+                .join(", "),
+        };
+        let mut rule = CacheRule {
+            cache_control,
+            pattern: None,
+            mime: None,
+            fingerprinted: false,
+        };
+        for option in parts {
+            match option.split_once('=') {
+                Some(("path", value)) if !value.is_empty() => {
+                    rule.pattern = Option::from(value.to_string())
+                }
+                Some(("type", value)) if !value.is_empty() => {
+                    rule.mime = Option::from(value.to_ascii_lowercase())
+                }
+                None if option == "fingerprinted" => rule.fingerprinted = true,
+                _ => {
+                    return Err(InvalidArgumentError::new(
+                        spec,
+                        format!("invalid cache option {}", option).as_str(),
+                    ));
+                }
+            }
+        }
+
+        Ok(rule)
+    }
+
+    fn matches(&self, path: &str, mime: &str) -> bool {
+        self.pattern
+            .as_deref()
+            .is_none_or(|pattern| glob::matches(pattern, path))
+            && self
+                .mime
+                .as_deref()
+                .is_none_or(|pattern| glob::matches(pattern, mime))
+            && (!self.fingerprinted || is_fingerprinted(path))
+    }
+}
+
+/// # Caching headers of responses
+///
+/// The first matching rule applies, then the mode default. Without a mode, responses matching
+/// no rule get no caching headers.
+#[derive(Debug, Default)]
+pub struct CachePolicy {
+    pub mode: Option<CacheMode>,
+    pub rules: Vec<CacheRule>,
+}
+
+impl CachePolicy {
+    /// # `Cache-Control` value of a URL path with a content type
+    pub fn cache_control(&self, path: &str, mime: &str) -> Option<String> {
+        let mime = mime.split(';').next().unwrap_or_default().trim();
+        let mime = mime.to_ascii_lowercase();
+        if let Some(rule) = self.rules.iter().find(|rule| rule.matches(path, &mime)) {
+            return Option::from(rule.cache_control.clone());
+        }
+
+        match self.mode? {
+            CacheMode::Dev => Option::from("no-cache".to_string()),
+            CacheMode::Production if mime == "text/html" => Option::from("no-cache".to_string()),
+            CacheMode::Production if is_fingerprinted(path) => Option::from(IMMUTABLE.to_string()),
+            CacheMode::Production => Option::from("public, max-age=3600".to_string()),
+        }
+    }
+}
+
+/// # Whether a file name carries a content hash
+///
+/// A part of the name between dots or dashes, before the extension, of at least 8 letters,
+/// digits and underscores including a digit, i.e. `app.1a2b3c4d.js` or `index-B3xk92Qa.css`.
+pub fn is_fingerprinted(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or_default();
+    let Some((stem, _)) = name.rsplit_once('.') else {
+        return false;
+    };
+
+    stem.split(['.', '-']).skip(1).any(|part| {
+        part.len() >= MIN_FINGERPRINT_LENGTH
+            && part
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || byte == b'_')
+            && part.bytes().any(|byte| byte.is_ascii_digit())
+    })
+}
+
+/// # Add `Cache-Control` and `Expires` to a successful or not modified response
+///
+/// The content type is the one of the response, from [`crate::utils::mimes::get_mime_type`].
+/// Headers already set, i.e. by rule files or host options, are kept. `Expires` follows the
+/// `max-age` directive, revalidated responses expire right away. Responses to authenticated
+/// requests are `private`, shared caches must not hand them to other clients, including those
+/// with a `Cache-Control` header already set.
+pub fn add_headers(
+    policy: &CachePolicy,
+    path: &str,
+    authenticated: bool,
+    response: &mut HttpResponse,
+    now: i64,
+) {
+    if !matches!(response.status, 200 | 304) {
+        return;
+    }
+    let headers = response.headers.get_or_insert_with(Vec::new);
+    let has_header = |headers: &[(String, String)], name: &str| {
+        headers
+            .iter()
+            .any(|(key, _)| key.eq_ignore_ascii_case(name))
+    };
+    if let Some((_, value)) = headers
+        .iter_mut()
+        .find(|(key, _)| key.eq_ignore_ascii_case("Cache-Control"))
+    {
+        if authenticated {
+            *value = private(value);
+        }
+        return;
+    }
+    let mime = headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("Content-Type"))
+        .map_or("", |(_, value)| value.as_str());
+    let Some(mut cache_control) = policy.cache_control(path, mime) else {
+        return;
+    };
+    if authenticated {
+        cache_control = private(&cache_control);
+    }
+
+    let directives: Vec<&str> = cache_control.split(',').map(str::trim).collect();
+    let max_age = directives
+        .iter()
+        .find_map(|directive| directive.strip_prefix("max-age="))
+        .and_then(|value| value.parse::<u64>().ok());
+    let expires = match max_age {
+        Some(max_age) => Option::from(now.saturating_add(max_age.min(ONE_YEAR) as i64)),
+        None if directives.contains(&"no-cache") || directives.contains(&"no-store") => {
+            Option::from(0)
+        }
+        None => None,
+    };
+    if let Some(expires) = expires.filter(|_| !has_header(headers, "Expires")) {
+        headers.push(("Expires".to_string(), date::format_http_date(expires)));
+    }
+    headers.push(("Cache-Control".to_string(), cache_control));
+}
+
+/// # `Cache-Control` value restricted to the browser of the client
+///
+/// `public` is replaced with `private`, which is added unless the response is not stored at all.
+fn private(cache_control: &str) -> String {
+    let mut directives: Vec<&str> = cache_control
+        .split(',')
+        .map(str::trim)
+        .filter(|directive| !directive.eq_ignore_ascii_case("public"))
+        .collect();
+    if !directives.iter().any(|directive| {
+        directive.eq_ignore_ascii_case("private") || directive.eq_ignore_ascii_case("no-store")
+    }) {
+        directives.insert(0, "private");
+    }
+    directives.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::cache_control::{
+        add_headers, is_fingerprinted, CacheMode, CachePolicy, CacheRule,
+    };
+    use crate::utils::http_response::HttpResponse;
+
+    fn response(mime: &str) -> HttpResponse {
+        HttpResponse::new(
+            200,
+            Option::from(vec![("Content-Type".to_string(), mime.to_string())]),
+            None,
+        )
+    }
+
+    fn header<'a>(response: &'a HttpResponse, name: &str) -> Option<&'a str> {
+        response
+            .headers
+            .as_ref()?
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn test_parse() {
+        let rule = CacheRule::parse("immutable,path=/assets/*,fingerprinted").unwrap();
+        assert_eq!(rule.cache_control, "public, max-age=31536000, immutable");
+        assert_eq!(rule.pattern.as_deref(), Some("/assets/*"));
+        assert!(rule.fingerprinted);
+        assert_eq!(
+            CacheRule::parse("600,type=Image/*").unwrap(),
+            CacheRule {
+                cache_control: "public, max-age=600".to_string(),
+                pattern: None,
+                mime: Option::from("image/*".to_string()),
+                fingerprinted: false,
+            }
+        );
+        assert_eq!(
+            CacheRule::parse("private;max-age=60")
+                .unwrap()
+                .cache_control,
+            "private, max-age=60"
+        );
+        assert!(CacheRule::parse("").is_err());
+        assert!(CacheRule::parse("no-cache,size=1").is_err());
+        assert!(CacheMode::parse("staging").is_err());
+    }
+
+    #[test]
+    fn test_is_fingerprinted() {
+        assert!(is_fingerprinted("/assets/app.1a2b3c4d.js"));
+        assert!(is_fingerprinted("/assets/index-B3xk92Qa.css"));
+        assert!(!is_fingerprinted("/assets/jquery.min.js"));
+        assert!(!is_fingerprinted("/assets/bootstrap-bundle.js"));
+        assert!(!is_fingerprinted("/1a2b3c4d5e.js"));
+    }
+
+    #[test]
+    fn test_cache_control() {
+        let mut policy = CachePolicy {
+            mode: Option::from(CacheMode::Production),
+            rules: vec![CacheRule::parse("no-store,path=/api/*").unwrap()],
+        };
+        assert_eq!(
+            policy
+                .cache_control("/api/data.json", "application/json")
+                .unwrap(),
+            "no-store"
+        );
+        assert_eq!(
+            policy.cache_control("/index.html", "text/html").unwrap(),
+            "no-cache"
+        );
+        assert_eq!(
+            policy
+                .cache_control("/app.1a2b3c4d.js", "text/javascript")
+                .unwrap(),
+            "public, max-age=31536000, immutable"
+        );
+        assert_eq!(
+            policy.cache_control("/logo.png", "image/png").unwrap(),
+            "public, max-age=3600"
+        );
+        policy.mode = Option::from(CacheMode::Dev);
+        assert_eq!(
+            policy
+                .cache_control("/app.1a2b3c4d.js", "text/javascript")
+                .unwrap(),
+            "no-cache"
+        );
+        policy.mode = None;
+        assert_eq!(policy.cache_control("/logo.png", "image/png"), None);
+    }
+
+    #[test]
+    fn test_add_headers() {
+        let policy = CachePolicy {
+            mode: Option::from(CacheMode::Production),
+            rules: vec![],
+        };
+        let mut html = response("text/html");
+        add_headers(&policy, "/index.html", false, &mut html, 971186136);
+        assert_eq!(header(&html, "Cache-Control"), Some("no-cache"));
+        assert_eq!(
+            header(&html, "Expires"),
+            Some("Thu, 01 Jan 1970 00:00:00 GMT")
+        );
+
+        let mut image = response("image/png");
+        add_headers(&policy, "/logo.png", false, &mut image, 971186136);
+        assert_eq!(
+            header(&image, "Expires"),
+            Some("Tue, 10 Oct 2000 14:55:36 GMT")
+        );
+
+        let mut preset = response("image/png");
+        preset
+            .headers
+            .as_mut()
+            .unwrap()
+            .push(("cache-control".to_string(), "no-store".to_string()));
+        add_headers(&policy, "/logo.png", false, &mut preset, 971186136);
+        assert_eq!(header(&preset, "Expires"), None);
+
+        let mut missing = HttpResponse::new(404, None, None);
+        add_headers(&policy, "/missing.png", false, &mut missing, 971186136);
+        assert_eq!(missing.headers, None);
+
+        let mut not_modified = response("image/png");
+        not_modified.status = 304;
+        add_headers(&policy, "/logo.png", false, &mut not_modified, 971186136);
+        assert_eq!(
+            header(&not_modified, "Cache-Control"),
+            Some("public, max-age=3600")
+        );
+    }
+
+    #[test]
+    fn test_add_headers_authenticated() {
+        let policy = CachePolicy {
+            mode: Option::from(CacheMode::Production),
+            rules: vec![CacheRule::parse("no-store,path=/api/*").unwrap()],
+        };
+        let mut image = response("image/png");
+        add_headers(&policy, "/logo.png", true, &mut image, 971186136);
+        assert_eq!(
+            header(&image, "Cache-Control"),
+            Some("private, max-age=3600")
+        );
+
+        let mut script = response("text/javascript");
+        add_headers(&policy, "/app.1a2b3c4d.js", true, &mut script, 971186136);
+        assert_eq!(
+            header(&script, "Cache-Control"),
+            Some("private, max-age=31536000, immutable")
+        );
+
+        let mut html = response("text/html");
+        add_headers(&policy, "/index.html", true, &mut html, 971186136);
+        assert_eq!(header(&html, "Cache-Control"), Some("private, no-cache"));
+
+        let mut data = response("application/json");
+        add_headers(&policy, "/api/data.json", true, &mut data, 971186136);
+        assert_eq!(header(&data, "Cache-Control"), Some("no-store"));
+
+        let mut preset = response("image/png");
+        preset.headers.as_mut().unwrap().push((
+            "Cache-Control".to_string(),
+            "public, max-age=600".to_string(),
+        ));
+        add_headers(&policy, "/logo.png", true, &mut preset, 971186136);
+        assert_eq!(
+            header(&preset, "Cache-Control"),
+            Some("private, max-age=600")
+        );
+        assert_eq!(header(&preset, "Expires"), None);
+    }
+}
@@ -0,0 +1,307 @@
+use crate::errors::invalid_argument::InvalidArgumentError;
+use crate::utils::glob;
+use crate::utils::http_request::HttpRequest;
+use crate::utils::http_response::{self, HttpResponse};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Write;
+use std::thread;
+use std::time::Duration;
+
+/// Request header selecting faults
+pub const CHAOS_HEADER: &str = "X-Chaos";
+/// Query parameter selecting faults
+pub const CHAOS_PARAMETER: &str = "chaos";
+const DEFAULT_STALL: Duration = Duration::from_secs(60);
+
+/// # Faults injected into a response
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Faults {
+    /// Wait before answering
+    pub delay: Option<Duration>,
+    /// Answer with this status instead
+    pub status: Option<u16>,
+    /// Reset the connection in the middle of the body
+    pub reset: bool,
+    /// Close the connection in the middle of the body
+    pub truncate: bool,
+    /// Announce a `Content-Length` off by this many bytes
+    pub length_offset: Option<i64>,
+    /// Pause in the middle of the body
+    pub stall: Option<Duration>,
+}
+
+impl Faults {
+    /// # Parse a list of faults
+    ///
+    /// Format: `FAULT[;FAULT...]` with `delay=MILLISECONDS`, `status=CODE`, `reset`,
+    /// `truncate`, `length=[+-]BYTES` and `stall[=MILLISECONDS]`, i.e. `delay=500;status=503`.
+    pub fn parse(spec: &str) -> Result<Faults, InvalidArgumentError> {
+        let mut faults = Faults::default();
+        for fault in spec.split(';').map(str::trim) {
+            let invalid =
+                || InvalidArgumentError::new(spec, format!("invalid fault {}", fault).as_str());
+            match fault.split_once('=').unwrap_or((fault, "")) {
+                ("delay", value) => {
+                    faults.delay =
+                        Option::from(Duration::from_millis(value.parse().map_err(|_| invalid())?))
+                }
+                ("status", value) => match value.parse::<u16>() {
+                    Ok(status) if (100..=599).contains(&status) => {
+                        faults.status = Option::from(status)
+                    }
+                    _ => return Err(invalid()),
+                },
+                ("reset", "") => faults.reset = true,
+                ("truncate", "") => faults.truncate = true,
+                ("length", value) => {
+                    faults.length_offset = Option::from(
+                        value
+                            .trim_start_matches('+')
+                            .parse::<i64>()
+                            .map_err(|_| invalid())?,
+                    )
+                }
+                ("stall", "") => faults.stall = Option::from(DEFAULT_STALL),
+                ("stall", value) => {
+                    faults.stall =
+                        Option::from(Duration::from_millis(value.parse().map_err(|_| invalid())?))
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(faults)
+    }
+
+    /// # Whether the body is sent other than as announced
+    pub fn has_body_faults(&self) -> bool {
+        self.reset || self.truncate || self.length_offset.is_some() || self.stall.is_some()
+    }
+
+    /// # Add the faults of `other`, which wins for conflicting values
+    fn merge(&mut self, other: Faults) {
+        self.delay = other.delay.or(self.delay);
+        self.status = other.status.or(self.status);
+        self.reset |= other.reset;
+        self.truncate |= other.truncate;
+        self.length_offset = other.length_offset.or(self.length_offset);
+        self.stall = other.stall.or(self.stall);
+    }
+}
+
+/// # Faults injected into some responses
+pub struct ChaosRule {
+    pub faults: Faults,
+    /// Glob matched against the URL path, all paths when `None`
+    pub pattern: Option<String>,
+    /// Share of the matching requests, from 0 to 1
+    pub probability: f64,
+}
+
+impl ChaosRule {
+    /// # Parse a fault injection rule
+    ///
+    /// Format: `FAULT[;FAULT...][,path=GLOB][,probability=P]`, i.e.
+    /// `status=503,path=/api/*,probability=0.1`.
+    pub fn parse(spec: &str) -> Result<ChaosRule, InvalidArgumentError> {
+        let mut parts = spec.split(',');
+        let faults = Faults::parse(parts.next().unwrap_or_default())?;
+        let mut pattern = None;
+        let mut probability = 1.0;
+        for option in parts {
+            match option.split_once('=') {
+                Some(("path", value)) if !value.is_empty() => {
+                    pattern = Option::from(value.to_string())
+                }
+                Some(("probability", value)) => match value.parse::<f64>() {
+                    Ok(value) if (0.0..=1.0).contains(&value) => probability = value,
+                    _ => return Err(InvalidArgumentError::new(spec, "invalid probability")),
+                },
+                _ => {
+                    return Err(InvalidArgumentError::new(
+                        spec,
+                        format!("invalid chaos option {}", option).as_str(),
+                    ));
+                }
+            }
+        }
+
+        Ok(ChaosRule {
+            faults,
+            pattern,
+            probability,
+        })
+    }
+
+    fn applies(&self, path: &str) -> bool {
+        self.pattern
+            .as_deref()
+            .is_none_or(|pattern| glob::matches(pattern, path))
+            && random() < self.probability
+    }
+}
+
+/// # Fault injection settings
+#[derive(Default)]
+pub struct Chaos {
+    /// Requests may select faults with the `X-Chaos` header or the `chaos` query parameter
+    pub enabled: bool,
+    pub rules: Vec<ChaosRule>,
+}
+
+impl Chaos {
+    /// # Faults of a request to a URL path
+    ///
+    /// Faults selected by the request add to those of the rules.
+    pub fn select(
+        &self,
+        request: &HttpRequest,
+        path: &str,
+    ) -> Result<Faults, InvalidArgumentError> {
+        let mut faults = Faults::default();
+        for rule in self.rules.iter().filter(|rule| rule.applies(path)) {
+            faults.merge(rule.faults.clone());
+        }
+        if !self.enabled {
+            return Ok(faults);
+        }
+
+        if let Some(spec) = request.header(CHAOS_HEADER) {
+            faults.merge(Faults::parse(spec)?);
+        }
+        let query = request
+            .path
+            .as_deref()
+            .and_then(|path| path.split('#').next())
+            .and_then(|path| path.split_once('?'))
+            .map_or("", |(_, query)| query);
+        for pair in query.split('&') {
+            if let Some((CHAOS_PARAMETER, spec)) = pair.split_once('=') {
+                faults.merge(Faults::parse(spec)?);
+            }
+        }
+
+        Ok(faults)
+    }
+}
+
+/// # Random number from 0 to 1
+///
+/// Every `RandomState` has new random keys, which is enough for picking requests.
+fn random() -> f64 {
+    RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64
+}
+
+/// # Apply the delay and status faults to a response
+pub fn apply(response: HttpResponse, faults: &Faults) -> HttpResponse {
+    if let Some(delay) = faults.delay {
+        thread::sleep(delay);
+    }
+    match faults.status {
+        Some(status) => HttpResponse::new(
+            status,
+            None,
+            Option::from(Vec::from(format!("Injected {} fault", status))),
+        ),
+        None => response,
+    }
+}
+
+/// # Send a response with the body faults
+///
+/// The body is cut, or paused, halfway. The connection is reset by the caller, once the
+/// writer is done with it.
+pub fn send<W: Write>(
+    mut writer: W,
+    status_line: &str,
+    headers: Option<Vec<(String, String)>>,
+    contents: Vec<u8>,
+    faults: &Faults,
+) {
+    let content_length =
+        (contents.len() as i64 + faults.length_offset.unwrap_or_default()).max(0) as usize;
+    let head = format!(
+        "{}\r\n{}\r\n",
+        status_line,
+        http_response::build_headers(headers.unwrap_or_default(), content_length)
+    );
+    let (first, second) = contents.split_at(contents.len() / 2);
+
+    // the client may be gone already, as with regular responses
+    let _ = writer
+        .write_all(head.as_bytes())
+        .and_then(|_| writer.write_all(first))
+        .and_then(|_| writer.flush());
+    if faults.reset || faults.truncate {
+        return;
+    }
+    if let Some(stall) = faults.stall {
+        thread::sleep(stall);
+    }
+    let _ = writer.write_all(second).and_then(|_| writer.flush());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::traits::stream_trait::StreamTrait;
+    use crate::utils::chaos::{Chaos, ChaosRule, Faults};
+    use crate::utils::http_request::HttpRequest;
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    fn request(input: &str) -> HttpRequest {
+        Cursor::new(input).parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Faults::parse("delay=250;status=503;length=+10;stall").unwrap(),
+            Faults {
+                delay: Option::from(Duration::from_millis(250)),
+                status: Option::from(503),
+                length_offset: Option::from(10),
+                stall: Option::from(Duration::from_secs(60)),
+                ..Faults::default()
+            }
+        );
+        assert!(Faults::parse("reset;truncate").unwrap().truncate);
+        assert!(Faults::parse("status=700").is_err());
+        assert!(Faults::parse("explode").is_err());
+
+        let rule = ChaosRule::parse("status=500,path=/api/*,probability=0.25").unwrap();
+        assert_eq!(rule.pattern.as_deref(), Some("/api/*"));
+        assert_eq!(rule.probability, 0.25);
+        assert!(ChaosRule::parse("status=500,probability=2").is_err());
+    }
+
+    #[test]
+    fn test_select() {
+        let mut chaos = Chaos {
+            enabled: false,
+            rules: vec![
+                ChaosRule::parse("delay=100,path=/api/*").unwrap(),
+                ChaosRule::parse("status=500,probability=0").unwrap(),
+            ],
+        };
+        let plain = request("GET /api/a HTTP/1.1\r\nX-Chaos: reset\r\n\r\n");
+        let faults = chaos.select(&plain, "/api/a").unwrap();
+        assert_eq!(faults.delay, Some(Duration::from_millis(100)));
+        assert_eq!(faults.status, None);
+        assert!(!faults.reset);
+        assert_eq!(
+            chaos.select(&plain, "/index.html").unwrap(),
+            Faults::default()
+        );
+
+        chaos.enabled = true;
+        assert!(chaos.select(&plain, "/api/a").unwrap().reset);
+        let query = request("GET /a?x=1&chaos=status=418;delay=5 HTTP/1.1\r\n\r\n");
+        let faults = chaos.select(&query, "/a").unwrap();
+        assert_eq!(faults.status, Some(418));
+        assert_eq!(faults.delay, Some(Duration::from_millis(5)));
+        let invalid = request("GET /a HTTP/1.1\r\nX-Chaos: explode\r\n\r\n");
+        assert!(chaos.select(&invalid, "/a").is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use crate::errors::invalid_argument::InvalidArgumentError;
+use std::fmt;
+use std::net::IpAddr;
+
+/// # IP network in CIDR notation
+///
+/// IPv4-mapped IPv6 addresses (`::ffff:10.0.0.1`) match IPv4 networks, as dual-stack sockets
+/// report IPv4 peers that way.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    /// # Parse a network
+    ///
+    /// Format: `ADDRESS/PREFIX_LENGTH` or `ADDRESS` for a single host, i.e. `10.0.0.0/8`,
+    /// `fd00::/8` or `192.168.1.10`.
+    pub fn parse(value: &str) -> Result<Cidr, InvalidArgumentError> {
+        let (address, prefix_len) = value.split_once('/').unwrap_or((value, ""));
+        let network = match address.parse::<IpAddr>() {
+            Ok(network) => network.to_canonical(),
+            Err(_) => return Err(InvalidArgumentError::new(value, "invalid IP address")),
+        };
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            "" => max_len,
+            _ => match prefix_len.parse::<u8>() {
+                Ok(prefix_len) if prefix_len <= max_len => prefix_len,
+                _ => return Err(InvalidArgumentError::new(value, "invalid prefix length")),
+            },
+        };
+
+        Ok(Cidr {
+            network,
+            prefix_len,
+        })
+    }
+
+    /// # Whether the network contains every address of its IP version
+    pub fn contains_all(&self) -> bool {
+        self.prefix_len == 0
+    }
+
+    /// # Whether the network contains an address
+    pub fn contains(&self, address: IpAddr) -> bool {
+        match (self.network, address.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                masked(network.to_bits().into(), self.prefix_len, 32)
+                    == masked(address.to_bits().into(), self.prefix_len, 32)
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                masked(network.to_bits(), self.prefix_len, 128)
+                    == masked(address.to_bits(), self.prefix_len, 128)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix_len)
+    }
+}
+
+/// # Leading `prefix_len` bits of a `bits` wide address
+fn masked(address: u128, prefix_len: u8, bits: u32) -> u128 {
+    match u32::from(prefix_len) {
+        0 => 0,
+        prefix_len => address >> (bits - prefix_len),
+    }
+}
+
+/// # Whether any network contains an address
+pub fn any_contains(networks: &[Cidr], address: IpAddr) -> bool {
+    networks.iter().any(|network| network.contains(address))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::cidr::{any_contains, Cidr};
+    use std::net::IpAddr;
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(Cidr::parse("10.0.0.0/8").is_ok());
+        assert!(Cidr::parse("fd00::/8").is_ok());
+        assert!(Cidr::parse("::/0").is_ok());
+        assert!(Cidr::parse("10.0.0.0/33").is_err());
+        assert!(Cidr::parse("10.0.0/8").is_err());
+        assert!(Cidr::parse("proxy.internal").is_err());
+    }
+
+    #[test]
+    fn test_contains() {
+        let network = Cidr::parse("10.1.0.0/16").unwrap();
+        assert!(network.contains(ip("10.1.200.3")));
+        assert!(network.contains(ip("::ffff:10.1.0.1")));
+        assert!(!network.contains(ip("10.2.0.1")));
+        assert!(!network.contains(ip("fd00::1")));
+
+        let host = Cidr::parse("::ffff:192.168.1.10").unwrap();
+        assert!(host.contains(ip("192.168.1.10")));
+        assert!(!host.contains(ip("192.168.1.11")));
+
+        let networks = [
+            Cidr::parse("fd00::/8").unwrap(),
+            Cidr::parse("0.0.0.0/0").unwrap(),
+        ];
+        assert!(any_contains(&networks, ip("fdab::1")));
+        assert!(any_contains(&networks, ip("203.0.113.7")));
+        assert!(!any_contains(&networks, ip("2001:db8::1")));
+    }
+}
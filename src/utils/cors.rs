@@ -0,0 +1,325 @@
+use crate::errors::invalid_argument::InvalidArgumentError;
+use crate::utils::glob;
+use crate::utils::http_request::HttpRequest;
+use crate::utils::http_response::HttpResponse;
+use crate::utils::mount;
+
+const DEFAULT_METHODS: [&str; 3] = ["GET", "HEAD", "OPTIONS"];
+
+/// # Origins allowed to read responses
+#[derive(Debug, PartialEq)]
+pub enum AllowedOrigins {
+    /// `*`, any origin
+    Any,
+    /// Any origin, sent back as the allowed one
+    Reflect,
+    /// Origins or origin patterns, i.e. `https://*.example.com`
+    List(Vec<String>),
+}
+
+/// # Cross-origin resource sharing policy of a URL prefix
+#[derive(Debug)]
+pub struct CorsPolicy {
+    /// URL path prefix without a trailing slash, empty for the whole site
+    pub prefix: String,
+    pub origins: AllowedOrigins,
+    /// Methods allowed by preflight requests
+    pub methods: Vec<String>,
+    /// Request headers allowed by preflight requests, those asked for when `None`
+    pub headers: Option<Vec<String>>,
+    /// Response headers readable by scripts
+    pub expose: Vec<String>,
+    /// Requests may carry cookies and credentials
+    pub credentials: bool,
+    /// Seconds a preflight response may be cached
+    pub max_age: Option<u64>,
+}
+
+impl CorsPolicy {
+    /// # Parse a `--cors` policy
+    ///
+    /// Format: `ORIGIN[,ORIGIN...][,methods=M;M][,headers=H;H][,expose=H;H][,credentials]
+    /// [,max-age=SECONDS][,path=/PREFIX]`, i.e. `http://localhost:3000,credentials`. `*` allows
+    /// any origin and `reflect` any origin by name, origins may contain `*` wildcards. `*` is
+    /// refused with `credentials`, sharing credentials with every origin takes `reflect`.
+    pub fn parse(spec: &str) -> Result<CorsPolicy, InvalidArgumentError> {
+        let list = |value: &str| -> Vec<String> {
+            value
+                .split(';')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        let mut policy = CorsPolicy {
+            prefix: String::new(),
+            origins: AllowedOrigins::List(vec![]),
+            methods: DEFAULT_METHODS.map(str::to_string).to_vec(),
+            headers: None,
+            expose: vec![],
+            credentials: false,
+            max_age: None,
+        };
+        let mut origins = vec![];
+        for part in spec.split(',') {
+            match part.split_once('=') {
+                Some(("path", value)) if value.starts_with('/') => {
+                    policy.prefix = value.trim_end_matches('/').to_string()
+                }
+                Some(("methods", value)) => policy.methods = list(value),
+                Some(("headers", value)) => policy.headers = Option::from(list(value)),
+                Some(("expose", value)) => policy.expose = list(value),
+                Some(("max-age", value)) => {
+                    policy.max_age = Option::from(
+                        value
+                            .parse::<u64>()
+                            .map_err(|_| InvalidArgumentError::new(spec, "invalid max-age"))?,
+                    )
+                }
+                Some(_) => {
+                    return Err(InvalidArgumentError::new(
+                        spec,
+                        format!("invalid CORS option {}", part).as_str(),
+                    ));
+                }
+                None if part == "credentials" => policy.credentials = true,
+                None if !part.is_empty() => origins.push(part.trim_end_matches('/').to_string()),
+                None => return Err(InvalidArgumentError::new(spec, "empty origin")),
+            }
+        }
+        if policy.credentials && origins.iter().any(|origin| origin == "*") {
+            return Err(InvalidArgumentError::new(
+                spec,
+                "credentials are not shared with *, use reflect",
+            ));
+        }
+        policy.origins = match origins.as_slice() {
+            [] => return Err(InvalidArgumentError::new(spec, "missing origin")),
+            [origin] if origin == "*" => AllowedOrigins::Any,
+            [origin] if origin == "reflect" => AllowedOrigins::Reflect,
+            _ => AllowedOrigins::List(origins),
+        };
+
+        Ok(policy)
+    }
+
+    /// # `Access-Control-Allow-Origin` value for a request origin
+    fn allow_origin(&self, origin: &str) -> Option<String> {
+        match &self.origins {
+            AllowedOrigins::Any => Option::from("*".to_string()),
+            AllowedOrigins::Reflect => Option::from(origin.to_string()),
+            AllowedOrigins::List(origins) => origins
+                .iter()
+                .any(|pattern| {
+                    glob::matches(&pattern.to_ascii_lowercase(), &origin.to_ascii_lowercase())
+                })
+                .then(|| origin.to_string()),
+        }
+    }
+}
+
+/// # Policy of a URL path
+///
+/// The longest prefix matching whole path segments wins.
+pub fn select<'a>(policies: &'a [CorsPolicy], path: &str) -> Option<&'a CorsPolicy> {
+    policies
+        .iter()
+        .filter(|policy| mount::strip_path_prefix(path, &policy.prefix).is_some())
+        .max_by_key(|policy| policy.prefix.len())
+}
+
+/// # Whether a request is a CORS preflight request
+pub fn is_preflight(request: &HttpRequest) -> bool {
+    request.method.as_deref() == Some("OPTIONS")
+        && request.header("Origin").is_some()
+        && request.header("Access-Control-Request-Method").is_some()
+}
+
+/// # Answer a preflight request to a URL path covered by a policy
+///
+/// The origin is added with the other responses, see [`add_headers`].
+pub fn preflight(
+    policies: &[CorsPolicy],
+    request: &HttpRequest,
+    path: &str,
+) -> Option<HttpResponse> {
+    if !is_preflight(request) {
+        return None;
+    }
+    let policy = select(policies, path)?;
+
+    let mut headers = vec![(
+        "Access-Control-Allow-Methods".to_string(),
+        policy.methods.join(", "),
+    )];
+    let allowed_headers = match &policy.headers {
+        Some(allowed_headers) => Option::from(allowed_headers.join(", ")),
+        None => request
+            .header("Access-Control-Request-Headers")
+            .map(str::to_string),
+    };
+    if let Some(allowed_headers) = allowed_headers.filter(|value| !value.is_empty()) {
+        headers.push(("Access-Control-Allow-Headers".to_string(), allowed_headers));
+    }
+    if let Some(max_age) = policy.max_age {
+        headers.push(("Access-Control-Max-Age".to_string(), max_age.to_string()));
+    }
+    if policy.headers.is_none() {
+        headers.push((
+            "Vary".to_string(),
+            "Access-Control-Request-Headers".to_string(),
+        ));
+    }
+
+    Option::from(HttpResponse::new(200, Option::from(headers), None))
+}
+
+/// # Add the CORS headers of a URL path to a response
+///
+/// Requests from origins the policy does not allow get no CORS headers, which browsers
+/// refuse to share.
+pub fn add_headers(
+    policies: &[CorsPolicy],
+    request: &HttpRequest,
+    path: &str,
+    response: &mut HttpResponse,
+) {
+    let Some(policy) = select(policies, path) else {
+        return;
+    };
+    let headers = response.headers.get_or_insert_with(Vec::new);
+    if policy.origins != AllowedOrigins::Any || policy.credentials {
+        headers.push(("Vary".to_string(), "Origin".to_string()));
+    }
+    let Some(allow_origin) = request
+        .header("Origin")
+        .and_then(|origin| policy.allow_origin(origin))
+    else {
+        return;
+    };
+
+    headers.push(("Access-Control-Allow-Origin".to_string(), allow_origin));
+    if policy.credentials {
+        headers.push((
+            "Access-Control-Allow-Credentials".to_string(),
+            "true".to_string(),
+        ));
+    }
+    if !policy.expose.is_empty() && !is_preflight(request) {
+        headers.push((
+            "Access-Control-Expose-Headers".to_string(),
+            policy.expose.join(", "),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::traits::stream_trait::StreamTrait;
+    use crate::utils::cors::{add_headers, preflight, AllowedOrigins, CorsPolicy};
+    use crate::utils::http_request::HttpRequest;
+    use crate::utils::http_response::HttpResponse;
+    use std::io::Cursor;
+
+    fn request(input: &str) -> HttpRequest {
+        Cursor::new(input).parse().unwrap()
+    }
+
+    fn header<'a>(response: &'a HttpResponse, name: &str) -> Option<&'a str> {
+        response
+            .headers
+            .as_ref()?
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn test_parse() {
+        let policy = CorsPolicy::parse(
+            "http://localhost:3000/,https://*.example.com,methods=GET;PUT,expose=ETag,credentials,max-age=600,path=/api/",
+        )
+        .unwrap();
+        assert_eq!(policy.prefix, "/api");
+        assert_eq!(
+            policy.origins,
+            AllowedOrigins::List(vec![
+                "http://localhost:3000".to_string(),
+                "https://*.example.com".to_string()
+            ])
+        );
+        assert_eq!(policy.methods, vec!["GET", "PUT"]);
+        assert_eq!(policy.headers, None);
+        assert!(policy.credentials);
+        assert_eq!(policy.max_age, Some(600));
+        assert_eq!(CorsPolicy::parse("*").unwrap().methods.len(), 3);
+        assert_eq!(
+            CorsPolicy::parse("reflect").unwrap().origins,
+            AllowedOrigins::Reflect
+        );
+        assert!(CorsPolicy::parse("credentials").is_err());
+        assert!(CorsPolicy::parse("*,credentials").is_err());
+        assert!(CorsPolicy::parse("https://a.example.com,*,credentials").is_err());
+        assert!(
+            CorsPolicy::parse("reflect,credentials")
+                .unwrap()
+                .credentials
+        );
+        assert!(CorsPolicy::parse("*,max-age=soon").is_err());
+    }
+
+    #[test]
+    fn test_add_headers() {
+        let policies = vec![
+            CorsPolicy::parse("*,expose=ETag").unwrap(),
+            CorsPolicy::parse("https://*.example.com,credentials,path=/api").unwrap(),
+        ];
+        let from_app = request("GET /api/a HTTP/1.1\r\nOrigin: https://app.example.com\r\n\r\n");
+        let mut response = HttpResponse::new(200, None, None);
+        add_headers(&policies, &from_app, "/api/a", &mut response);
+        assert_eq!(
+            header(&response, "Access-Control-Allow-Origin"),
+            Some("https://app.example.com")
+        );
+        assert_eq!(
+            header(&response, "Access-Control-Allow-Credentials"),
+            Some("true")
+        );
+        assert_eq!(header(&response, "Vary"), Some("Origin"));
+
+        let mut response = HttpResponse::new(200, None, None);
+        add_headers(&policies, &from_app, "/data.json", &mut response);
+        assert_eq!(header(&response, "Access-Control-Allow-Origin"), Some("*"));
+        assert_eq!(
+            header(&response, "Access-Control-Expose-Headers"),
+            Some("ETag")
+        );
+        assert_eq!(header(&response, "Vary"), None);
+
+        let other = request("GET /api/a HTTP/1.1\r\nOrigin: https://evil.test\r\n\r\n");
+        let mut response = HttpResponse::new(200, None, None);
+        add_headers(&policies, &other, "/api/a", &mut response);
+        assert_eq!(header(&response, "Access-Control-Allow-Origin"), None);
+    }
+
+    #[test]
+    fn test_preflight() {
+        let policies = vec![CorsPolicy::parse("reflect,max-age=60").unwrap()];
+        let input = "OPTIONS /a HTTP/1.1\r\nOrigin: http://localhost:3000\r\n\
+            Access-Control-Request-Method: PUT\r\nAccess-Control-Request-Headers: content-type\r\n\r\n";
+        let response = preflight(&policies, &request(input), "/a").unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(
+            header(&response, "Access-Control-Allow-Methods"),
+            Some("GET, HEAD, OPTIONS")
+        );
+        assert_eq!(
+            header(&response, "Access-Control-Allow-Headers"),
+            Some("content-type")
+        );
+        assert_eq!(header(&response, "Access-Control-Max-Age"), Some("60"));
+
+        assert!(preflight(&policies, &request("OPTIONS /a HTTP/1.1\r\n\r\n"), "/a").is_none());
+        assert!(preflight(&[], &request(input), "/a").is_none());
+    }
+}
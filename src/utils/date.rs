@@ -0,0 +1,98 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+pub const SECONDS_PER_DAY: i64 = 86400;
+
+/// # Seconds since the Unix epoch
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// # Year, month and day of a Unix timestamp
+pub fn get_ymd(timestamp: i64) -> (i32, u8, u8) {
+    // civil from days, see <https://howardhinnant.github.io/date_algorithms.html>
+    let z = timestamp.div_euclid(SECONDS_PER_DAY) + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year as i32, month as u8, day as u8)
+}
+
+/// # Hours, minutes and seconds of a Unix timestamp
+fn get_hms(timestamp: i64) -> (i64, i64, i64) {
+    let seconds = timestamp.rem_euclid(SECONDS_PER_DAY);
+    (seconds / 3600, seconds % 3600 / 60, seconds % 60)
+}
+
+/// # Format a Unix timestamp as used by the Common Log Format
+///
+/// i.e. `10/Oct/2000:13:55:36 +0000`
+pub fn format_clf(timestamp: i64) -> String {
+    let (year, month, day) = get_ymd(timestamp);
+    let (hours, minutes, seconds) = get_hms(timestamp);
+    format!(
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        hours,
+        minutes,
+        seconds
+    )
+}
+
+/// # Format a Unix timestamp as an HTTP date (RFC 9110)
+///
+/// i.e. `Tue, 10 Oct 2000 13:55:36 GMT`
+pub fn format_http_date(timestamp: i64) -> String {
+    let (year, month, day) = get_ymd(timestamp);
+    let (hours, minutes, seconds) = get_hms(timestamp);
+    // the epoch was a Thursday
+    let weekday = (timestamp.div_euclid(SECONDS_PER_DAY) + 4).rem_euclid(7);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[weekday as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        hours,
+        minutes,
+        seconds
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::date::{format_clf, format_http_date, get_ymd};
+
+    #[test]
+    fn test_get_ymd() {
+        assert_eq!(get_ymd(0), (1970, 1, 1));
+        assert_eq!(get_ymd(951782400), (2000, 2, 29));
+        assert_eq!(get_ymd(-86400), (1969, 12, 31));
+    }
+
+    #[test]
+    fn test_format_clf() {
+        assert_eq!(format_clf(971186136), "10/Oct/2000:13:55:36 +0000");
+    }
+
+    #[test]
+    fn test_format_http_date() {
+        assert_eq!(format_http_date(971186136), "Tue, 10 Oct 2000 13:55:36 GMT");
+        assert_eq!(format_http_date(0), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(format_http_date(784111777), "Sun, 06 Nov 1994 08:49:37 GMT");
+    }
+}
@@ -0,0 +1,226 @@
+use crate::utils::cidr::{self, Cidr};
+use crate::utils::http_request::HttpRequest;
+use crate::utils::x509::ClientIdentity;
+use std::net::{IpAddr, SocketAddr};
+
+/// # Client of a request, as seen through trusted proxies
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Client {
+    /// Effective client address, `None` when unknown
+    pub addr: Option<IpAddr>,
+    /// Scheme of the request received by the proxy, `http` or `https`, or `https` on a TLS
+    /// connection
+    pub scheme: Option<String>,
+    /// Host of the request received by the proxy
+    pub host: Option<String>,
+    /// User authenticated by the server
+    pub user: Option<String>,
+    /// Identity of the client certificate, when the client connected directly over TLS
+    pub identity: Option<ClientIdentity>,
+}
+
+impl Client {
+    /// # Client connected directly
+    pub fn direct(peer: Option<IpAddr>) -> Client {
+        Client {
+            addr: peer,
+            ..Client::default()
+        }
+    }
+
+    /// # Host the client asked for
+    ///
+    /// The forwarded host, the `Host` header otherwise.
+    pub fn host<'a>(&'a self, request: &'a HttpRequest) -> Option<&'a str> {
+        self.host.as_deref().or(request.header("Host"))
+    }
+}
+
+/// # Proxy hop of a request
+#[derive(Default)]
+struct Hop {
+    addr: Option<IpAddr>,
+    proto: Option<String>,
+    host: Option<String>,
+}
+
+/// # Resolve the client of a request
+///
+/// Requests from peers outside of `trusted_proxies` are taken as they are. Otherwise the
+/// `Forwarded` header, or `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host` when
+/// it is missing, are walked from the closest hop and the first hop which is not a trusted
+/// proxy is the client.
+pub fn resolve(request: &HttpRequest, peer: Option<IpAddr>, trusted_proxies: &[Cidr]) -> Client {
+    let is_trusted =
+        |addr: Option<IpAddr>| addr.is_some_and(|addr| cidr::any_contains(trusted_proxies, addr));
+    if !is_trusted(peer) {
+        return Client::direct(peer);
+    }
+
+    let hops: Vec<Hop> = match header_values(request, "Forwarded") {
+        forwarded if !forwarded.is_empty() => forwarded
+            .iter()
+            .map(|element| parse_element(element))
+            .collect(),
+        _ => {
+            // the scheme and host of the outermost proxy apply to any hop
+            let proto = header_values(request, "X-Forwarded-Proto").first().cloned();
+            let host = header_values(request, "X-Forwarded-Host").first().cloned();
+            let mut hops: Vec<Hop> = header_values(request, "X-Forwarded-For")
+                .iter()
+                .map(|node| Hop {
+                    addr: parse_node(node),
+                    proto: proto.clone(),
+                    host: host.clone(),
+                })
+                .collect();
+            if hops.is_empty() {
+                // the proxy only forwards the scheme or host
+                hops.push(Hop {
+                    addr: peer,
+                    proto,
+                    host,
+                });
+            }
+            hops
+        }
+    };
+
+    let client = hops
+        .iter()
+        .rev()
+        .find(|hop| !is_trusted(hop.addr))
+        .or(hops.first());
+    match client {
+        Some(hop) => Client {
+            user: None,
+            identity: None,
+            addr: hop.addr,
+            scheme: hop
+                .proto
+                .as_deref()
+                .map(str::to_ascii_lowercase)
+                .filter(|proto| proto == "http" || proto == "https"),
+            host: hop
+                .host
+                .clone()
+                .filter(|host| !host.is_empty() && !host.contains(['/', ' ', '\t'])),
+        },
+        None => Client::direct(peer),
+    }
+}
+
+/// # Comma separated values of all headers named `name`, in order
+fn header_values(request: &HttpRequest, name: &str) -> Vec<String> {
+    request
+        .headers
+        .iter()
+        .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+        .flat_map(|(_, value)| value.split(','))
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+/// # Parse a `Forwarded` element, i.e. `for=192.0.2.60;proto=https;host=example.com`
+fn parse_element(element: &str) -> Hop {
+    let mut hop = Hop::default();
+    for pair in element.split(';') {
+        let (name, value) = match pair.split_once('=') {
+            Some((name, value)) => (name.trim(), value.trim().trim_matches('"')),
+            None => continue,
+        };
+        if name.eq_ignore_ascii_case("for") {
+            hop.addr = parse_node(value);
+        } else if name.eq_ignore_ascii_case("proto") {
+            hop.proto = Option::from(value.to_string());
+        } else if name.eq_ignore_ascii_case("host") {
+            hop.host = Option::from(value.to_string());
+        }
+    }
+
+    hop
+}
+
+/// # Address of a node, with or without port
+///
+/// `unknown` and obfuscated identifiers have no address.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim_matches('"');
+    if let Ok(addr) = node.parse::<IpAddr>() {
+        return Option::from(addr);
+    }
+    if let Ok(addr) = node.parse::<SocketAddr>() {
+        return Option::from(addr.ip());
+    }
+
+    // IPv6 without port
+    node.strip_prefix('[')
+        .and_then(|node| node.strip_suffix(']'))
+        .and_then(|node| node.parse::<IpAddr>().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::traits::stream_trait::StreamTrait;
+    use crate::utils::cidr::Cidr;
+    use crate::utils::forwarded::{resolve, Client};
+    use crate::utils::http_request::HttpRequest;
+    use std::io::Cursor;
+    use std::net::IpAddr;
+
+    fn ip(address: &str) -> Option<IpAddr> {
+        Option::from(address.parse::<IpAddr>().unwrap())
+    }
+
+    fn request(headers: &str) -> HttpRequest {
+        Cursor::new(format!(
+            "GET / HTTP/1.1\r\nHost: backend\r\n{}\r\n",
+            headers
+        ))
+        .parse()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_resolve() {
+        let trusted = [Cidr::parse("10.0.0.0/8").unwrap()];
+        let proxy = ip("10.0.0.2");
+        let resolved = |headers, peer| resolve(&request(headers), peer, &trusted);
+
+        let client = resolved(
+            "Forwarded: for=192.0.2.60;proto=HTTPS;host=share.example\r\nForwarded: for=\"10.0.0.1:80\"\r\n",
+            proxy,
+        );
+        assert_eq!(client.addr, ip("192.0.2.60"));
+        assert_eq!(client.scheme.as_deref(), Some("https"));
+        assert_eq!(client.host(&request("")), Some("share.example"));
+
+        let client = resolved(
+            "X-Forwarded-For: 198.51.100.1, [2001:db8::1]:4711, 10.0.0.1\r\nX-Forwarded-Proto: https\r\n",
+            proxy,
+        );
+        assert_eq!(client.addr, ip("2001:db8::1"));
+        assert_eq!(client.scheme.as_deref(), Some("https"));
+
+        let client = resolved(
+            "X-Forwarded-For: 10.0.0.9\r\nX-Forwarded-Host: a.test\r\n",
+            proxy,
+        );
+        assert_eq!(client.addr, ip("10.0.0.9"));
+        assert_eq!(client.host.as_deref(), Some("a.test"));
+
+        let client = resolved("X-Forwarded-Proto: https\r\n", proxy);
+        assert_eq!(client.addr, proxy);
+        assert_eq!(client.scheme.as_deref(), Some("https"));
+
+        assert_eq!(resolved("Forwarded: for=unknown\r\n", proxy).addr, None);
+        assert_eq!(resolved("", proxy), Client::direct(proxy));
+
+        // headers from untrusted peers are ignored
+        let peer = ip("192.0.2.1");
+        let client = resolved("X-Forwarded-For: 198.51.100.1\r\n", peer);
+        assert_eq!(client, Client::direct(peer));
+        assert_eq!(client.host(&request("")), Some("backend"));
+    }
+}
@@ -39,24 +39,31 @@ const LISTING_LINK_PART_2: &str = "\" target=\"_self\">";
 const LISTING_LINK_PART_3: &str = "</a>";
 const LISTING_DIR_SLASH: &str = "/";
 
+/// # Directory listing
+///
+/// `url_path` is the URL path of the directory, i.e. `/assets/img`. Links to entries are
+/// `link_prefix` followed by the URL path of the entry, or relative to the directory when
+/// there is no `link_prefix`.
 pub fn get_dir_contents_as_html(
     path: &Path,
-    source_dir: &Path,
-    address: &str,
+    url_path: &str,
+    link_prefix: Option<&str>,
 ) -> Result<String, Error> {
     if !path.is_dir() {
         return Err(Error::new(ErrorKind::NotFound, "Not a directory"));
     }
 
-    if !path.starts_with(source_dir) {
-        return Err(Error::new(ErrorKind::NotFound, "Access forbidden"));
-    }
-
     let dir_read = fs::read_dir(path);
     if dir_read.is_err() {
         return Err(dir_read.err().unwrap());
     }
 
+    // without the trailing slash, relative links resolve against the parent directory
+    let relative_dir = match url_path.rsplit_once('/') {
+        Some((_, "")) | None => String::new(),
+        Some((_, last_segment)) => format!("{}/", last_segment),
+    };
+    let url_path = url_path.trim_end_matches('/');
     let mut result = format!("{}\n", LISTING_LIST_START);
 
     let dir_paths = dir_read.unwrap();
@@ -67,13 +74,14 @@ pub fn get_dir_contents_as_html(
 
         let dir_path = dir_path.unwrap();
         let is_dir = dir_path.path().is_dir();
-        let entry_display_path = dir_path
-            .path()
-            .into_os_string()
-            .into_string()
-            .unwrap()
-            .replace(source_dir.to_str().unwrap(), ".");
-        let entry_server_uri = format!("{}{}", address, entry_display_path.trim_start_matches("."));
+        let file_name = dir_path.file_name();
+        let file_name = file_name.to_string_lossy();
+        let entry_url_path = format!("{}/{}", url_path, file_name);
+        let entry_display_path = format!(".{}", entry_url_path);
+        let entry_server_uri = match link_prefix {
+            Some(link_prefix) => format!("{}{}", link_prefix, entry_url_path),
+            None => format!("{}{}", relative_dir, file_name),
+        };
 
         result = format!(
             "{}{}{}{}{}{}{}{}{}{}\n",
@@ -91,13 +99,7 @@ pub fn get_dir_contents_as_html(
     }
     result = format!("{}\n{}\n", result, LISTING_LIST_END);
     result = HTML_TEMPLATE
-        .replace(
-            "###TITLE###",
-            path.to_str()
-                .unwrap()
-                .replace(source_dir.to_str().unwrap(), ".")
-                .as_str(),
-        )
+        .replace("###TITLE###", format!(".{}", url_path).as_str())
         .replace("###BODY###", result.as_str());
 
     Ok(result)
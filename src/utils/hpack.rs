@@ -0,0 +1,608 @@
+use crate::utils::http_request::RequestLimits;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// Default size of the dynamic table, also the largest size accepted from clients
+pub const DEFAULT_TABLE_SIZE: usize = 4096;
+/// Size of an entry besides its name and value, also counted for header list sizes
+pub const ENTRY_OVERHEAD: usize = 32;
+
+/// # Static table of RFC 7541 Appendix A
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// # Huffman code lengths of RFC 7541 Appendix B, indexed by symbol, 256 is EOS
+///
+/// The code is canonical: codes are assigned in order of length, then symbol, so the
+/// lengths are enough to rebuild it.
+const HUFFMAN_CODE_LENGTHS: [u8; 257] = [
+    13, 23, 28, 28, 28, 28, 28, 28, 28, 24, 30, 28, 28, 30, 28, 28, 28, 28, 28, 28, 28, 28, 30, 28,
+    28, 28, 28, 28, 28, 28, 28, 28, 6, 10, 10, 12, 13, 6, 8, 11, 10, 10, 8, 11, 8, 6, 6, 6, 5, 5,
+    5, 6, 6, 6, 6, 6, 6, 6, 7, 8, 15, 6, 12, 10, 13, 6, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7,
+    7, 7, 7, 7, 7, 7, 7, 7, 8, 7, 8, 13, 19, 13, 14, 6, 15, 5, 6, 5, 6, 5, 6, 6, 6, 5, 7, 7, 6, 6,
+    6, 5, 6, 7, 6, 5, 5, 6, 7, 7, 7, 7, 7, 15, 11, 14, 13, 28, 20, 22, 20, 20, 22, 22, 22, 23, 22,
+    23, 23, 23, 23, 23, 24, 23, 24, 24, 22, 23, 24, 23, 23, 23, 23, 21, 22, 23, 22, 23, 23, 24, 22,
+    21, 20, 22, 22, 23, 23, 21, 23, 22, 22, 24, 21, 22, 23, 23, 21, 21, 22, 21, 23, 22, 23, 23, 20,
+    22, 22, 22, 23, 22, 22, 23, 26, 26, 20, 19, 22, 23, 22, 25, 26, 26, 26, 27, 27, 26, 24, 25, 19,
+    21, 26, 27, 27, 26, 27, 24, 21, 21, 26, 26, 28, 27, 27, 27, 20, 24, 20, 21, 22, 21, 21, 23, 22,
+    22, 25, 25, 24, 24, 26, 23, 26, 27, 26, 26, 27, 27, 27, 27, 27, 28, 27, 27, 27, 27, 27, 26, 30,
+];
+const HUFFMAN_EOS: u16 = 256;
+const HUFFMAN_MAX_LENGTH: usize = 30;
+
+/// # HPACK decoding errors
+#[derive(Debug, PartialEq)]
+pub enum HpackError {
+    Truncated,
+    IntegerOverflow,
+    InvalidIndex,
+    InvalidHuffman,
+    InvalidTableSize,
+    /// The header list has more fields than allowed, the block was decoded nonetheless
+    TooManyHeaders,
+    /// The header list is larger than allowed, the block was decoded nonetheless
+    HeadersTooLarge,
+}
+
+impl fmt::Display for HpackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                HpackError::Truncated => "Truncated header block",
+                HpackError::IntegerOverflow => "Integer overflow in header block",
+                HpackError::InvalidIndex => "Invalid header table index",
+                HpackError::InvalidHuffman => "Invalid Huffman encoded string",
+                HpackError::InvalidTableSize => "Invalid dynamic table size update",
+                HpackError::TooManyHeaders => "Too many header fields",
+                HpackError::HeadersTooLarge => "Header fields too large",
+            }
+        )
+    }
+}
+
+/// # Canonical Huffman decoding tables
+struct HuffmanDecoder {
+    /// Symbols ordered by code
+    symbols: Vec<u16>,
+    /// First code of every length
+    first_code: [u32; HUFFMAN_MAX_LENGTH + 1],
+    /// Index in `symbols` of the first code of every length
+    first_index: [usize; HUFFMAN_MAX_LENGTH + 1],
+    /// Number of codes of every length
+    count: [u32; HUFFMAN_MAX_LENGTH + 1],
+}
+
+fn get_huffman_decoder() -> &'static HuffmanDecoder {
+    static DECODER: OnceLock<HuffmanDecoder> = OnceLock::new();
+    DECODER.get_or_init(|| {
+        let mut symbols: Vec<u16> = (0..=HUFFMAN_EOS).collect();
+        symbols.sort_by_key(|&symbol| (HUFFMAN_CODE_LENGTHS[symbol as usize], symbol));
+
+        let mut decoder = HuffmanDecoder {
+            symbols,
+            first_code: [0; HUFFMAN_MAX_LENGTH + 1],
+            first_index: [0; HUFFMAN_MAX_LENGTH + 1],
+            count: [0; HUFFMAN_MAX_LENGTH + 1],
+        };
+        for length in HUFFMAN_CODE_LENGTHS {
+            decoder.count[length as usize] += 1;
+        }
+        let (mut code, mut index) = (0u32, 0usize);
+        for length in 1..=HUFFMAN_MAX_LENGTH {
+            decoder.first_code[length] = code;
+            decoder.first_index[length] = index;
+            code = (code + decoder.count[length]) << 1;
+            index += decoder.count[length] as usize;
+        }
+        decoder
+    })
+}
+
+/// # Decode a Huffman encoded string
+///
+/// The padding must be the most significant bits of EOS, shorter than a byte.
+pub fn huffman_decode(data: &[u8]) -> Result<Vec<u8>, HpackError> {
+    let decoder = get_huffman_decoder();
+    let mut result = Vec::with_capacity(data.len() * 8 / 5);
+    let (mut code, mut length) = (0u32, 0usize);
+
+    for byte in data {
+        for shift in (0..8).rev() {
+            code = (code << 1) | ((byte >> shift) & 1) as u32;
+            length += 1;
+            if length > HUFFMAN_MAX_LENGTH {
+                return Err(HpackError::InvalidHuffman);
+            }
+
+            let offset = code.wrapping_sub(decoder.first_code[length]);
+            if offset < decoder.count[length] {
+                let symbol = decoder.symbols[decoder.first_index[length] + offset as usize];
+                if symbol == HUFFMAN_EOS {
+                    return Err(HpackError::InvalidHuffman);
+                }
+                result.push(symbol as u8);
+                code = 0;
+                length = 0;
+            }
+        }
+    }
+
+    if length >= 8 || code != (1 << length) - 1 {
+        return Err(HpackError::InvalidHuffman);
+    }
+
+    Ok(result)
+}
+
+/// # Decode a prefixed integer
+///
+/// Returns the value and the number of bytes read. Values over `u32::MAX` overflow, whatever
+/// the width of `usize`.
+fn decode_integer(data: &[u8], prefix_bits: u8) -> Result<(usize, usize), HpackError> {
+    let mask = (1u8 << prefix_bits) - 1;
+    let prefix = *data.first().ok_or(HpackError::Truncated)? & mask;
+    if prefix < mask {
+        return Ok((prefix as usize, 1));
+    }
+
+    // at most 35 bits, computed in u64 to be checked on 32-bit targets too
+    let mut value = prefix as u64;
+    let mut shift = 0;
+    for (index, byte) in data.iter().enumerate().skip(1) {
+        if shift > 28 {
+            return Err(HpackError::IntegerOverflow);
+        }
+        value += ((byte & 0x7f) as u64) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            let value = u32::try_from(value).map_err(|_| HpackError::IntegerOverflow)?;
+            return Ok((value as usize, index + 1));
+        }
+    }
+
+    Err(HpackError::Truncated)
+}
+
+/// # Encode a prefixed integer, `flags` fill the bits above the prefix
+fn encode_integer(output: &mut Vec<u8>, value: usize, prefix_bits: u8, flags: u8) {
+    let mask = (1usize << prefix_bits) - 1;
+    if value < mask {
+        output.push(flags | value as u8);
+        return;
+    }
+
+    output.push(flags | mask as u8);
+    let mut value = value - mask;
+    while value >= 0x80 {
+        output.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
+}
+
+/// # Decode a string literal
+///
+/// Returns the string and the number of bytes read.
+fn decode_string(data: &[u8]) -> Result<(String, usize), HpackError> {
+    let huffman = data.first().ok_or(HpackError::Truncated)? & 0x80 != 0;
+    let (length, read) = decode_integer(data, 7)?;
+    let end = read.checked_add(length).ok_or(HpackError::Truncated)?;
+    let bytes = data.get(read..end).ok_or(HpackError::Truncated)?;
+
+    let bytes = if huffman {
+        huffman_decode(bytes)?
+    } else {
+        bytes.to_vec()
+    };
+    Ok((String::from_utf8_lossy(&bytes).into_owned(), end))
+}
+
+/// # Decoded header list, collected within request limits
+struct HeaderList<'a> {
+    limits: &'a RequestLimits,
+    headers: Vec<(String, String)>,
+    /// Regular fields, pseudo-header fields are left out as for HTTP/1
+    count: usize,
+    /// Names and values, plus [`ENTRY_OVERHEAD`] for every field
+    size: usize,
+    error: Option<HpackError>,
+}
+
+impl HeaderList<'_> {
+    /// # Add a field, or only count it once a limit is exceeded
+    fn push(&mut self, name: &str, value: &str) {
+        if !name.starts_with(':') {
+            self.count += 1;
+        }
+        self.size = self
+            .size
+            .saturating_add(name.len() + value.len() + ENTRY_OVERHEAD);
+        if self.error.is_none() {
+            if self.count > self.limits.max_headers {
+                self.error = Option::from(HpackError::TooManyHeaders);
+            } else if self.size > self.limits.max_header_size {
+                self.error = Option::from(HpackError::HeadersTooLarge);
+            }
+        }
+
+        match self.error {
+            None => self.headers.push((name.to_string(), value.to_string())),
+            Some(_) => self.headers.clear(),
+        }
+    }
+}
+
+/// # Header block decoder of a connection
+///
+/// Keeps the dynamic table shared by all header blocks sent by the client.
+pub struct Decoder {
+    table: VecDeque<(String, String)>,
+    table_size: usize,
+    max_table_size: usize,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder {
+            table: VecDeque::new(),
+            table_size: 0,
+            max_table_size: DEFAULT_TABLE_SIZE,
+        }
+    }
+}
+
+impl Decoder {
+    /// # Decode a header block
+    ///
+    /// A header list exceeding the number of fields or the header size of `limits` fails with
+    /// [`HpackError::TooManyHeaders`] or [`HpackError::HeadersTooLarge`]. The rest of the block
+    /// is still decoded, without collecting the fields, so that the dynamic table stays in sync
+    /// and the connection can go on.
+    pub fn decode(
+        &mut self,
+        mut data: &[u8],
+        limits: &RequestLimits,
+    ) -> Result<Vec<(String, String)>, HpackError> {
+        let mut headers = HeaderList {
+            limits,
+            headers: vec![],
+            count: 0,
+            size: 0,
+            error: None,
+        };
+        while let Some(&first) = data.first() {
+            if first & 0x80 != 0 {
+                // indexed header field, referenced rather than copied while only counted
+                let (index, read) = decode_integer(data, 7)?;
+                let (name, value) = self.entry(index)?;
+                headers.push(name, value);
+                data = &data[read..];
+            } else if first & 0xe0 == 0x20 {
+                // dynamic table size update
+                let (size, read) = decode_integer(data, 5)?;
+                if size > DEFAULT_TABLE_SIZE {
+                    return Err(HpackError::InvalidTableSize);
+                }
+                self.max_table_size = size;
+                self.evict(0);
+                data = &data[read..];
+            } else {
+                // literal header field, with incremental indexing or not
+                let indexing = first & 0x40 != 0;
+                let (index, mut read) = decode_integer(data, if indexing { 6 } else { 4 })?;
+                let name = if index == 0 {
+                    let (name, name_read) = decode_string(&data[read..])?;
+                    read += name_read;
+                    name
+                } else {
+                    self.entry(index)?.0.to_string()
+                };
+                let (value, value_read) = decode_string(&data[read..])?;
+                read += value_read;
+
+                headers.push(&name, &value);
+                if indexing {
+                    self.insert(name, value);
+                }
+                data = &data[read..];
+            }
+        }
+
+        match headers.error {
+            Some(error) => Err(error),
+            None => Ok(headers.headers),
+        }
+    }
+
+    /// # Entry of the static or dynamic table
+    fn entry(&self, index: usize) -> Result<(&str, &str), HpackError> {
+        match index {
+            0 => Err(HpackError::InvalidIndex),
+            1..=61 => Ok(STATIC_TABLE[index - 1]),
+            _ => self
+                .table
+                .get(index - 62)
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .ok_or(HpackError::InvalidIndex),
+        }
+    }
+
+    fn insert(&mut self, name: String, value: String) {
+        let size = name.len() + value.len() + ENTRY_OVERHEAD;
+        self.evict(size);
+        // an entry larger than the table empties it and is not added
+        if size <= self.max_table_size {
+            self.table_size += size;
+            self.table.push_front((name, value));
+        }
+    }
+
+    /// # Evict entries until `additional` bytes fit in the table
+    fn evict(&mut self, additional: usize) {
+        while self.table_size + additional > self.max_table_size {
+            match self.table.pop_back() {
+                Some((name, value)) => {
+                    self.table_size -= name.len() + value.len() + ENTRY_OVERHEAD;
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+/// # Encode a header block
+///
+/// The dynamic table is not used, so a single encoder can serve all streams. Headers
+/// found in the static table are referenced, other strings are sent as raw literals.
+pub fn encode(headers: &[(String, String)]) -> Vec<u8> {
+    let mut output = vec![];
+    for (name, value) in headers {
+        let name = name.to_lowercase();
+        if let Some(index) = STATIC_TABLE
+            .iter()
+            .position(|(n, v)| *n == name && *v == value)
+        {
+            encode_integer(&mut output, index + 1, 7, 0x80);
+            continue;
+        }
+
+        // literal header field without indexing
+        match STATIC_TABLE.iter().position(|(n, _)| *n == name) {
+            Some(index) => encode_integer(&mut output, index + 1, 4, 0),
+            None => {
+                output.push(0);
+                encode_integer(&mut output, name.len(), 7, 0);
+                output.extend_from_slice(name.as_bytes());
+            }
+        }
+        encode_integer(&mut output, value.len(), 7, 0);
+        output.extend_from_slice(value.as_bytes());
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::hpack::{decode_integer, encode, huffman_decode, Decoder, HpackError};
+    use crate::utils::http_request::RequestLimits;
+
+    const LIMITS: RequestLimits = RequestLimits {
+        max_request_line: 8192,
+        max_headers: 100,
+        max_header_size: 65536,
+        max_body_size: None,
+    };
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        let hex: String = hex.split_whitespace().collect();
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn to_pairs(headers: &[(&str, &str)]) -> Vec<(String, String)> {
+        headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_huffman_decode() {
+        assert_eq!(
+            huffman_decode(&from_hex("f1e3 c2e5 f23a 6ba0 ab90 f4ff")).unwrap(),
+            b"www.example.com"
+        );
+        // padding longer than 7 bits
+        assert_eq!(
+            huffman_decode(&from_hex("f1e3 c2e5 f23a 6ba0 ab90 f4ff ff")),
+            Err(HpackError::InvalidHuffman)
+        );
+    }
+
+    /// RFC 7541 C.4, requests with Huffman coding sharing the dynamic table
+    #[test]
+    fn test_decode_integer() {
+        // RFC 7541 C.1.2, 1337 with a 5-bit prefix
+        assert_eq!(decode_integer(&[0x1f, 0x9a, 0x0a], 5), Ok((1337, 3)));
+        assert_eq!(decode_integer(&[0x0a], 5), Ok((10, 1)));
+        assert_eq!(
+            decode_integer(&[0x7f, 0x80, 0xff, 0xff, 0xff, 0x0f], 7),
+            Ok((127 + 0xffff_ff80, 6))
+        );
+        assert_eq!(
+            decode_integer(&[0x7f, 0xff, 0xff, 0xff, 0xff, 0x0f], 7),
+            Err(HpackError::IntegerOverflow)
+        );
+        assert_eq!(
+            decode_integer(&[0x7f, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01], 7),
+            Err(HpackError::IntegerOverflow)
+        );
+        assert_eq!(decode_integer(&[0x7f, 0x80], 7), Err(HpackError::Truncated));
+    }
+
+    #[test]
+    fn test_decoder() {
+        let mut decoder = Decoder::default();
+        assert_eq!(
+            decoder
+                .decode(
+                    &from_hex("8286 8441 8cf1 e3c2 e5f2 3a6b a0ab 90f4 ff"),
+                    &LIMITS
+                )
+                .unwrap(),
+            to_pairs(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+            ])
+        );
+        assert_eq!(
+            decoder
+                .decode(&from_hex("8286 84be 5886 a8eb 1064 9cbf"), &LIMITS)
+                .unwrap(),
+            to_pairs(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+                ("cache-control", "no-cache"),
+            ])
+        );
+        assert_eq!(
+            decoder.decode(&from_hex("be"), &LIMITS).unwrap()[0].1,
+            "no-cache".to_string()
+        );
+        assert_eq!(
+            decoder.decode(&from_hex("c8"), &LIMITS),
+            Err(HpackError::InvalidIndex)
+        );
+    }
+
+    #[test]
+    fn test_decoder_limits() {
+        let limits = RequestLimits {
+            max_headers: 2,
+            max_header_size: 200,
+            ..RequestLimits::default()
+        };
+        // a literal indexed as entry 62, then referenced
+        let mut block = from_hex("4001 7805 6869 6869 21");
+        let mut decoder = Decoder::default();
+        assert_eq!(
+            decoder.decode(&block, &limits).unwrap(),
+            to_pairs(&[("x", "hihi!")])
+        );
+        block = from_hex("82be bebe");
+        assert_eq!(
+            decoder.decode(&block, &limits),
+            Err(HpackError::TooManyHeaders)
+        );
+        // the whole block was decoded, the table is still in sync
+        assert_eq!(
+            decoder.decode(&from_hex("be"), &limits).unwrap()[0].1,
+            "hihi!"
+        );
+
+        let limits = RequestLimits {
+            max_header_size: 64,
+            ..limits
+        };
+        assert_eq!(
+            decoder.decode(&from_hex("bebe"), &limits),
+            Err(HpackError::HeadersTooLarge)
+        );
+        // pseudo-header fields are not counted as fields
+        assert!(decoder
+            .decode(
+                &from_hex("8286 84"),
+                &RequestLimits {
+                    max_headers: 0,
+                    ..RequestLimits::default()
+                }
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn test_encode() {
+        let headers = to_pairs(&[
+            (":status", "200"),
+            ("Content-Type", "text/html"),
+            ("x-custom", "a"),
+        ]);
+        let encoded = encode(&headers);
+        assert_eq!(encoded[0], 0x88);
+
+        let mut decoded = Decoder::default().decode(&encoded, &LIMITS).unwrap();
+        decoded[1].0 = "Content-Type".to_string();
+        assert_eq!(decoded, headers);
+    }
+}
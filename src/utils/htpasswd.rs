@@ -0,0 +1,209 @@
+use crate::errors::invalid_argument::InvalidArgumentError;
+use crate::utils::digest::{constant_time_eq, sha1};
+use crate::utils::{base64, sha_crypt};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// # Users of an Apache htpasswd file
+///
+/// The file is read again when its modification time changes. A file which cannot be read or
+/// parsed keeps the previous users.
+pub struct Htpasswd {
+    path: PathBuf,
+    state: Mutex<HtpasswdState>,
+}
+
+struct HtpasswdState {
+    modified: Option<SystemTime>,
+    users: HashMap<String, String>,
+    /// Hash checked for unknown users, so that they take as long as known ones
+    dummy_hash: Option<String>,
+}
+
+impl Htpasswd {
+    /// # Load an htpasswd file
+    pub fn load(path: &Path) -> Result<Htpasswd, InvalidArgumentError> {
+        let argument = path.to_string_lossy();
+        let modified = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        let contents = fs::read_to_string(path)
+            .map_err(|err| InvalidArgumentError::new(&argument, err.to_string().as_str()))?;
+        let (users, dummy_hash) = parse(&contents)
+            .map_err(|reason| InvalidArgumentError::new(&argument, reason.as_str()))?;
+
+        Ok(Htpasswd {
+            path: path.to_path_buf(),
+            state: Mutex::new(HtpasswdState {
+                modified,
+                users,
+                dummy_hash,
+            }),
+        })
+    }
+
+    /// # Check the password of a user
+    ///
+    /// Unknown users are checked against the hash of another user and refused, so that the
+    /// response time does not tell which users exist.
+    pub fn verify(&self, user: &str, password: &str) -> bool {
+        let (hash, known) = {
+            let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+            self.reload_if_changed(&mut state);
+            match state.users.get(user) {
+                Some(hash) => (Option::from(hash.clone()), true),
+                None => (state.dummy_hash.clone(), false),
+            }
+        };
+
+        // the lock is not held while hashing
+        let verified = hash.is_some_and(|hash| verify_hash(password.as_bytes(), &hash));
+        verified && known
+    }
+
+    fn reload_if_changed(&self, state: &mut HtpasswdState) {
+        let modified = fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if modified.is_none() || modified == state.modified {
+            return;
+        }
+
+        match fs::read_to_string(&self.path)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| parse(&contents))
+        {
+            Ok((users, dummy_hash)) => {
+                state.users = users;
+                state.dummy_hash = dummy_hash;
+                state.modified = modified;
+            }
+            Err(err) => eprintln!("htpasswd Error: {}: {}", self.path.display(), err),
+        }
+    }
+}
+
+/// # Parse `USER:HASH` lines, with the hash checked for unknown users
+///
+/// Empty lines and `#` comments are skipped. Invalid lines and hashes which this build cannot
+/// verify are errors, rather than users silently locked out. The hash of the first user stands
+/// in for unknown users, as it has a scheme and cost used by the file.
+pub fn parse(contents: &str) -> Result<(HashMap<String, String>, Option<String>), String> {
+    let mut users = HashMap::new();
+    let mut dummy_hash = None;
+    for (number, line) in contents.lines().map(str::trim).enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once(':') {
+            Some((user, hash)) if is_supported(hash) => {
+                users.insert(user.to_string(), hash.to_string());
+                dummy_hash.get_or_insert_with(|| hash.to_string());
+            }
+            Some((user, hash)) if is_bcrypt(hash) => {
+                return Err(format!(
+                    "the bcrypt password hash of user {} requires the bcrypt feature",
+                    user
+                ));
+            }
+            Some((user, _)) => {
+                return Err(format!("unsupported password hash of user {}", user));
+            }
+            None => return Err(format!("invalid line {}", number + 1)),
+        }
+    }
+
+    Ok((users, dummy_hash))
+}
+
+/// # Whether a password hash scheme is supported
+///
+/// `{SHA}`, SHA-256 and SHA-512 crypt, and bcrypt with the `bcrypt` feature.
+fn is_supported(hash: &str) -> bool {
+    hash.starts_with("{SHA}")
+        || hash.starts_with("$5$")
+        || hash.starts_with("$6$")
+        || (cfg!(feature = "bcrypt") && is_bcrypt(hash))
+}
+
+fn is_bcrypt(hash: &str) -> bool {
+    hash.starts_with("$2a$") || hash.starts_with("$2b$") || hash.starts_with("$2y$")
+}
+
+/// # Check a password against an htpasswd hash
+pub fn verify_hash(password: &[u8], hash: &str) -> bool {
+    if let Some(digest) = hash.strip_prefix("{SHA}") {
+        return base64::decode(digest)
+            .is_some_and(|digest| constant_time_eq(&sha1(password), &digest));
+    }
+    if hash.starts_with("$5$") || hash.starts_with("$6$") {
+        return sha_crypt::verify(password, hash);
+    }
+    #[cfg(feature = "bcrypt")]
+    if is_bcrypt(hash) {
+        return bcrypt::verify(password, hash).unwrap_or(false);
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::htpasswd::{parse, verify_hash, Htpasswd};
+    use std::{env, fs, process};
+
+    #[test]
+    fn test_parse() {
+        let (users, dummy_hash) =
+            parse("# team\nalice:{SHA}5en6G6MezRroT3XKqkdPOmY/BfQ=\n\nbob:$5$salt$hash\n").unwrap();
+        assert_eq!(users.len(), 2);
+        assert!(users.contains_key("alice"));
+        assert_eq!(
+            dummy_hash.as_deref(),
+            Some("{SHA}5en6G6MezRroT3XKqkdPOmY/BfQ=")
+        );
+        assert!(parse("bob:$apr1$x$y\n").is_err());
+        assert!(parse("alice:{SHA}5en6G6MezRroT3XKqkdPOmY/BfQ=\nbroken\n").is_err());
+        assert_eq!(
+            parse("carol:$2y$04$KBCwKxOzLha2MUDgW0PjXe/hE/UCO9qF89dOydoHjSwxcHev5LRn.").is_ok(),
+            cfg!(feature = "bcrypt")
+        );
+    }
+
+    #[test]
+    fn test_verify_unknown_user() {
+        let path = env::temp_dir().join(format!("http-mini-htpasswd-{}", process::id()));
+        fs::write(&path, "alice:{SHA}5en6G6MezRroT3XKqkdPOmY/BfQ=\n").unwrap();
+        let htpasswd = Htpasswd::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(htpasswd.verify("alice", "secret"));
+        // the password of alice checks against her hash, but is not hers
+        assert!(!htpasswd.verify("mallory", "secret"));
+        assert!(!htpasswd.verify("mallory", "wrong"));
+    }
+
+    #[test]
+    fn test_verify_hash() {
+        // htpasswd -nbs alice secret
+        assert!(verify_hash(b"secret", "{SHA}5en6G6MezRroT3XKqkdPOmY/BfQ="));
+        assert!(!verify_hash(b"Secret", "{SHA}5en6G6MezRroT3XKqkdPOmY/BfQ="));
+        assert!(verify_hash(
+            b"secret",
+            "$5$ZbNBV2pm1ZvtRrUE$pLlrlSl2WYvk7YBXRz/subdBLpZHGmyc4m56CBr0xu3"
+        ));
+        assert!(!verify_hash(b"secret", "secret"));
+    }
+
+    #[cfg(feature = "bcrypt")]
+    #[test]
+    fn test_verify_hash_bcrypt() {
+        // cost 4, to keep the test fast
+        let hash = "$2y$04$KBCwKxOzLha2MUDgW0PjXe/hE/UCO9qF89dOydoHjSwxcHev5LRn.";
+        assert!(verify_hash(b"secret", hash));
+        assert!(!verify_hash(b"wrong", hash));
+    }
+}
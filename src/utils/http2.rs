@@ -0,0 +1,1052 @@
+use crate::traits::connection::Connection;
+use crate::utils::chaos::Faults;
+use crate::utils::hpack::{self, HpackError};
+use crate::utils::http_request::{HttpRequest, ParseHttpRequestError};
+use crate::utils::http_response::HttpResponse;
+use crate::utils::http_server::{
+    build_response, get_client, get_peer, get_request_line, log_request, site_path, ServerContext,
+};
+use crate::utils::throttle::ResponseThrottle;
+use std::collections::BTreeMap;
+use std::io::{Error, Read, Write};
+use std::thread;
+
+/// ALPN protocol identifier of HTTP/2 over TLS
+pub const ALPN_PROTOCOL: &[u8] = b"h2";
+/// First bytes sent by HTTP/2 clients
+pub const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+const FRAME_DATA: u8 = 0x0;
+const FRAME_HEADERS: u8 = 0x1;
+const FRAME_PRIORITY: u8 = 0x2;
+const FRAME_RST_STREAM: u8 = 0x3;
+const FRAME_SETTINGS: u8 = 0x4;
+const FRAME_PUSH_PROMISE: u8 = 0x5;
+const FRAME_PING: u8 = 0x6;
+const FRAME_GOAWAY: u8 = 0x7;
+const FRAME_WINDOW_UPDATE: u8 = 0x8;
+const FRAME_CONTINUATION: u8 = 0x9;
+
+const FLAG_END_STREAM: u8 = 0x1;
+const FLAG_ACK: u8 = 0x1;
+const FLAG_END_HEADERS: u8 = 0x4;
+const FLAG_PADDED: u8 = 0x8;
+const FLAG_PRIORITY: u8 = 0x20;
+
+const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+const SETTINGS_MAX_HEADER_LIST_SIZE: u16 = 0x6;
+
+const ERROR_NO_ERROR: u32 = 0x0;
+const ERROR_PROTOCOL: u32 = 0x1;
+const ERROR_INTERNAL: u32 = 0x2;
+const ERROR_FLOW_CONTROL: u32 = 0x3;
+const ERROR_STREAM_CLOSED: u32 = 0x5;
+const ERROR_FRAME_SIZE: u32 = 0x6;
+const ERROR_REFUSED_STREAM: u32 = 0x7;
+const ERROR_COMPRESSION: u32 = 0x9;
+const ERROR_ENHANCE_YOUR_CALM: u32 = 0xb;
+
+const DEFAULT_WINDOW_SIZE: i64 = 65535;
+const MAX_WINDOW_SIZE: i64 = (1 << 31) - 1;
+/// Largest frame accepted from clients, the protocol default
+const MAX_FRAME_SIZE: usize = 16384;
+const LARGEST_FRAME_SIZE: u32 = (1 << 24) - 1;
+const MAX_CONCURRENT_STREAMS: usize = 100;
+/// Largest header block accepted, CONTINUATION frames included
+const MAX_HEADER_BLOCK_SIZE: usize = 65536;
+const DEFAULT_WEIGHT: u16 = 16;
+
+/// Headers which are specific to HTTP/1 connections and must not be sent over HTTP/2
+const CONNECTION_HEADERS: [&str; 5] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// # Read the start of a connection as long as it matches the HTTP/2 preface
+///
+/// Returns the bytes read, equal to [`PREFACE`] for HTTP/2 clients. Other clients stop
+/// matching within the first bytes, which are then parsed as HTTP/1.
+pub fn read_preface<R: Read>(reader: &mut R) -> Vec<u8> {
+    let mut prefix = vec![];
+    let mut byte = [0u8; 1];
+    while prefix.len() < PREFACE.len() {
+        match reader.read(&mut byte) {
+            Ok(1) => {
+                prefix.push(byte[0]);
+                if byte[0] != PREFACE[prefix.len() - 1] {
+                    break;
+                }
+            }
+            _ => break,
+        }
+    }
+
+    prefix
+}
+
+/// # Reason to stop serving a connection
+enum Http2Error {
+    /// The connection failed or was closed by the client
+    Io,
+    /// The client broke the protocol, the connection ends with GOAWAY and this error code
+    Connection(u32),
+}
+
+impl From<Error> for Http2Error {
+    fn from(_: Error) -> Self {
+        Http2Error::Io
+    }
+}
+
+struct Frame {
+    kind: u8,
+    flags: u8,
+    stream_id: u32,
+    payload: Vec<u8>,
+}
+
+/// # Header block being received, possibly spanning CONTINUATION frames
+struct HeaderBlock {
+    stream_id: u32,
+    end_stream: bool,
+    weight: Option<u16>,
+    data: Vec<u8>,
+}
+
+/// # State of an open stream
+struct StreamState {
+    /// The request is still being received
+    receiving: bool,
+    /// Request headers, or the limit they exceed
+    request_headers: Result<Vec<(String, String)>, ParseHttpRequestError>,
+    /// Request contents received, in bytes
+    received: u64,
+    /// The request was answered before its end, the rest of its contents is ignored
+    discarding: bool,
+    send_window: i64,
+    weight: u16,
+    /// Response contents, sent as flow control allows
+    contents: Vec<u8>,
+    sent: usize,
+    /// Bandwidth limits of the response
+    throttle: Option<ResponseThrottle>,
+    /// Body faults of the response, applied once `fault_at` bytes are sent
+    faults: Faults,
+    fault_at: Option<usize>,
+}
+
+fn read_frame<R: Read>(reader: &mut R) -> Result<Frame, Http2Error> {
+    let mut header = [0u8; 9];
+    reader.read_exact(&mut header)?;
+    let length = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+    if length > MAX_FRAME_SIZE {
+        return Err(Http2Error::Connection(ERROR_FRAME_SIZE));
+    }
+
+    let mut payload = vec![0u8; length];
+    reader.read_exact(&mut payload)?;
+    Ok(Frame {
+        kind: header[3],
+        flags: header[4],
+        stream_id: u32::from_be_bytes([header[5], header[6], header[7], header[8]]) & 0x7fff_ffff,
+        payload,
+    })
+}
+
+fn write_frame<W: Write>(
+    writer: &mut W,
+    kind: u8,
+    flags: u8,
+    stream_id: u32,
+    payload: &[u8],
+) -> Result<(), Error> {
+    let length = (payload.len() as u32).to_be_bytes();
+    let mut frame = Vec::with_capacity(9 + payload.len());
+    frame.extend_from_slice(&length[1..]);
+    frame.push(kind);
+    frame.push(flags);
+    frame.extend_from_slice(&stream_id.to_be_bytes());
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// # Payload of a frame without its padding
+fn strip_padding(payload: &[u8], flags: u8) -> Result<&[u8], Http2Error> {
+    if flags & FLAG_PADDED == 0 {
+        return Ok(payload);
+    }
+
+    match payload.first() {
+        Some(&padding) if (padding as usize) < payload.len() => {
+            Ok(&payload[1..payload.len() - padding as usize])
+        }
+        _ => Err(Http2Error::Connection(ERROR_PROTOCOL)),
+    }
+}
+
+/// # Request of decoded HTTP/2 headers
+///
+/// Pseudo-headers give the request line, `:authority` stands in for a missing `Host`.
+fn get_request(headers: Vec<(String, String)>) -> Result<HttpRequest, ParseHttpRequestError> {
+    let (mut method, mut path, mut authority) = (String::new(), String::new(), None);
+    let mut regular_headers = vec![];
+    for (name, value) in headers {
+        match name.as_str() {
+            ":method" => method = value,
+            ":path" => path = value,
+            ":authority" => authority = Option::from(value),
+            _ if name.starts_with(':') => {}
+            _ => regular_headers.push((name, value)),
+        }
+    }
+
+    if let Some(authority) = authority {
+        if !regular_headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("host"))
+        {
+            regular_headers.insert(0, ("host".to_string(), authority));
+        }
+    }
+
+    HttpRequest::new(&method, &path, "HTTP/2", regular_headers)
+}
+
+/// # HTTP/2 connection
+///
+/// Requests are answered as soon as they are complete. Response contents of all streams are
+/// interleaved by weight within the flow control windows granted by the client.
+struct Http2Connection<'a, C: Connection> {
+    stream: C,
+    context: &'a ServerContext,
+    address: &'a str,
+    decoder: hpack::Decoder,
+    streams: BTreeMap<u32, StreamState>,
+    header_block: Option<HeaderBlock>,
+    last_stream_id: u32,
+    send_window: i64,
+    initial_window_size: i64,
+    peer_max_frame_size: usize,
+}
+
+/// # Serve HTTP/2 on a connection
+///
+/// `preface_read` tells whether the client preface was consumed already, as happens when
+/// detecting prior knowledge clients.
+pub fn serve<C: Connection>(stream: C, context: &ServerContext, address: &str, preface_read: bool) {
+    let mut connection = Http2Connection {
+        stream,
+        context,
+        address,
+        decoder: hpack::Decoder::default(),
+        streams: BTreeMap::new(),
+        header_block: None,
+        last_stream_id: 0,
+        send_window: DEFAULT_WINDOW_SIZE,
+        initial_window_size: DEFAULT_WINDOW_SIZE,
+        peer_max_frame_size: MAX_FRAME_SIZE,
+    };
+
+    match connection.run(preface_read) {
+        Ok(()) => connection.go_away(ERROR_NO_ERROR),
+        Err(Http2Error::Connection(code)) => connection.go_away(code),
+        Err(Http2Error::Io) => {}
+    }
+}
+
+impl<C: Connection> Http2Connection<'_, C> {
+    fn run(&mut self, preface_read: bool) -> Result<(), Http2Error> {
+        if !preface_read {
+            let mut preface = [0u8; 24];
+            self.stream.read_exact(&mut preface)?;
+            if preface != PREFACE {
+                return Err(Http2Error::Connection(ERROR_PROTOCOL));
+            }
+        }
+
+        let mut settings = vec![];
+        settings.extend_from_slice(&SETTINGS_MAX_CONCURRENT_STREAMS.to_be_bytes());
+        settings.extend_from_slice(&(MAX_CONCURRENT_STREAMS as u32).to_be_bytes());
+        // advisory, larger header lists are answered with 431
+        let max_header_list_size = self.context.request_limits.max_header_size;
+        settings.extend_from_slice(&SETTINGS_MAX_HEADER_LIST_SIZE.to_be_bytes());
+        settings
+            .extend_from_slice(&(max_header_list_size.min(u32::MAX as usize) as u32).to_be_bytes());
+        write_frame(&mut self.stream, FRAME_SETTINGS, 0, 0, &settings)?;
+        self.stream.flush()?;
+
+        // the client preface ends with a SETTINGS frame
+        let frame = read_frame(&mut self.stream)?;
+        if frame.kind != FRAME_SETTINGS || frame.flags & FLAG_ACK != 0 {
+            return Err(Http2Error::Connection(ERROR_PROTOCOL));
+        }
+        self.on_settings(frame)?;
+        self.stream.flush()?;
+
+        loop {
+            let frame = read_frame(&mut self.stream)?;
+            if frame.kind == FRAME_GOAWAY {
+                return Ok(());
+            }
+            self.on_frame(frame)?;
+            self.send_data()?;
+            self.stream.flush()?;
+        }
+    }
+
+    fn on_frame(&mut self, frame: Frame) -> Result<(), Http2Error> {
+        if let Some(block) = &self.header_block {
+            if frame.kind != FRAME_CONTINUATION || frame.stream_id != block.stream_id {
+                return Err(Http2Error::Connection(ERROR_PROTOCOL));
+            }
+        }
+
+        match frame.kind {
+            FRAME_DATA => self.on_data(frame),
+            FRAME_HEADERS => self.on_headers(frame),
+            FRAME_PRIORITY => self.on_priority(frame),
+            FRAME_RST_STREAM => {
+                if frame.stream_id == 0 {
+                    return Err(Http2Error::Connection(ERROR_PROTOCOL));
+                }
+                if frame.payload.len() != 4 {
+                    return Err(Http2Error::Connection(ERROR_FRAME_SIZE));
+                }
+                self.streams.remove(&frame.stream_id);
+                Ok(())
+            }
+            FRAME_SETTINGS => self.on_settings(frame),
+            FRAME_PUSH_PROMISE => Err(Http2Error::Connection(ERROR_PROTOCOL)),
+            FRAME_PING => {
+                if frame.stream_id != 0 {
+                    return Err(Http2Error::Connection(ERROR_PROTOCOL));
+                }
+                if frame.payload.len() != 8 {
+                    return Err(Http2Error::Connection(ERROR_FRAME_SIZE));
+                }
+                if frame.flags & FLAG_ACK == 0 {
+                    write_frame(&mut self.stream, FRAME_PING, FLAG_ACK, 0, &frame.payload)?;
+                }
+                Ok(())
+            }
+            FRAME_WINDOW_UPDATE => self.on_window_update(frame),
+            FRAME_CONTINUATION => self.on_continuation(frame),
+            // unknown frame types are ignored
+            _ => Ok(()),
+        }
+    }
+
+    fn on_settings(&mut self, frame: Frame) -> Result<(), Http2Error> {
+        if frame.stream_id != 0 {
+            return Err(Http2Error::Connection(ERROR_PROTOCOL));
+        }
+        if frame.flags & FLAG_ACK != 0 {
+            return match frame.payload.is_empty() {
+                true => Ok(()),
+                false => Err(Http2Error::Connection(ERROR_FRAME_SIZE)),
+            };
+        }
+        if !frame.payload.len().is_multiple_of(6) {
+            return Err(Http2Error::Connection(ERROR_FRAME_SIZE));
+        }
+
+        for setting in frame.payload.chunks_exact(6) {
+            let value = read_u32(&setting[2..]);
+            match u16::from_be_bytes([setting[0], setting[1]]) {
+                SETTINGS_ENABLE_PUSH if value > 1 => {
+                    return Err(Http2Error::Connection(ERROR_PROTOCOL));
+                }
+                SETTINGS_INITIAL_WINDOW_SIZE => {
+                    if value as i64 > MAX_WINDOW_SIZE {
+                        return Err(Http2Error::Connection(ERROR_FLOW_CONTROL));
+                    }
+                    // open streams keep what they used of the previous window size
+                    let delta = value as i64 - self.initial_window_size;
+                    for state in self.streams.values_mut() {
+                        state.send_window += delta;
+                        if state.send_window > MAX_WINDOW_SIZE {
+                            return Err(Http2Error::Connection(ERROR_FLOW_CONTROL));
+                        }
+                    }
+                    self.initial_window_size = value as i64;
+                }
+                SETTINGS_MAX_FRAME_SIZE => {
+                    if !(MAX_FRAME_SIZE as u32..=LARGEST_FRAME_SIZE).contains(&value) {
+                        return Err(Http2Error::Connection(ERROR_PROTOCOL));
+                    }
+                    self.peer_max_frame_size = value as usize;
+                }
+                // the header table size concerns the encoder, which does not use the
+                // dynamic table, other settings concern clients
+                _ => {}
+            }
+        }
+
+        write_frame(&mut self.stream, FRAME_SETTINGS, FLAG_ACK, 0, &[])?;
+        Ok(())
+    }
+
+    fn on_window_update(&mut self, frame: Frame) -> Result<(), Http2Error> {
+        if frame.payload.len() != 4 {
+            return Err(Http2Error::Connection(ERROR_FRAME_SIZE));
+        }
+        let increment = (read_u32(&frame.payload) & 0x7fff_ffff) as i64;
+
+        if frame.stream_id == 0 {
+            if increment == 0 {
+                return Err(Http2Error::Connection(ERROR_PROTOCOL));
+            }
+            self.send_window += increment;
+            if self.send_window > MAX_WINDOW_SIZE {
+                return Err(Http2Error::Connection(ERROR_FLOW_CONTROL));
+            }
+            return Ok(());
+        }
+
+        let error = match self.streams.get_mut(&frame.stream_id) {
+            Some(_) if increment == 0 => ERROR_PROTOCOL,
+            Some(state) => {
+                state.send_window += increment;
+                if state.send_window <= MAX_WINDOW_SIZE {
+                    return Ok(());
+                }
+                ERROR_FLOW_CONTROL
+            }
+            // the stream may have been closed already
+            None => return Ok(()),
+        };
+        self.reset_stream(frame.stream_id, error)
+    }
+
+    fn on_priority(&mut self, frame: Frame) -> Result<(), Http2Error> {
+        if frame.stream_id == 0 {
+            return Err(Http2Error::Connection(ERROR_PROTOCOL));
+        }
+        if frame.payload.len() != 5 {
+            return self.reset_stream(frame.stream_id, ERROR_FRAME_SIZE);
+        }
+
+        // dependencies are not tracked, the weight orders the streams sending contents
+        if let Some(state) = self.streams.get_mut(&frame.stream_id) {
+            state.weight = frame.payload[4] as u16 + 1;
+        }
+        Ok(())
+    }
+
+    fn on_data(&mut self, frame: Frame) -> Result<(), Http2Error> {
+        if frame.stream_id == 0 {
+            return Err(Http2Error::Connection(ERROR_PROTOCOL));
+        }
+        // request contents are not used, the window they took is given back right away
+        strip_padding(&frame.payload, frame.flags)?;
+        let length = (frame.payload.len() as u32).to_be_bytes();
+        let end_stream = frame.flags & FLAG_END_STREAM != 0;
+        if !frame.payload.is_empty() {
+            write_frame(&mut self.stream, FRAME_WINDOW_UPDATE, 0, 0, &length)?;
+        }
+
+        let max_body_size = self.context.request_limits.max_body_size;
+        match self.streams.get_mut(&frame.stream_id) {
+            Some(state) if state.discarding => {
+                state.discarding = !end_stream;
+                Ok(())
+            }
+            Some(state) if state.receiving => {
+                state.received += frame.payload.len() as u64;
+                if max_body_size.is_some_and(|max| state.received > max) {
+                    // answered right away, the client may stop sending
+                    state.request_headers = Err(ParseHttpRequestError::BodyTooLarge);
+                    state.discarding = !end_stream;
+                    return self.on_request(frame.stream_id);
+                }
+                if end_stream {
+                    return self.on_request(frame.stream_id);
+                }
+                if !frame.payload.is_empty() {
+                    write_frame(
+                        &mut self.stream,
+                        FRAME_WINDOW_UPDATE,
+                        0,
+                        frame.stream_id,
+                        &length,
+                    )?;
+                }
+                Ok(())
+            }
+            _ if frame.stream_id > self.last_stream_id => {
+                Err(Http2Error::Connection(ERROR_PROTOCOL))
+            }
+            _ => self.reset_stream(frame.stream_id, ERROR_STREAM_CLOSED),
+        }
+    }
+
+    fn on_headers(&mut self, frame: Frame) -> Result<(), Http2Error> {
+        if frame.stream_id == 0 || frame.stream_id.is_multiple_of(2) {
+            return Err(Http2Error::Connection(ERROR_PROTOCOL));
+        }
+
+        let mut data = strip_padding(&frame.payload, frame.flags)?;
+        let mut weight = None;
+        if frame.flags & FLAG_PRIORITY != 0 {
+            if data.len() < 5 {
+                return Err(Http2Error::Connection(ERROR_FRAME_SIZE));
+            }
+            if read_u32(data) & 0x7fff_ffff == frame.stream_id {
+                return Err(Http2Error::Connection(ERROR_PROTOCOL));
+            }
+            weight = Option::from(data[4] as u16 + 1);
+            data = &data[5..];
+        }
+
+        let block = HeaderBlock {
+            stream_id: frame.stream_id,
+            end_stream: frame.flags & FLAG_END_STREAM != 0,
+            weight,
+            data: data.to_vec(),
+        };
+        if frame.flags & FLAG_END_HEADERS != 0 {
+            return self.on_header_block(block);
+        }
+        self.header_block = Option::from(block);
+        Ok(())
+    }
+
+    fn on_continuation(&mut self, frame: Frame) -> Result<(), Http2Error> {
+        let mut block = match self.header_block.take() {
+            Some(block) => block,
+            None => return Err(Http2Error::Connection(ERROR_PROTOCOL)),
+        };
+        block.data.extend_from_slice(&frame.payload);
+        if block.data.len() > MAX_HEADER_BLOCK_SIZE {
+            return Err(Http2Error::Connection(ERROR_ENHANCE_YOUR_CALM));
+        }
+
+        if frame.flags & FLAG_END_HEADERS != 0 {
+            return self.on_header_block(block);
+        }
+        self.header_block = Option::from(block);
+        Ok(())
+    }
+
+    fn on_header_block(&mut self, block: HeaderBlock) -> Result<(), Http2Error> {
+        // blocks are decoded even for refused streams, to keep the HPACK state in sync
+        let headers = match self
+            .decoder
+            .decode(&block.data, &self.context.request_limits)
+        {
+            Ok(headers) => Ok(headers),
+            Err(HpackError::TooManyHeaders) => Err(ParseHttpRequestError::TooManyHeaders),
+            Err(HpackError::HeadersTooLarge) => Err(ParseHttpRequestError::HeadersTooLarge),
+            Err(_) => return Err(Http2Error::Connection(ERROR_COMPRESSION)),
+        };
+
+        // trailers
+        if let Some(state) = self.streams.get_mut(&block.stream_id) {
+            if state.discarding {
+                state.discarding = !block.end_stream;
+                return Ok(());
+            }
+            if !state.receiving {
+                return self.reset_stream(block.stream_id, ERROR_STREAM_CLOSED);
+            }
+            if !block.end_stream {
+                return Err(Http2Error::Connection(ERROR_PROTOCOL));
+            }
+            if let Err(err) = headers {
+                state.request_headers = Err(err);
+            }
+            return self.on_request(block.stream_id);
+        }
+
+        if block.stream_id <= self.last_stream_id {
+            return Err(Http2Error::Connection(ERROR_PROTOCOL));
+        }
+        self.last_stream_id = block.stream_id;
+        if self.streams.len() >= MAX_CONCURRENT_STREAMS {
+            return self.reset_stream(block.stream_id, ERROR_REFUSED_STREAM);
+        }
+
+        self.streams.insert(
+            block.stream_id,
+            StreamState {
+                receiving: true,
+                request_headers: headers,
+                received: 0,
+                discarding: false,
+                send_window: self.initial_window_size,
+                weight: block.weight.unwrap_or(DEFAULT_WEIGHT),
+                contents: vec![],
+                sent: 0,
+                throttle: None,
+                faults: Faults::default(),
+                fault_at: None,
+            },
+        );
+        if block.end_stream {
+            return self.on_request(block.stream_id);
+        }
+        Ok(())
+    }
+
+    /// # Answer a complete request
+    ///
+    /// Headers are sent right away, contents are left to [`Self::send_data`].
+    fn on_request(&mut self, stream_id: u32) -> Result<(), Http2Error> {
+        let headers = match self.streams.get_mut(&stream_id) {
+            Some(state) => {
+                state.receiving = false;
+                std::mem::replace(&mut state.request_headers, Ok(vec![]))
+            }
+            None => return Ok(()),
+        };
+
+        let bandwidth = &self.context.bandwidth;
+        let (response, faults, client, request_line, is_head, response_rate) =
+            match headers.and_then(get_request) {
+                Ok(request) => {
+                    let mut client = get_client(&self.stream, &request, self.context);
+                    let (response, faults) =
+                        build_response(&request, self.context, self.address, &mut client);
+                    (
+                        response,
+                        faults,
+                        client,
+                        get_request_line(&request),
+                        request.method.as_deref() == Some("HEAD"),
+                        bandwidth.response_rate(site_path(self.context, &request)),
+                    )
+                }
+                Err(err) => (
+                    HttpResponse::new(err.status(), None, Option::from(Vec::from(err.to_string()))),
+                    Faults::default(),
+                    get_peer(&self.stream),
+                    "-".to_string(),
+                    false,
+                    bandwidth.response_rate,
+                ),
+            };
+        log_request(
+            &self.stream,
+            self.context,
+            &client,
+            request_line.as_str(),
+            &response,
+        );
+
+        let contents = response.contents.unwrap_or_default();
+        let mut headers = vec![(":status".to_string(), response.status.to_string())];
+        for (name, value) in response.headers.unwrap_or_default() {
+            let name = name.to_lowercase();
+            if !CONNECTION_HEADERS.contains(&name.as_str()) {
+                headers.push((name, value));
+            }
+        }
+        let content_length =
+            (contents.len() as i64 + faults.length_offset.unwrap_or_default()).max(0);
+        headers.push(("content-length".to_string(), content_length.to_string()));
+
+        // the body is cut or paused halfway, as over HTTP/1.1
+        let cut = !is_head && (faults.reset || faults.truncate);
+        let fault_at = (cut || (!is_head && faults.stall.is_some())).then_some(contents.len() / 2);
+        let end_stream = (contents.is_empty() || is_head) && !cut;
+        self.write_headers(stream_id, &headers, end_stream)?;
+        if end_stream {
+            self.streams.remove(&stream_id);
+        } else if let Some(state) = self.streams.get_mut(&stream_id) {
+            state.contents = contents;
+            state.faults = faults;
+            state.fault_at = fault_at;
+            if bandwidth.is_limited() {
+                state.throttle =
+                    Option::from(ResponseThrottle::new(bandwidth, client.addr, response_rate));
+            }
+            if state.contents.is_empty() {
+                self.apply_fault(stream_id)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// # Apply the body faults of a stream, halfway through its contents
+    ///
+    /// `reset` resets the stream with INTERNAL_ERROR, `truncate` closes it without ending its
+    /// contents, and `stall` pauses the whole connection.
+    fn apply_fault(&mut self, stream_id: u32) -> Result<(), Error> {
+        let Some(state) = self.streams.get_mut(&stream_id) else {
+            return Ok(());
+        };
+        state.fault_at = None;
+        let error = if state.faults.reset {
+            ERROR_INTERNAL
+        } else if state.faults.truncate {
+            ERROR_NO_ERROR
+        } else {
+            if let Some(stall) = state.faults.stall {
+                self.stream.flush()?;
+                thread::sleep(stall);
+            }
+            return Ok(());
+        };
+        self.streams.remove(&stream_id);
+        write_frame(
+            &mut self.stream,
+            FRAME_RST_STREAM,
+            0,
+            stream_id,
+            &error.to_be_bytes(),
+        )
+    }
+
+    /// # Send a header block, split into CONTINUATION frames when needed
+    fn write_headers(
+        &mut self,
+        stream_id: u32,
+        headers: &[(String, String)],
+        end_stream: bool,
+    ) -> Result<(), Error> {
+        let block = hpack::encode(headers);
+        let mut chunks = block.chunks(self.peer_max_frame_size).peekable();
+        let mut kind = FRAME_HEADERS;
+        let mut flags = if end_stream { FLAG_END_STREAM } else { 0 };
+        loop {
+            let chunk = chunks.next().unwrap_or_default();
+            if chunks.peek().is_none() {
+                flags |= FLAG_END_HEADERS;
+            }
+            write_frame(&mut self.stream, kind, flags, stream_id, chunk)?;
+            if flags & FLAG_END_HEADERS != 0 {
+                return Ok(());
+            }
+            kind = FRAME_CONTINUATION;
+            flags = 0;
+        }
+    }
+
+    /// # Send response contents as flow control allows
+    ///
+    /// Streams take turns, each sending a number of frames proportional to its weight.
+    fn send_data(&mut self) -> Result<(), Error> {
+        loop {
+            let mut ready: Vec<(u16, u32)> = self
+                .streams
+                .iter()
+                .filter(|(_, state)| {
+                    !state.receiving && state.sent < state.contents.len() && state.send_window > 0
+                })
+                .map(|(&stream_id, state)| (state.weight, stream_id))
+                .collect();
+            if ready.is_empty() || self.send_window <= 0 {
+                return Ok(());
+            }
+            ready.sort_by_key(|&(weight, stream_id)| (u16::MAX - weight, stream_id));
+
+            for (weight, stream_id) in ready {
+                for _ in 0..weight.div_ceil(DEFAULT_WEIGHT) {
+                    if self
+                        .streams
+                        .get(&stream_id)
+                        .is_some_and(|state| state.fault_at == Some(state.sent))
+                    {
+                        self.apply_fault(stream_id)?;
+                    }
+                    let state = match self.streams.get_mut(&stream_id) {
+                        Some(state) => state,
+                        None => break,
+                    };
+                    let size = (state.contents.len() - state.sent)
+                        .min(state.fault_at.map_or(usize::MAX, |at| at - state.sent))
+                        .min(self.send_window.max(0) as usize)
+                        .min(state.send_window.max(0) as usize)
+                        .min(self.peer_max_frame_size)
+                        .min(state.throttle.as_ref().map_or(usize::MAX, |t| t.chunk_size));
+                    if size == 0 {
+                        break;
+                    }
+                    if let Some(throttle) = &mut state.throttle {
+                        let wait = throttle.reserve(&self.context.bandwidth, size);
+                        if !wait.is_zero() {
+                            self.stream.flush()?;
+                            thread::sleep(wait);
+                        }
+                    }
+
+                    let end = state.sent + size;
+                    let flags = if end == state.contents.len() {
+                        FLAG_END_STREAM
+                    } else {
+                        0
+                    };
+                    write_frame(
+                        &mut self.stream,
+                        FRAME_DATA,
+                        flags,
+                        stream_id,
+                        &state.contents[state.sent..end],
+                    )?;
+                    state.sent = end;
+                    state.send_window -= size as i64;
+                    self.send_window -= size as i64;
+                    if flags == FLAG_END_STREAM {
+                        self.streams.remove(&stream_id);
+                    }
+                }
+            }
+        }
+    }
+
+    fn reset_stream(&mut self, stream_id: u32, error: u32) -> Result<(), Http2Error> {
+        self.streams.remove(&stream_id);
+        write_frame(
+            &mut self.stream,
+            FRAME_RST_STREAM,
+            0,
+            stream_id,
+            &error.to_be_bytes(),
+        )?;
+        Ok(())
+    }
+
+    /// # End the connection
+    fn go_away(&mut self, error: u32) {
+        let mut payload = self.last_stream_id.to_be_bytes().to_vec();
+        payload.extend_from_slice(&error.to_be_bytes());
+        let _ = write_frame(&mut self.stream, FRAME_GOAWAY, 0, 0, &payload)
+            .and_then(|_| self.stream.flush());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::hpack;
+    use crate::utils::http2::{
+        write_frame, ERROR_INTERNAL, ERROR_NO_ERROR, FLAG_END_HEADERS, FLAG_END_STREAM, FRAME_DATA,
+        FRAME_GOAWAY, FRAME_HEADERS, FRAME_RST_STREAM, FRAME_SETTINGS, PREFACE,
+    };
+    use crate::utils::http_request::RequestLimits;
+    use crate::utils::http_server::{handle_connection, ServerContext};
+    use crate::utils::mock_connection::MockConnection;
+    use std::ffi::OsStr;
+    use std::path::PathBuf;
+
+    /// # Frames as `(kind, flags, stream_id, payload)`
+    fn split_frames(mut data: &[u8]) -> Vec<(u8, u8, u32, Vec<u8>)> {
+        let mut frames = vec![];
+        while data.len() >= 9 {
+            let length = u32::from_be_bytes([0, data[0], data[1], data[2]]) as usize;
+            let stream_id = u32::from_be_bytes([data[5], data[6], data[7], data[8]]);
+            frames.push((data[3], data[4], stream_id, data[9..9 + length].to_vec()));
+            data = &data[9 + length..];
+        }
+        frames
+    }
+
+    /// # Response headers as `(stream_id, headers)`
+    fn response_headers(output: &[u8]) -> Vec<(u32, Vec<(String, String)>)> {
+        let mut decoder = hpack::Decoder::default();
+        split_frames(output)
+            .iter()
+            .filter(|frame| frame.0 == FRAME_HEADERS)
+            .map(|frame| {
+                let headers = decoder.decode(&frame.3, &RequestLimits::default());
+                (frame.2, headers.unwrap())
+            })
+            .collect()
+    }
+
+    fn request_headers(path: &str) -> Vec<u8> {
+        hpack::encode(&[
+            (":method".to_string(), "GET".to_string()),
+            (":scheme".to_string(), "http".to_string()),
+            (":path".to_string(), path.to_string()),
+            (":authority".to_string(), "localhost".to_string()),
+        ])
+    }
+
+    #[test]
+    fn test_prior_knowledge() {
+        let mut input = PREFACE.to_vec();
+        write_frame(&mut input, FRAME_SETTINGS, 0, 0, &[]).unwrap();
+        let flags = FLAG_END_HEADERS | FLAG_END_STREAM;
+        write_frame(
+            &mut input,
+            FRAME_HEADERS,
+            flags,
+            1,
+            &request_headers("/Cargo.toml"),
+        )
+        .unwrap();
+        write_frame(
+            &mut input,
+            FRAME_HEADERS,
+            flags,
+            3,
+            &request_headers("/src"),
+        )
+        .unwrap();
+
+        let mut connection = MockConnection::new(&input);
+        let context = ServerContext::new(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")),
+            OsStr::new("http-mini"),
+        );
+        handle_connection(&mut connection, &context, "");
+
+        let frames = split_frames(connection.output());
+        let headers = response_headers(connection.output());
+        assert_eq!(headers[0].0, 1);
+        assert!(headers[0]
+            .1
+            .contains(&(":status".to_string(), "200".to_string())));
+        assert!(headers[0]
+            .1
+            .contains(&("content-type".to_string(), "text/x-toml".to_string())));
+        assert_eq!(headers[1].0, 3);
+        assert!(headers[1]
+            .1
+            .contains(&("content-type".to_string(), "text/html".to_string())));
+
+        let data = frames.iter().find(|frame| frame.0 == FRAME_DATA).unwrap();
+        assert_eq!(data.1, FLAG_END_STREAM);
+        assert!(String::from_utf8_lossy(&data.3).contains("name = \"http-mini\""));
+        // the connection ends when the client goes away
+        assert!(!frames.iter().any(|frame| frame.0 == FRAME_GOAWAY));
+    }
+
+    #[test]
+    fn test_hpack_bomb() {
+        // a 4000 bytes header added to the dynamic table, then repeated from its index
+        let mut block = vec![0x40, 0x01, b'x', 0x7f, 0xa1, 0x1e];
+        block.extend_from_slice(&[b'a'; 4000]);
+        block.extend_from_slice(&[0xbe; 3000]);
+
+        let mut input = PREFACE.to_vec();
+        write_frame(&mut input, FRAME_SETTINGS, 0, 0, &[]).unwrap();
+        let flags = FLAG_END_HEADERS | FLAG_END_STREAM;
+        write_frame(&mut input, FRAME_HEADERS, flags, 1, &block).unwrap();
+        write_frame(
+            &mut input,
+            FRAME_HEADERS,
+            flags,
+            3,
+            &request_headers("/Cargo.toml"),
+        )
+        .unwrap();
+
+        let mut connection = MockConnection::new(&input);
+        let context = ServerContext::new(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")),
+            OsStr::new("http-mini"),
+        );
+        handle_connection(&mut connection, &context, "");
+
+        let headers = response_headers(connection.output());
+        assert_eq!(headers[0].0, 1);
+        assert!(headers[0]
+            .1
+            .contains(&(":status".to_string(), "431".to_string())));
+        // the connection is kept
+        assert_eq!(headers[1].0, 3);
+        assert!(headers[1]
+            .1
+            .contains(&(":status".to_string(), "200".to_string())));
+    }
+
+    #[test]
+    fn test_body_too_large() {
+        let mut input = PREFACE.to_vec();
+        write_frame(&mut input, FRAME_SETTINGS, 0, 0, &[]).unwrap();
+        write_frame(
+            &mut input,
+            FRAME_HEADERS,
+            FLAG_END_HEADERS,
+            1,
+            &request_headers("/Cargo.toml"),
+        )
+        .unwrap();
+        write_frame(&mut input, FRAME_DATA, 0, 1, &[b'a'; 20]).unwrap();
+        write_frame(&mut input, FRAME_DATA, FLAG_END_STREAM, 1, &[b'a'; 20]).unwrap();
+
+        let mut connection = MockConnection::new(&input);
+        let mut context = ServerContext::new(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")),
+            OsStr::new("http-mini"),
+        );
+        context.request_limits.max_body_size = Option::from(10);
+        handle_connection(&mut connection, &context, "");
+
+        let headers = response_headers(connection.output());
+        assert_eq!(headers.len(), 1);
+        assert!(headers[0]
+            .1
+            .contains(&(":status".to_string(), "413".to_string())));
+    }
+
+    #[test]
+    fn test_chaos() {
+        let mut input = PREFACE.to_vec();
+        write_frame(&mut input, FRAME_SETTINGS, 0, 0, &[]).unwrap();
+        let flags = FLAG_END_HEADERS | FLAG_END_STREAM;
+        let paths = [
+            "/Cargo.toml?chaos=reset",
+            "/Cargo.toml?chaos=truncate;length=+10",
+            "/Cargo.toml?chaos=stall=10",
+        ];
+        for (index, path) in paths.iter().enumerate() {
+            let stream_id = 2 * index as u32 + 1;
+            write_frame(
+                &mut input,
+                FRAME_HEADERS,
+                flags,
+                stream_id,
+                &request_headers(path),
+            )
+            .unwrap();
+        }
+
+        let mut connection = MockConnection::new(&input);
+        let mut context = ServerContext::new(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")),
+            OsStr::new("http-mini"),
+        );
+        context.chaos.enabled = true;
+        handle_connection(&mut connection, &context, "");
+
+        let contents = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml")).unwrap();
+        let frames = split_frames(connection.output());
+        let stream = |stream_id: u32| {
+            let data: Vec<u8> = frames
+                .iter()
+                .filter(|frame| frame.0 == FRAME_DATA && frame.2 == stream_id)
+                .flat_map(|frame| frame.3.clone())
+                .collect();
+            let end_stream = frames.iter().any(|frame| {
+                frame.0 == FRAME_DATA && frame.2 == stream_id && frame.1 & FLAG_END_STREAM != 0
+            });
+            let reset = frames
+                .iter()
+                .find(|frame| frame.0 == FRAME_RST_STREAM && frame.2 == stream_id)
+                .map(|frame| u32::from_be_bytes([frame.3[0], frame.3[1], frame.3[2], frame.3[3]]));
+            (data, end_stream, reset)
+        };
+        let half = &contents[..contents.len() / 2];
+        assert_eq!(stream(1), (half.to_vec(), false, Some(ERROR_INTERNAL)));
+        assert_eq!(stream(3), (half.to_vec(), false, Some(ERROR_NO_ERROR)));
+        assert_eq!(stream(5), (contents.clone(), true, None));
+
+        let headers = response_headers(connection.output());
+        let length = (contents.len() + 10).to_string();
+        assert!(headers[1]
+            .1
+            .contains(&("content-length".to_string(), length)));
+    }
+}
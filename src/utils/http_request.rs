@@ -1,11 +1,34 @@
 use crate::traits::stream_trait::StreamTrait;
 use std::fmt::Display;
-use std::io::{BufRead, BufReader};
-use std::net::TcpStream;
+use std::io::{BufRead, BufReader, ErrorKind, Read};
 
 /// # Allowed request methods
 const REQUEST_METHODS: [&str; 6] = ["GET", "POST", "PUT", "DELETE", "HEAD", "OPTIONS"];
 
+/// # Size limits of a request
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RequestLimits {
+    /// Request line length, in bytes
+    pub max_request_line: usize,
+    /// Number of header fields
+    pub max_headers: usize,
+    /// Total length of the header lines, in bytes
+    pub max_header_size: usize,
+    /// Announced `Content-Length`, in bytes
+    pub max_body_size: Option<u64>,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        RequestLimits {
+            max_request_line: 8192,
+            max_headers: 100,
+            max_header_size: 65536,
+            max_body_size: None,
+        }
+    }
+}
+
 /// # Http request parse errors
 #[derive(Debug, PartialEq)]
 pub enum ParseHttpRequestError {
@@ -15,6 +38,25 @@ pub enum ParseHttpRequestError {
     NoPath,
     NoProtocol,
     UnknownProtocol,
+    InvalidHeader,
+    RequestLineTooLong,
+    TooManyHeaders,
+    HeadersTooLarge,
+    BodyTooLarge,
+    Timeout,
+}
+
+impl ParseHttpRequestError {
+    /// # Status code of the response to the request
+    pub fn status(&self) -> u16 {
+        match self {
+            ParseHttpRequestError::RequestLineTooLong => 414,
+            ParseHttpRequestError::TooManyHeaders | ParseHttpRequestError::HeadersTooLarge => 431,
+            ParseHttpRequestError::BodyTooLarge => 413,
+            ParseHttpRequestError::Timeout => 408,
+            _ => 400,
+        }
+    }
 }
 
 impl Display for ParseHttpRequestError {
@@ -29,6 +71,12 @@ impl Display for ParseHttpRequestError {
                 ParseHttpRequestError::NoPath => "Missing request path",
                 ParseHttpRequestError::NoProtocol => "Missing request protocol",
                 ParseHttpRequestError::UnknownProtocol => "Unknown request protocol",
+                ParseHttpRequestError::InvalidHeader => "Invalid header line",
+                ParseHttpRequestError::RequestLineTooLong => "Request line too long",
+                ParseHttpRequestError::TooManyHeaders => "Too many header fields",
+                ParseHttpRequestError::HeadersTooLarge => "Header fields too large",
+                ParseHttpRequestError::BodyTooLarge => "Request body too large",
+                ParseHttpRequestError::Timeout => "Request not received in time",
             }
         )
     }
@@ -43,54 +91,155 @@ pub struct HttpRequest {
     pub body: Option<String>,
 }
 
-impl StreamTrait for TcpStream {
-    /// # Stream parser
-    fn parse(&self) -> Result<HttpRequest, ParseHttpRequestError> {
-        let buf_reader = BufReader::new(self);
-        let mut lines_iterator = buf_reader.lines();
-
+impl HttpRequest {
+    /// # Request from already separated parts, validated like a parsed request line
+    pub fn new(
+        method: &str,
+        path: &str,
+        protocol: &str,
+        headers: Vec<(String, String)>,
+    ) -> Result<HttpRequest, ParseHttpRequestError> {
         let mut request = HttpRequest {
             method: None,
             protocol: None,
             path: None,
-            headers: vec![],
+            headers,
             body: None,
         };
+        init_request(&mut request, &[method, path, protocol])?;
 
-        let mut first_line_vector: Vec<&str>;
-        let mut has_first_line = false;
-        let mut request_line;
-        loop {
-            request_line = lines_iterator.next();
-            if request_line.is_none() {
-                break;
-            }
+        Ok(request)
+    }
 
-            let line_content = request_line.unwrap().unwrap();
-            if line_content.is_empty() {
-                break;
-            }
+    /// # Value of the first header named `name`, case-insensitive
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
 
-            if has_first_line {
-                // headers
-                let line_content_iterator = line_content.splitn(2, ": ").collect::<Vec<&str>>();
-                request.headers.push((
-                    line_content_iterator[0].to_string(),
-                    line_content_iterator[1].to_string(),
-                ));
-            } else {
-                first_line_vector = line_content.splitn(3, ' ').collect::<Vec<&str>>();
-                let init_result = init_request(&mut request, &first_line_vector);
-                if init_result.is_err() {
-                    return Err(init_result.err().unwrap());
-                }
+impl<T: Read> StreamTrait for T {
+    /// # Stream parser
+    fn parse(&mut self) -> Result<HttpRequest, ParseHttpRequestError> {
+        parse_request(self, &RequestLimits::default())
+    }
 
-                has_first_line = true;
+    /// # Stream parser enforcing size limits
+    fn parse_with_limits(
+        &mut self,
+        limits: &RequestLimits,
+    ) -> Result<HttpRequest, ParseHttpRequestError> {
+        parse_request(self, limits)
+    }
+}
+
+/// # Parse a request from any reader
+fn parse_request<R: Read>(
+    reader: R,
+    limits: &RequestLimits,
+) -> Result<HttpRequest, ParseHttpRequestError> {
+    let mut buf_reader = BufReader::new(reader);
+
+    let mut request = HttpRequest {
+        method: None,
+        protocol: None,
+        path: None,
+        headers: vec![],
+        body: None,
+    };
+
+    let mut has_first_line = false;
+    let mut header_size = 0;
+    loop {
+        let line_content = if has_first_line {
+            read_line(
+                &mut buf_reader,
+                limits.max_header_size - header_size,
+                ParseHttpRequestError::HeadersTooLarge,
+            )?
+        } else {
+            read_line(
+                &mut buf_reader,
+                limits.max_request_line,
+                ParseHttpRequestError::RequestLineTooLong,
+            )?
+        };
+        let line_content = match line_content {
+            Some(line_content) if !line_content.is_empty() => line_content,
+            _ => break,
+        };
+
+        if has_first_line {
+            // headers
+            header_size += line_content.len();
+            if request.headers.len() >= limits.max_headers {
+                return Err(ParseHttpRequestError::TooManyHeaders);
+            }
+            match line_content.split_once(':') {
+                Some((name, value)) if !name.is_empty() && !name.contains(char::is_whitespace) => {
+                    request
+                        .headers
+                        .push((name.to_string(), value.trim().to_string()));
+                }
+                _ => return Err(ParseHttpRequestError::InvalidHeader),
             }
+        } else {
+            let first_line_vector = line_content.splitn(3, ' ').collect::<Vec<&str>>();
+            init_request(&mut request, &first_line_vector)?;
+
+            has_first_line = true;
         }
+    }
 
-        Ok(request)
+    let content_length = request
+        .header("Content-Length")
+        .and_then(|length| length.parse::<u64>().ok());
+    if let (Some(max_body_size), Some(content_length)) = (limits.max_body_size, content_length) {
+        if content_length > max_body_size {
+            return Err(ParseHttpRequestError::BodyTooLarge);
+        }
     }
+
+    Ok(request)
+}
+
+/// # Read a line of at most `max_length` bytes, without its line ending
+///
+/// Returns `None` at the end of the stream and on read errors. Lines over the limit fail with
+/// `too_long`, lines which are not UTF-8 with `InvalidHeader` and reads timing out with
+/// `Timeout`.
+fn read_line<R: BufRead>(
+    reader: &mut R,
+    max_length: usize,
+    too_long: ParseHttpRequestError,
+) -> Result<Option<String>, ParseHttpRequestError> {
+    let mut line = vec![];
+    // room for the line ending
+    let limit = max_length as u64 + 2;
+    match reader.take(limit).read_until(b'\n', &mut line) {
+        Ok(0) => return Ok(None),
+        Ok(_) => {}
+        Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+            return Err(ParseHttpRequestError::Timeout);
+        }
+        Err(_) => return Ok(None),
+    }
+
+    if line.last() == Some(&b'\n') {
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+    }
+    if line.len() > max_length {
+        return Err(too_long);
+    }
+
+    String::from_utf8(line)
+        .map(Option::from)
+        .map_err(|_| ParseHttpRequestError::InvalidHeader)
 }
 
 /// # Process Http request
@@ -129,3 +278,66 @@ fn init_request(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::traits::stream_trait::StreamTrait;
+    use crate::utils::http_request::{ParseHttpRequestError, RequestLimits};
+    use std::io::Cursor;
+
+    const LIMITS: RequestLimits = RequestLimits {
+        max_request_line: 32,
+        max_headers: 2,
+        max_header_size: 48,
+        max_body_size: Option::Some(10),
+    };
+
+    fn parse(input: &str) -> Result<(), ParseHttpRequestError> {
+        Cursor::new(input).parse_with_limits(&LIMITS).map(|_| ())
+    }
+
+    #[test]
+    fn test_parse() {
+        let request = Cursor::new("GET /a HTTP/1.1\r\nHost:a\r\nAccept:  */* \r\n\r\n")
+            .parse()
+            .unwrap();
+        assert_eq!(request.path.as_deref(), Some("/a"));
+        assert_eq!(request.header("host"), Some("a"));
+        assert_eq!(request.header("Accept"), Some("*/*"));
+
+        assert_eq!(
+            parse("GET / HTTP/1.1\r\nHost a\r\n\r\n"),
+            Err(ParseHttpRequestError::InvalidHeader)
+        );
+        assert_eq!(
+            parse("GET / HTTP/1.1\r\nHost : a\r\n\r\n"),
+            Err(ParseHttpRequestError::InvalidHeader)
+        );
+        // a line which is not UTF-8 does not end the headers
+        let request = Cursor::new(b"GET / HTTP/1.1\r\nX-A: \xff\r\nHost: a\r\n\r\n").parse();
+        assert_eq!(request.unwrap_err(), ParseHttpRequestError::InvalidHeader);
+    }
+
+    #[test]
+    fn test_parse_with_limits() {
+        assert!(parse("GET /0123456789012345 HTTP/1.1\r\nHost: a\r\n\r\n").is_ok());
+        let error = parse("GET /0123456789012345678 HTTP/1.1\r\n\r\n").unwrap_err();
+        assert_eq!(error, ParseHttpRequestError::RequestLineTooLong);
+        assert_eq!(error.status(), 414);
+        assert_eq!(
+            parse("GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n"),
+            Err(ParseHttpRequestError::TooManyHeaders)
+        );
+        assert_eq!(
+            parse(
+                "GET / HTTP/1.1\r\nA: 0123456789012345678901234\r\nB: 01234567890123456789\r\n\r\n"
+            ),
+            Err(ParseHttpRequestError::HeadersTooLarge)
+        );
+        assert_eq!(
+            parse("POST / HTTP/1.1\r\nContent-Length: 11\r\n\r\n"),
+            Err(ParseHttpRequestError::BodyTooLarge)
+        );
+        assert!(parse("POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\n").is_ok());
+    }
+}
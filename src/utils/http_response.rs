@@ -1,22 +1,82 @@
 use std::io::Write;
-use std::net::TcpStream;
 
 const CRLF: &str = "\r\n";
 
 /// # Assembles headers
-fn build_headers(headers: Vec<(String, String)>, content_length: usize) -> String {
+pub fn build_headers(headers: Vec<(String, String)>, content_length: usize) -> String {
     let mut result = String::new();
     for (key, value) in headers {
-        result = format!("{}{}: {}\n", result, key, value);
+        result = format!("{}{}: {}{}", result, key, value, CRLF);
     }
 
-    result = format!("{}{}: {}\n", result, "Content-Length", content_length);
+    result = format!("{}{}: {}{}", result, "Content-Length", content_length, CRLF);
     result
 }
 
+/// # Reason phrase of a status code
+pub fn get_reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        410 => "Gone",
+        413 => "Content Too Large",
+        414 => "URI Too Long",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "",
+    }
+}
+
+/// # Http response
+///
+/// Independent of the protocol version, written by HTTP/1 and HTTP/2 connections alike.
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Option<Vec<(String, String)>>,
+    pub contents: Option<Vec<u8>>,
+}
+
+impl HttpResponse {
+    pub fn new(
+        status: u16,
+        headers: Option<Vec<(String, String)>>,
+        contents: Option<Vec<u8>>,
+    ) -> HttpResponse {
+        HttpResponse {
+            status,
+            headers,
+            contents,
+        }
+    }
+
+    /// # HTTP/1 status line, i.e. `HTTP/1.1 200 OK`
+    pub fn status_line(&self, protocol: &str) -> String {
+        format!(
+            "{} {} {}",
+            protocol,
+            self.status,
+            get_reason_phrase(self.status)
+        )
+    }
+}
+
 /// # Send a response
-pub fn send(
-    mut stream: &TcpStream,
+pub fn send<W: Write>(
+    mut stream: W,
     status_line: &str,
     headers: Option<Vec<(String, String)>>,
     contents_option: Option<Vec<u8>>,
@@ -27,7 +87,9 @@ pub fn send(
 
     let response = format!("{status_line}{CRLF}{headers_content}{CRLF}");
 
-    stream.write_all(response.as_bytes()).unwrap();
-    stream.write_all(&contents).unwrap();
-    stream.flush().unwrap();
+    // the client may be gone already, there is nobody left to report write errors to
+    let _ = stream
+        .write_all(response.as_bytes())
+        .and_then(|_| stream.write_all(&contents))
+        .and_then(|_| stream.flush());
 }
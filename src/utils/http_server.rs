@@ -1,98 +1,928 @@
+use crate::traits::connection::Connection;
 use crate::traits::stream_trait::StreamTrait;
+use crate::utils::access_control::AccessRule;
+use crate::utils::access_log::AccessLogEntry;
+use crate::utils::app::{ListenerParams, Params};
+use crate::utils::base_url::BaseUrl;
+use crate::utils::basic_auth::BasicAuth;
+use crate::utils::cache_control::CachePolicy;
+use crate::utils::chaos::{Chaos, Faults};
+use crate::utils::cidr::Cidr;
+use crate::utils::cors::CorsPolicy;
+use crate::utils::forwarded::Client;
 use crate::utils::fs::get_dir_contents_as_html;
+use crate::utils::http_request::{HttpRequest, ParseHttpRequestError, RequestLimits};
+use crate::utils::http_response::HttpResponse;
+use crate::utils::listener::{Listener, ListenerSocket};
 use crate::utils::mimes::get_mime_type;
-use crate::utils::{fs, http_response};
-use std::ffi::OsStr;
-use std::io::Error;
-use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
-use std::path::Path;
+use crate::utils::mount::{Mount, ServeOptions};
+use crate::utils::rate_limit::RateLimiter;
+use crate::utils::security_headers::SecurityHeaders;
+use crate::utils::signed_url::SignedUrls;
+use crate::utils::site_rules::{Redirect, SiteRules};
+use crate::utils::throttle::{Bandwidth, ThrottledWriter};
+use crate::utils::timeouts::{DeadlineReader, Timeouts};
+use crate::utils::token_auth::TokenAuth;
+use crate::utils::virtual_host::VirtualHost;
+use crate::utils::{
+    access_control, access_log, basic_auth, cache_control, chaos, cidr, cors, date, forwarded, fs,
+    http2, http_response, mount, proxy_protocol, rate_limit, security_headers, signed_url,
+    token_auth, virtual_host,
+};
+use std::ffi::{OsStr, OsString};
+use std::io::{Cursor, Error, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-/// # Setup and start TcpListener
-pub fn run(address: &str, mut port: i32) -> Result<TcpListener, Error> {
-    if !(1..=65535).contains(&port) {
-        println!("Invalid port number {}. Using port 8080", port);
-        port = 8080;
+/// # Shared server state
+///
+/// A single instance is shared by all listeners.
+pub struct ServerContext {
+    pub source_dir: PathBuf,
+    pub executable_name: OsString,
+    /// Write an access log entry for every request
+    pub access_log: bool,
+    /// Name-based virtual hosts, `source_dir` serves requests matching none of them
+    pub virtual_hosts: Vec<VirtualHost>,
+    /// Directories mounted at URL prefixes, for all hosts
+    pub mounts: Vec<Mount>,
+    /// Public URL when served behind a reverse proxy
+    pub base_url: Option<BaseUrl>,
+    /// Listings link to entries with relative URLs
+    pub relative_links: bool,
+    /// Peers whose forwarding headers are trusted
+    pub trusted_proxies: Vec<Cidr>,
+    /// Client address rules, by URL prefix
+    pub access_rules: Vec<AccessRule>,
+    /// Basic authentication, by URL prefix
+    pub basic_auth: Vec<BasicAuth>,
+    /// Bearer tokens, by URL prefix
+    pub token_auth: Vec<TokenAuth>,
+    /// Signed links, by URL prefix
+    pub signed_urls: Vec<SignedUrls>,
+    /// Request rate and connection limits
+    pub rate_limiter: Arc<RateLimiter>,
+    pub timeouts: Timeouts,
+    pub request_limits: RequestLimits,
+    /// Response bandwidth limits
+    pub bandwidth: Bandwidth,
+    /// Fault injection, off by default
+    pub chaos: Chaos,
+    /// Cross-origin resource sharing, by URL prefix
+    pub cors: Vec<CorsPolicy>,
+    /// `_headers` and `_redirects` files of `source_dir`
+    pub site_rules: Option<SiteRules>,
+    /// Security headers, by URL prefix
+    pub security_headers: Vec<SecurityHeaders>,
+    /// `Cache-Control` rules and defaults
+    pub cache: CachePolicy,
+}
+
+impl ServerContext {
+    /// # Context serving `source_dir` with default settings
+    pub fn new(source_dir: PathBuf, executable_name: &OsStr) -> ServerContext {
+        ServerContext {
+            source_dir,
+            executable_name: executable_name.to_os_string(),
+            access_log: false,
+            virtual_hosts: vec![],
+            mounts: vec![],
+            base_url: None,
+            relative_links: false,
+            trusted_proxies: vec![],
+            access_rules: vec![],
+            basic_auth: vec![],
+            token_auth: vec![],
+            signed_urls: vec![],
+            rate_limiter: Arc::new(RateLimiter::default()),
+            timeouts: Timeouts::default(),
+            request_limits: RequestLimits::default(),
+            bandwidth: Bandwidth::default(),
+            chaos: Chaos::default(),
+            cors: vec![],
+            site_rules: None,
+            security_headers: vec![],
+            cache: CachePolicy::default(),
+        }
+    }
+
+    /// # Context serving the command line parameters
+    ///
+    /// The access log and the rate limiter depend on how connections are served, they are left
+    /// to the caller.
+    pub fn from_params(params: Params, executable_name: &OsStr) -> ServerContext {
+        let site_rules = if params.rule_files {
+            Option::from(SiteRules::load(&params.source_dir))
+        } else {
+            None
+        };
+        ServerContext {
+            virtual_hosts: params.virtual_hosts,
+            mounts: params.mounts,
+            base_url: params.base_url,
+            relative_links: params.relative_links,
+            trusted_proxies: params.trusted_proxies,
+            access_rules: params.access_rules,
+            basic_auth: params.basic_auth,
+            token_auth: params.token_auth,
+            signed_urls: params.signed_urls,
+            timeouts: params.timeouts,
+            request_limits: params.request_limits,
+            bandwidth: params.bandwidth,
+            chaos: params.chaos,
+            cors: params.cors,
+            site_rules,
+            security_headers: params.security_headers,
+            cache: params.cache,
+            ..ServerContext::new(params.source_dir, executable_name)
+        }
+    }
+}
+
+/// # Setup and start listeners
+pub fn run(listeners_params: &[ListenerParams]) -> Result<Vec<Listener>, Error> {
+    let mut listeners: Vec<Listener> = vec![];
+    for params in listeners_params {
+        listeners.push(Listener::bind(params)?);
+    }
+
+    Ok(listeners)
+}
+
+/// # Accept connections on all listeners
+///
+/// Every listener is driven by its own thread and every connection is served by its own
+/// thread, as HTTP/2 connections stay open. Returns when all listeners stop.
+// grcov-excl-start
+pub fn serve(listeners: Vec<Listener>, context: Arc<ServerContext>) {
+    let mut handles = vec![];
+    for listener in listeners {
+        let context = Arc::clone(&context);
+        let listener = Arc::new(listener);
+        handles.push(thread::spawn(move || match &listener.socket {
+            ListenerSocket::Tcp(socket) => accept_loop(socket.incoming(), &listener, &context),
+            #[cfg(unix)]
+            ListenerSocket::Unix(socket) => accept_loop(socket.incoming(), &listener, &context),
+        }));
+    }
+
+    for handle in handles {
+        let _ = handle.join();
+    }
+}
+
+/// # Handle incoming connections of a listener
+fn accept_loop<C, I>(incoming: I, listener: &Arc<Listener>, context: &Arc<ServerContext>)
+where
+    C: Connection + Send + 'static,
+    I: Iterator<Item = Result<C, Error>>,
+{
+    for stream in incoming {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                println!("Connection Error: {}", err);
+                continue;
+            }
+        };
+
+        // behind a proxy the peer address is not the client's
+        let peer = stream.peer_addr().map(|addr| addr.ip()).filter(|ip| {
+            listener.params.proxy_protocol.is_none()
+                && !cidr::any_contains(&context.trusted_proxies, *ip)
+        });
+        let Some(slot) = RateLimiter::open_connection(&context.rate_limiter, peer) else {
+            refuse_connection(stream, listener);
+            continue;
+        };
+
+        let listener = Arc::clone(listener);
+        let context = Arc::clone(context);
+        thread::spawn(move || {
+            serve_connection(stream, &listener, &context);
+            drop(slot);
+        });
+    }
+}
+
+/// # Close a connection over the connection limits
+///
+/// Plain HTTP clients are told to retry, TLS clients see the connection closed. The response is
+/// written from the accept loop, a peer not reading it is given up on after a second.
+fn refuse_connection<C: Connection>(stream: C, listener: &Listener) {
+    if listener.params.tls.is_some() || listener.params.proxy_protocol.is_some() {
+        return;
+    }
+    if stream
+        .set_write_timeout(Option::from(Duration::from_secs(1)))
+        .is_err()
+    {
+        return;
+    }
+    let response = too_many_requests(1);
+    http_response::send(
+        stream,
+        response.status_line("HTTP/1.1").as_str(),
+        response.headers,
+        response.contents,
+    );
+}
+
+/// # 429 Too Many Requests response
+fn too_many_requests(retry_after: u64) -> HttpResponse {
+    HttpResponse::new(
+        429,
+        Option::from(vec![("Retry-After".to_string(), retry_after.to_string())]),
+        None,
+    )
+}
+
+/// # Serve an accepted connection of a listener
+fn serve_connection<C: Connection>(stream: C, listener: &Listener, context: &ServerContext) {
+    // bounds the PROXY protocol header and the TLS handshake as well
+    let _ = stream.set_read_timeout(context.timeouts.read);
+    let _ = stream.set_write_timeout(context.timeouts.write);
+    match listener.params.proxy_protocol {
+        Some(mode) => match proxy_protocol::accept(stream, mode) {
+            Ok(stream) => serve_stream(stream, listener, context),
+            Err(err) => println!("PROXY protocol Error: {}", err),
+        },
+        None => serve_stream(stream, listener, context),
+    }
+}
+
+/// # Serve a connection once its client address is known
+fn serve_stream<C: Connection>(stream: C, listener: &Listener, context: &ServerContext) {
+    let link_addr = listener.link_addr();
+
+    #[cfg(feature = "tls")]
+    if let Some(acceptor) = &listener.tls {
+        match acceptor.accept(stream) {
+            Ok(tls_stream) => {
+                if acceptor.is_client_allowed(&tls_stream) {
+                    handle_connection(tls_stream, context, link_addr.as_str());
+                } else {
+                    forbid(tls_stream, context);
+                }
+            }
+            Err(err) => println!("TLS Error: {}", err),
+        }
+        return;
+    }
+
+    match listener.params.redirect_https {
+        Some(https_port) => redirect_to_https(stream, https_port, context, link_addr.as_str()),
+        None => handle_connection(stream, context, link_addr.as_str()),
     }
+}
+// grcov-excl-stop
 
-    let ip: IpAddr = address.parse().unwrap();
-    let addr = SocketAddr::new(ip, port as u16);
-    let result_tcp_listener = TcpListener::bind(addr);
+/// # Refuse a request with 403 Forbidden
+pub fn forbid<C: Connection>(mut stream: C, context: &ServerContext) {
+    // the request is read so that closing the connection does not reset it
+    let (client, request_line) = match stream.parse() {
+        Ok(request) => (
+            get_client(&stream, &request, context),
+            get_request_line(&request),
+        ),
+        Err(_) => (get_peer(&stream), "-".to_string()),
+    };
+    let response = HttpResponse::new(403, None, None);
+    log_request(&stream, context, &client, request_line.as_str(), &response);
+    http_response::send(
+        &mut stream,
+        response.status_line("HTTP/1.1").as_str(),
+        None,
+        None,
+    );
+}
 
-    if result_tcp_listener.is_err() {
-        return Err(result_tcp_listener.err().unwrap());
+/// # Redirect a request to the same location over HTTPS
+///
+/// An HTTPS base URL replaces the host and port, the path already carries its base path.
+/// Behind a trusted proxy, the forwarded host is redirected to on the default port, and requests
+/// the proxy received over HTTPS are served.
+pub fn redirect_to_https<C: Connection>(
+    mut stream: C,
+    https_port: u16,
+    context: &ServerContext,
+    address: &str,
+) {
+    let request = match stream.parse() {
+        Ok(request) => request,
+        Err(err) => {
+            http_response::send(
+                &mut stream,
+                "HTTP/1.1 400 Bad Request",
+                None,
+                Option::from(Vec::from(err.to_string())),
+            );
+            return;
+        }
+    };
+    let client = get_client(&stream, &request, context);
+    if client.scheme.as_deref() == Some("https") {
+        respond(stream, Ok(request), context, address);
+        return;
     }
 
-    let listener = result_tcp_listener?;
+    let public_origin = context
+        .base_url
+        .as_ref()
+        .and_then(|base_url| base_url.origin.as_deref())
+        .filter(|origin| origin.starts_with("https://"));
+    let origin = match (public_origin, &client.host) {
+        (Some(origin), _) => origin.to_string(),
+        (None, Some(host)) => format!("https://{}", host_without_port(host)),
+        (None, None) => format!(
+            "https://{}{}",
+            request
+                .header("Host")
+                .map(host_without_port)
+                .unwrap_or("localhost"),
+            if https_port == 443 {
+                String::new()
+            } else {
+                format!(":{}", https_port)
+            },
+        ),
+    };
+    let location = format!("{}{}", origin, request.path.as_deref().unwrap_or("/"));
+
+    http_response::send(
+        &mut stream,
+        "HTTP/1.1 301 Moved Permanently",
+        Option::from(vec![("Location".to_string(), location)]),
+        None,
+    );
+}
+
+/// # Host header value without the port
+///
+/// IPv6 literals keep their brackets, i.e. `[::1]:8080` results in `[::1]`.
+pub fn host_without_port(host: &str) -> &str {
+    if host.starts_with('[') {
+        return match host.find(']') {
+            Some(end) => &host[..=end],
+            None => host,
+        };
+    }
 
-    Ok(listener)
+    match host.rsplit_once(':') {
+        Some((hostname, _)) => hostname,
+        None => host,
+    }
 }
 
 /// # Main connections handler
-pub fn handle_connection(
-    stream: TcpStream,
-    source_dir: &Path,
-    executable_name: &OsStr,
+///
+/// Serves HTTP/2 when negotiated with ALPN or when the client starts with the HTTP/2
+/// connection preface (prior knowledge), HTTP/1 otherwise.
+pub fn handle_connection<C: Connection>(mut stream: C, context: &ServerContext, address: &str) {
+    if stream.alpn_protocol().as_deref() == Some(http2::ALPN_PROTOCOL) {
+        let _ = stream.set_read_timeout(context.timeouts.idle);
+        http2::serve(stream, context, address, false);
+        return;
+    }
+    let mut reader = DeadlineReader::new(&mut stream, context.timeouts);
+    let prefix = http2::read_preface(&mut reader);
+    if prefix == http2::PREFACE {
+        let _ = stream.set_read_timeout(context.timeouts.idle);
+        http2::serve(stream, context, address, true);
+        return;
+    }
+    // closed or idle before sending anything
+    if prefix.is_empty() {
+        return;
+    }
+
+    let http_request = Cursor::new(prefix)
+        .chain(reader)
+        .parse_with_limits(&context.request_limits);
+    respond(stream, http_request, context, address);
+}
+
+/// # Answer an HTTP/1 request
+fn respond<C: Connection>(
+    mut stream: C,
+    http_request: Result<HttpRequest, ParseHttpRequestError>,
+    context: &ServerContext,
     address: &str,
 ) {
-    let http_request = stream.parse();
-    if http_request.is_err() {
-        http_response::send(
-            &stream,
-            "HTTP/1.1 400 Bad Request",
-            None,
-            Option::from(Vec::from(http_request.err().unwrap().to_string())),
+    let mut faults = Faults::default();
+    let (response, client, request_line, protocol) = match &http_request {
+        Ok(request) => {
+            let mut client = get_client(&stream, request, context);
+            let response;
+            (response, faults) = build_response(request, context, address, &mut client);
+            (
+                response,
+                client,
+                get_request_line(request),
+                match request.protocol.as_deref() {
+                    Some("HTTP/1.0") => "HTTP/1.0",
+                    _ => "HTTP/1.1",
+                },
+            )
+        }
+        Err(err) => (
+            HttpResponse::new(err.status(), None, Option::from(Vec::from(err.to_string()))),
+            get_peer(&stream),
+            "-".to_string(),
+            "HTTP/1.1",
+        ),
+    };
+    log_request(&stream, context, &client, request_line.as_str(), &response);
+
+    let response_rate = match &http_request {
+        Ok(request) => context.bandwidth.response_rate(site_path(context, request)),
+        Err(_) => context.bandwidth.response_rate,
+    };
+    let writer = ThrottledWriter::new(&mut stream, &context.bandwidth, client.addr, response_rate);
+    if faults.has_body_faults() {
+        chaos::send(
+            writer,
+            response.status_line(protocol).as_str(),
+            response.headers,
+            response.contents.unwrap_or_default(),
+            &faults,
         );
+        if faults.reset {
+            stream.abort();
+        }
         return;
     }
+    http_response::send(
+        writer,
+        response.status_line(protocol).as_str(),
+        response.headers,
+        response.contents,
+    );
+}
+
+/// # Client of a connection, before any request
+pub fn get_peer<C: Connection>(stream: &C) -> Client {
+    Client::direct(stream.peer_addr().map(|addr| addr.ip()))
+}
+
+/// # Client of a request, resolved through trusted proxies
+pub fn get_client<C: Connection>(
+    stream: &C,
+    request: &HttpRequest,
+    context: &ServerContext,
+) -> Client {
+    let peer = stream.peer_addr().map(|addr| addr.ip());
+    let mut client = forwarded::resolve(request, peer, &context.trusted_proxies);
+    if client.scheme.is_none() && stream.is_tls() {
+        client.scheme = Option::from("https".to_string());
+    }
+    // the certificate of a trusted proxy does not identify its clients
+    if !peer.is_some_and(|peer| cidr::any_contains(&context.trusted_proxies, peer)) {
+        client.identity = stream.client_identity();
+    }
+
+    client
+}
 
-    let request = http_request.unwrap();
+/// # Request line as written in the access log
+pub fn get_request_line(request: &HttpRequest) -> String {
+    format!(
+        "{} {} {}",
+        request.method.as_deref().unwrap_or("-"),
+        request.path.as_deref().unwrap_or("-"),
+        request.protocol.as_deref().unwrap_or("-")
+    )
+}
 
-    // get file contents
-    let request_path = request.path;
-    if request_path.is_none() {
-        http_response::send(&stream, "HTTP/1.1 400 Bad Request", None, None);
+/// # Write the access log entry of a request, when enabled
+pub fn log_request<C: Connection>(
+    stream: &C,
+    context: &ServerContext,
+    client: &Client,
+    request_line: &str,
+    response: &HttpResponse,
+) {
+    if !context.access_log {
         return;
     }
 
-    let file_path = source_dir.join(request_path.unwrap().trim_start_matches('/'));
+    access_log::log(&AccessLogEntry {
+        client: client.addr,
+        user: client.user.clone().or_else(|| {
+            stream
+                .client_identity()
+                .map(|identity| identity.to_string())
+        }),
+        request_line,
+        status: response.status,
+        bytes: response.contents.as_ref().map_or(0, Vec::len),
+    });
+}
+
+/// # URL path of a request below the base URL, without the query string
+///
+/// Empty for paths outside of the base URL.
+pub fn site_path<'a>(context: &ServerContext, request: &'a HttpRequest) -> &'a str {
+    let url_path = request
+        .path
+        .as_deref()
+        .unwrap_or_default()
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default();
+    match &context.base_url {
+        Some(base_url) => base_url.strip(url_path).unwrap_or_default(),
+        None => url_path,
+    }
+}
+
+/// # Check the credentials required for a URL path
+///
+/// A path protected by several schemes is served once any of them succeeds. A signed link
+/// is checked first, a wrong signature is refused even with other credentials. The user or
+/// token name is set on `client`. Returns whether the path is protected, its responses are
+/// then private to the client.
+fn authorize(
+    request: &HttpRequest,
+    context: &ServerContext,
+    url_path: &str,
+    query: Option<&str>,
+    client: &mut Client,
+) -> Result<bool, HttpResponse> {
+    let basic = basic_auth::select(&context.basic_auth, url_path);
+    let token = token_auth::select(&context.token_auth, url_path);
+    let signed = signed_url::select(&context.signed_urls, url_path);
+    if basic.is_none() && token.is_none() && signed.is_none() {
+        return Ok(false);
+    }
+
+    let mut error = signed_url::SignatureError::Missing;
+    if let Some(signed) = signed {
+        match signed.verify(url_path, query, client.addr, date::now()) {
+            Ok(()) => return Ok(true),
+            Err(signed_url::SignatureError::Missing) => {}
+            Err(err) => error = err,
+        }
+    }
+    if error == signed_url::SignatureError::Missing {
+        if let Some(user) = basic.and_then(|auth| auth.authenticate(request)) {
+            client.user = Option::from(user);
+            return Ok(true);
+        }
+        if let Some(name) = token.and_then(|auth| auth.authenticate(request)) {
+            client.user = Option::from(name);
+            return Ok(true);
+        }
+    }
+
+    let mut challenges = vec![];
+    if let Some(basic) = basic {
+        challenges.push(("WWW-Authenticate".to_string(), basic.challenge()));
+    }
+    if token.is_some() {
+        let challenge = match token_auth::bearer_token(request) {
+            Some(_) => "Bearer error=\"invalid_token\"",
+            None => "Bearer",
+        };
+        challenges.push(("WWW-Authenticate".to_string(), challenge.to_string()));
+    }
+    if challenges.is_empty() || error != signed_url::SignatureError::Missing {
+        return Err(HttpResponse::new(
+            403,
+            None,
+            Option::from(Vec::from(error.message())),
+        ));
+    }
+
+    Err(HttpResponse::new(401, Option::from(challenges), None))
+}
+
+/// # Whether two URL paths are protected by the same authentication rules
+fn same_authentication(context: &ServerContext, path: &str, other: &str) -> bool {
+    fn same<T>(rule: Option<&T>, other: Option<&T>) -> bool {
+        rule.map(|rule| rule as *const T) == other.map(|other| other as *const T)
+    }
+    same(
+        basic_auth::select(&context.basic_auth, path),
+        basic_auth::select(&context.basic_auth, other),
+    ) && same(
+        token_auth::select(&context.token_auth, path),
+        token_auth::select(&context.token_auth, other),
+    ) && same(
+        signed_url::select(&context.signed_urls, path),
+        signed_url::select(&context.signed_urls, other),
+    )
+}
+
+/// # Build the response to a request, with its faults and CORS headers
+///
+/// The body faults are left to the protocol sending the response. A request selecting invalid
+/// faults is answered with 400 Bad Request.
+pub fn build_response(
+    request: &HttpRequest,
+    context: &ServerContext,
+    address: &str,
+    client: &mut Client,
+) -> (HttpResponse, Faults) {
+    let path = site_path(context, request);
+    let (mut response, faults) = match context.chaos.select(request, path) {
+        Ok(faults) => (
+            chaos::apply(get_response(request, context, address, client), &faults),
+            faults,
+        ),
+        Err(err) => (
+            HttpResponse::new(400, None, Option::from(Vec::from(err.to_string()))),
+            Faults::default(),
+        ),
+    };
+    // error responses are readable by scripts as well
+    cors::add_headers(&context.cors, request, path, &mut response);
+
+    (response, faults)
+}
+
+/// # Build the response to a request
+///
+/// `address` is the listener address used in links, replaced by the scheme and host forwarded
+/// by a trusted proxy. The user is set on `client` once authenticated.
+pub fn get_response(
+    request: &HttpRequest,
+    context: &ServerContext,
+    address: &str,
+    client: &mut Client,
+) -> HttpResponse {
+    if let Some(addr) = client.addr {
+        if let Err(wait) = context.rate_limiter.check_request(addr, Instant::now()) {
+            return too_many_requests(rate_limit::retry_after(wait));
+        }
+    }
+
+    // HTTP/1.1 requires exactly one Host header
+    let host_count = request
+        .headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("Host"))
+        .count();
+    if host_count > 1 || (host_count == 0 && request.protocol.as_deref() == Some("HTTP/1.1")) {
+        return HttpResponse::new(
+            400,
+            None,
+            Option::from(Vec::from("Missing or repeated Host header")),
+        );
+    }
+
+    let virtual_host = virtual_host::select(
+        &context.virtual_hosts,
+        client.host(request).map(host_without_port),
+    );
+    let default_options = ServeOptions::default();
+    let (site_root, site_options) = match virtual_host {
+        Some(virtual_host) => (virtual_host.root.as_path(), &virtual_host.options),
+        None => (context.source_dir.as_path(), &default_options),
+    };
+
+    let request_path = match &request.path {
+        Some(request_path) => request_path,
+        None => return HttpResponse::new(400, None, None),
+    };
+    // the query string does not select the file
+    let url_path = request_path.split(['?', '#']).next().unwrap_or_default();
+    let query = request_path
+        .split('#')
+        .next()
+        .and_then(|path| path.split_once('?'))
+        .map(|(_, query)| query);
+    if url_path.split('/').any(|segment| segment == "..") {
+        return HttpResponse::new(400, None, None);
+    }
+    let url_path = match &context.base_url {
+        Some(base_url) => match base_url.strip(url_path) {
+            Some(url_path) => url_path,
+            None => return HttpResponse::new(404, None, None),
+        },
+        None => url_path,
+    };
+
+    if !access_control::is_allowed(
+        &context.access_rules,
+        url_path,
+        client.addr,
+        client.identity.as_ref(),
+    ) {
+        return HttpResponse::new(403, None, None);
+    }
+    // preflight requests carry no credentials
+    if let Some(response) = cors::preflight(&context.cors, request, url_path) {
+        return response;
+    }
+    let mut authenticated = match authorize(request, context, url_path, query, client) {
+        Ok(authenticated) => authenticated,
+        Err(response) => return response,
+    };
+
+    let forwarded_origin = client
+        .host
+        .as_ref()
+        .map(|host| format!("{}://{}", client.scheme.as_deref().unwrap_or("http"), host));
+    let link_prefix = if context.relative_links {
+        None
+    } else {
+        let origin = match (virtual_host, &context.base_url) {
+            // links are relative to the host the client asked for
+            (Some(_), _) => "",
+            (
+                None,
+                Some(BaseUrl {
+                    origin: Some(origin),
+                    ..
+                }),
+            ) => origin.as_str(),
+            (None, _) => forwarded_origin.as_deref().unwrap_or(address),
+        };
+        let base_path = context
+            .base_url
+            .as_ref()
+            .map_or("", |base_url| base_url.path.as_str());
+        Option::from(format!("{}{}", origin, base_path))
+    };
+
+    // rule files belong to the source directory, virtual hosts have their own roots
+    let site_rules = context
+        .site_rules
+        .as_ref()
+        .filter(|_| virtual_host.is_none());
+    let request_url_path = url_path;
+    let mut rewrite_status = None;
+    let rewritten;
+    let url_path = match site_rules {
+        Some(_)
+            if SiteRules::is_rule_file(url_path)
+                && mount::select(&context.mounts, url_path).is_none() =>
+        {
+            return HttpResponse::new(404, None, None);
+        }
+        Some(rules) => match rules.redirect(url_path, query, |path| {
+            let (root, _, relative_path) = resolve(context, site_root, site_options, path);
+            root.join(relative_path.trim_start_matches('/')).exists()
+        }) {
+            Some(Redirect::Location(status, location)) => {
+                let base_path = match &context.base_url {
+                    Some(base_url) if location.starts_with('/') => base_url.path.as_str(),
+                    _ => "",
+                };
+                return HttpResponse::new(
+                    status,
+                    Option::from(vec![(
+                        "Location".to_string(),
+                        format!("{}{}", base_path, location),
+                    )]),
+                    None,
+                );
+            }
+            Some(Redirect::Rewrite(status, path)) => {
+                rewrite_status = Option::from(status);
+                rewritten = path;
+                rewritten.split(['?', '#']).next().unwrap_or_default()
+            }
+            None => url_path,
+        },
+        None => url_path,
+    };
+    // a rewritten path keeps its own protections
+    if rewrite_status.is_some() {
+        if !access_control::is_allowed(
+            &context.access_rules,
+            url_path,
+            client.addr,
+            client.identity.as_ref(),
+        ) {
+            return HttpResponse::new(403, None, None);
+        }
+        if !same_authentication(context, request_url_path, url_path) {
+            match authorize(request, context, url_path, query, client) {
+                Ok(protected) => authenticated |= protected,
+                Err(response) => return response,
+            }
+        }
+    }
+
+    let (root, options, relative_path) = resolve(context, site_root, site_options, url_path);
+
+    let mut listing = false;
+    let mut response = if options.read_only
+        && !matches!(request.method.as_deref(), Some("GET" | "HEAD" | "OPTIONS"))
+    {
+        HttpResponse::new(
+            405,
+            Option::from(vec![(
+                "Allow".to_string(),
+                "GET, HEAD, OPTIONS".to_string(),
+            )]),
+            None,
+        )
+    } else {
+        let file_path = root.join(relative_path.trim_start_matches('/'));
+        listing = file_path.is_dir();
+        get_file_response(
+            context,
+            &file_path,
+            url_path,
+            options.listing,
+            link_prefix.as_deref(),
+        )
+    };
+    response
+        .headers
+        .get_or_insert_with(Vec::new)
+        .extend(options.headers.iter().cloned());
+    if let Some(status) = rewrite_status.filter(|_| response.status == 200) {
+        response.status = status;
+    }
+    if let Some(rules) = site_rules {
+        response
+            .headers
+            .get_or_insert_with(Vec::new)
+            .extend(rules.headers(request_url_path));
+    }
+    security_headers::add_headers(
+        &context.security_headers,
+        request_url_path,
+        client.scheme.as_deref() == Some("https"),
+        listing,
+        &mut response,
+    );
+    cache_control::add_headers(
+        &context.cache,
+        request_url_path,
+        authenticated,
+        &mut response,
+        date::now(),
+    );
+
+    response
+}
+
+/// # Root, options and relative path serving a URL path, from a mount or the site
+fn resolve<'a>(
+    context: &'a ServerContext,
+    site_root: &'a Path,
+    site_options: &'a ServeOptions,
+    url_path: &'a str,
+) -> (&'a Path, &'a ServeOptions, &'a str) {
+    match mount::select(&context.mounts, url_path) {
+        Some((mount, relative_path)) => (mount.root.as_path(), &mount.options, relative_path),
+        None => (site_root, site_options, url_path),
+    }
+}
+
+/// # Serve a file, or the listing of a directory
+///
+/// `url_path` and `link_prefix` are used for the links of listings, see
+/// [`get_dir_contents_as_html`].
+fn get_file_response(
+    context: &ServerContext,
+    file_path: &Path,
+    url_path: &str,
+    listing: bool,
+    link_prefix: Option<&str>,
+) -> HttpResponse {
+    let executable_name = context.executable_name.as_os_str();
 
     // list directory contents with usable links
     if file_path.is_dir() {
-        let dir_contents_as_html = get_dir_contents_as_html(&file_path, source_dir, address);
+        if !listing {
+            return HttpResponse::new(403, None, None);
+        }
+        let dir_contents_as_html = get_dir_contents_as_html(file_path, url_path, link_prefix);
         if dir_contents_as_html.is_err() {
-            http_response::send(&stream, "HTTP/1.1 500 Internal Server Error", None, None);
-            return;
+            return HttpResponse::new(500, None, None);
         }
 
-        http_response::send(
-            &stream,
-            "HTTP/1.1 200 OK",
+        return HttpResponse::new(
+            200,
             Option::from(vec![("Content-Type".to_string(), "text/html".to_string())]),
             Option::from(Vec::from(dir_contents_as_html.ok().unwrap())),
         );
-        return;
     }
 
-    if !fs::validate_path(file_path.as_ref()) {
-        http_response::send(&stream, "HTTP/1.1 404 Not Found", None, None);
-        return;
+    if !fs::validate_path(file_path) {
+        return HttpResponse::new(404, None, None);
     }
 
     let file_contents = fs::get_file_contents(file_path.to_str().unwrap());
     if file_contents.is_err() {
-        http_response::send(
-            &stream,
-            "HTTP/1.1 400 Bad Request",
+        return HttpResponse::new(
+            400,
             None,
             Option::from(Vec::from(file_contents.err().unwrap().to_string())),
         );
-        return;
     }
 
     // Extra protection. Prevent calling own executable i.e. http://localhost:8080/mini-http !!!
     if file_path.file_name().is_none() || file_path.file_name().unwrap() == executable_name {
-        http_response::send(&stream, "HTTP/1.1 400 Bad Request", None, None);
-        return;
+        return HttpResponse::new(400, None, None);
     }
 
     // All OK. Show the file.
@@ -108,11 +938,598 @@ pub fn handle_connection(
         response_headers.push(("Content-Type".to_string(), "text/plain".to_string()));
     }
 
-    let status_line = format!("{} 200 OK", request.protocol.unwrap());
-    http_response::send(
-        &stream,
-        status_line.as_str(),
+    HttpResponse::new(
+        200,
         Option::from(response_headers),
         Option::from(file_contents.unwrap()),
-    );
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::access_control::AccessRule;
+    use crate::utils::base_url::BaseUrl;
+    use crate::utils::basic_auth::BasicAuth;
+    use crate::utils::cache_control::{CacheMode, CacheRule};
+    use crate::utils::chaos::ChaosRule;
+    use crate::utils::cidr::Cidr;
+    use crate::utils::cors::CorsPolicy;
+    use crate::utils::date;
+    use crate::utils::http_server::{handle_connection, redirect_to_https, ServerContext};
+    use crate::utils::mock_connection::MockConnection;
+    use crate::utils::mount::Mount;
+    use crate::utils::rate_limit::{RateLimiter, RequestRate};
+    use crate::utils::security_headers::SecurityHeaders;
+    use crate::utils::signed_url::{signed_path, SignedUrls};
+    use crate::utils::site_rules::SiteRules;
+    use crate::utils::throttle::PathRate;
+    use crate::utils::token_auth::TokenAuth;
+    use crate::utils::virtual_host::VirtualHost;
+    use crate::utils::x509::ClientIdentity;
+    use std::ffi::OsStr;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use std::{env, fs, process};
+
+    fn get_context() -> ServerContext {
+        ServerContext::new(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")),
+            OsStr::new("http-mini"),
+        )
+    }
+
+    fn request_with(context: &ServerContext, input: &str) -> String {
+        let mut connection = MockConnection::new(input.as_bytes());
+        handle_connection(&mut connection, context, "");
+        connection.output_as_string()
+    }
+
+    fn request(input: &str) -> String {
+        request_with(&get_context(), input)
+    }
+
+    #[test]
+    fn test_handle_connection_file() {
+        let response = request("GET /Cargo.toml HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: text/x-toml"));
+        assert!(response.contains("name = \"http-mini\""));
+    }
+
+    #[test]
+    fn test_handle_connection_listing() {
+        let response = request("GET /src HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("<a href=\"/src/lib.rs\""));
+    }
+
+    #[test]
+    fn test_handle_connection_host() {
+        let response = request("GET /Cargo.toml HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        let response = request("GET /Cargo.toml HTTP/1.1\r\nHost: a\r\nHost: b\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        let response = request("GET /Cargo.toml HTTP/1.0\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.0 200 OK\r\n"));
+    }
+
+    #[test]
+    fn test_handle_connection_virtual_host() {
+        let mut context = get_context();
+        context.virtual_hosts =
+            vec![
+                VirtualHost::parse("docs.test=src,listing=off,header=X-Robots-Tag:noindex")
+                    .unwrap(),
+            ];
+
+        let response = request_with(
+            &context,
+            "GET /lib.rs HTTP/1.1\r\nHost: Docs.test:8080\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("X-Robots-Tag: noindex\r\n"));
+        let response = request_with(&context, "GET /utils HTTP/1.1\r\nHost: docs.test\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+        // other hosts are served from the source directory
+        let response = request_with(
+            &context,
+            "GET /Cargo.toml HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(!response.contains("X-Robots-Tag"));
+    }
+
+    #[test]
+    fn test_handle_connection_mount() {
+        let mut context = get_context();
+        context.mounts = vec![Mount::parse("/assets=src/utils,read-only").unwrap()];
+
+        let response = request_with(
+            &context,
+            "GET /assets/glob.rs?v=1 HTTP/1.1\r\nHost: a\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("pub fn matches"));
+        let response = request_with(&context, "GET /assets HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.contains("<a href=\"/assets/glob.rs\""));
+        let response = request_with(&context, "PUT /assets/glob.rs HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+        assert!(response.contains("Allow: GET, HEAD, OPTIONS\r\n"));
+        let response = request_with(
+            &context,
+            "GET /assets/../Cargo.toml HTTP/1.1\r\nHost: a\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    #[test]
+    fn test_handle_connection_base_url() {
+        let mut context = get_context();
+        context.base_url = Option::from(BaseUrl::parse("https://tools.internal/share/").unwrap());
+
+        let response = request_with(&context, "GET /share/src HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.contains("<a href=\"https://tools.internal/share/src/lib.rs\""));
+        let response = request_with(&context, "GET /share HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.contains("<a href=\"https://tools.internal/share/Cargo.toml\""));
+        let response = request_with(&context, "GET /src/lib.rs HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        context.relative_links = true;
+        let response = request_with(&context, "GET /share/src HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.contains("<a href=\"src/lib.rs\""));
+        let response = request_with(&context, "GET /share/src/ HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.contains("<a href=\"lib.rs\""));
+    }
+
+    #[test]
+    fn test_handle_connection_trusted_proxy() {
+        let mut context = get_context();
+        context.trusted_proxies = vec![Cidr::parse("10.0.0.0/8").unwrap()];
+        let input = b"GET /src HTTP/1.1\r\nHost: backend:8080\r\nX-Forwarded-Proto: https\r\nX-Forwarded-Host: share.example\r\n\r\n";
+
+        let mut connection =
+            MockConnection::new(input).with_peer_addr("10.0.0.2:4000".parse().unwrap());
+        handle_connection(&mut connection, &context, "http://backend:8080");
+        assert!(connection
+            .output_as_string()
+            .contains("<a href=\"https://share.example/src/lib.rs\""));
+
+        // served, not redirected, as the proxy received it over HTTPS
+        let mut connection =
+            MockConnection::new(input).with_peer_addr("10.0.0.2:4000".parse().unwrap());
+        redirect_to_https(&mut connection, 8443, &context, "http://backend:8080");
+        assert!(connection
+            .output_as_string()
+            .starts_with("HTTP/1.1 200 OK\r\n"));
+
+        let mut connection =
+            MockConnection::new(input).with_peer_addr("192.0.2.1:4000".parse().unwrap());
+        handle_connection(&mut connection, &context, "http://backend:8080");
+        assert!(connection
+            .output_as_string()
+            .contains("<a href=\"http://backend:8080/src/lib.rs\""));
+    }
+
+    #[test]
+    fn test_handle_connection_access_rules() {
+        let mut context = get_context();
+        context.trusted_proxies = vec![Cidr::parse("10.0.0.0/8").unwrap()];
+        context.access_rules = vec![
+            AccessRule::parse("127.0.0.1,path=/src", true).unwrap(),
+            AccessRule::parse("all,path=/src", false).unwrap(),
+        ];
+        let status = |peer: &str, input: &[u8]| {
+            let mut connection = MockConnection::new(input).with_peer_addr(peer.parse().unwrap());
+            handle_connection(&mut connection, &context, "");
+            connection.output_as_string()[..12].to_string()
+        };
+
+        let input = b"GET /src/lib.rs HTTP/1.1\r\nHost: a\r\n\r\n";
+        assert_eq!(status("[::ffff:127.0.0.1]:4000", input), "HTTP/1.1 200");
+        assert_eq!(status("192.0.2.1:4000", input), "HTTP/1.1 403");
+        assert_eq!(
+            status(
+                "192.0.2.1:4000",
+                b"GET /Cargo.toml HTTP/1.1\r\nHost: a\r\n\r\n"
+            ),
+            "HTTP/1.1 200"
+        );
+        let forwarded =
+            b"GET /src/lib.rs HTTP/1.1\r\nHost: a\r\nX-Forwarded-For: 127.0.0.1\r\n\r\n";
+        assert_eq!(status("10.0.0.2:4000", forwarded), "HTTP/1.1 200");
+        assert_eq!(status("192.0.2.1:4000", forwarded), "HTTP/1.1 403");
+    }
+
+    #[test]
+    fn test_handle_connection_access_rules_certificate() {
+        let mut context = get_context();
+        context.trusted_proxies = vec![Cidr::parse("10.0.0.0/8").unwrap()];
+        context.access_rules = vec![
+            AccessRule::parse("subject=ci-*,path=/src", true).unwrap(),
+            AccessRule::parse("all,path=/src", false).unwrap(),
+        ];
+        let status = |peer: &str, common_name: &str| {
+            let identity = ClientIdentity {
+                common_name: Option::from(common_name.to_string()),
+                alt_names: vec![],
+            };
+            let mut connection =
+                MockConnection::new(b"GET /src/lib.rs HTTP/1.1\r\nHost: a\r\n\r\n")
+                    .with_peer_addr(peer.parse().unwrap())
+                    .with_client_identity(identity);
+            handle_connection(&mut connection, &context, "");
+            connection.output_as_string()[..12].to_string()
+        };
+
+        assert_eq!(status("192.0.2.1:4000", "ci-runner"), "HTTP/1.1 200");
+        assert_eq!(status("192.0.2.1:4000", "alice"), "HTTP/1.1 403");
+        // the certificate of a trusted proxy is not the one of its clients
+        assert_eq!(status("10.0.0.2:4000", "ci-runner"), "HTTP/1.1 403");
+    }
+
+    #[test]
+    fn test_handle_connection_rate_limit() {
+        let mut context = get_context();
+        context.rate_limiter = Arc::new(RateLimiter::new(
+            Option::from(RequestRate::parse("1").unwrap()),
+            None,
+            None,
+        ));
+        let response = |peer: &str| {
+            let mut connection =
+                MockConnection::new(b"GET /Cargo.toml HTTP/1.1\r\nHost: a\r\n\r\n")
+                    .with_peer_addr(peer.parse().unwrap());
+            handle_connection(&mut connection, &context, "");
+            connection.output_as_string()
+        };
+
+        assert!(response("192.0.2.1:4000").starts_with("HTTP/1.1 200 OK\r\n"));
+        let limited = response("192.0.2.1:4001");
+        assert!(limited.starts_with("HTTP/1.1 429 Too Many Requests\r\n"));
+        assert!(limited.contains("Retry-After: 1\r\n"));
+        assert!(response("192.0.2.2:4000").starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+
+    #[test]
+    fn test_handle_connection_throttle() {
+        let mut context = get_context();
+        context.bandwidth.paths = vec![PathRate {
+            prefix: "/Cargo.toml".to_string(),
+            rate: Option::from(4000),
+        }];
+
+        let started = Instant::now();
+        let response = request_with(&context, "GET /Cargo.toml HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert!(response.ends_with(fs::read_to_string("Cargo.toml").unwrap().as_str()));
+
+        let started = Instant::now();
+        request_with(&context, "GET /README.md HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(started.elapsed() < Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_handle_connection_chaos() {
+        let mut context = get_context();
+        let input = "GET /Cargo.toml HTTP/1.1\r\nHost: a\r\nX-Chaos: status=503\r\n\r\n";
+        assert!(request_with(&context, input).starts_with("HTTP/1.1 200 OK"));
+
+        context.chaos.enabled = true;
+        assert!(request_with(&context, input).starts_with("HTTP/1.1 503 Service Unavailable"));
+        let response = request_with(
+            &context,
+            "GET /Cargo.toml?chaos=x HTTP/1.1\r\nHost: a\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request"));
+
+        let contents = fs::read_to_string("Cargo.toml").unwrap();
+        let response = request_with(
+            &context,
+            "GET /Cargo.toml?chaos=truncate;length=+10 HTTP/1.1\r\nHost: a\r\n\r\n",
+        );
+        assert!(response.contains(format!("Content-Length: {}\r\n", contents.len() + 10).as_str()));
+        assert!(response.ends_with(&contents[..contents.len() / 2]));
+
+        context.chaos.rules = vec![ChaosRule::parse("status=502,path=*.md").unwrap()];
+        let response = request_with(&context, "GET /README.md HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 502 Bad Gateway"));
+        let response = request_with(&context, "GET /Cargo.toml HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+    }
+
+    #[test]
+    fn test_handle_connection_cors() {
+        let mut context = get_context();
+        let path = env::temp_dir().join(format!("http-mini-cors-htpasswd-{}", process::id()));
+        fs::write(&path, "alice:{SHA}5en6G6MezRroT3XKqkdPOmY/BfQ=\n").unwrap();
+        context.basic_auth =
+            vec![BasicAuth::parse(format!("{},path=/src", path.display()).as_str()).unwrap()];
+        context.cors = vec![CorsPolicy::parse("http://localhost:3000,credentials").unwrap()];
+
+        let response = request_with(
+            &context,
+            "OPTIONS /src/lib.rs HTTP/1.1\r\nHost: a\r\nOrigin: http://localhost:3000\r\n\
+            Access-Control-Request-Method: GET\r\nAccess-Control-Request-Headers: authorization\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("Access-Control-Allow-Origin: http://localhost:3000\r\n"));
+        assert!(response.contains("Access-Control-Allow-Headers: authorization\r\n"));
+        assert!(response.ends_with("Content-Length: 0\r\n\r\n"));
+
+        let response = request_with(
+            &context,
+            "GET /src/lib.rs HTTP/1.1\r\nHost: a\r\nOrigin: http://localhost:3000\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 401 Unauthorized"));
+        assert!(response.contains("Access-Control-Allow-Credentials: true\r\n"));
+
+        let response = request_with(
+            &context,
+            "GET /Cargo.toml HTTP/1.1\r\nHost: a\r\nOrigin: http://localhost:4000\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(!response.contains("Access-Control-Allow-Origin"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_handle_connection_site_rules() {
+        let root = env::temp_dir().join(format!("http-mini-server-rules-{}", process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("app.html"), "app").unwrap();
+        fs::write(root.join("old.html"), "old").unwrap();
+        fs::write(root.join("404.html"), "not found").unwrap();
+        fs::write(
+            root.join("_redirects"),
+            "/old.html /new.html\n/docs/* /manual/:splat 302!\n/app/* /app.html 200\n/* /404.html 404\n",
+        )
+        .unwrap();
+        fs::write(root.join("_headers"), "/app/*\n  X-Frame-Options: DENY\n").unwrap();
+        let mut context = ServerContext::new(root.clone(), OsStr::new("http-mini"));
+        context.site_rules = Option::from(SiteRules::load(&root));
+        context.base_url = Option::from(BaseUrl::parse("/site").unwrap());
+
+        let response = request_with(&context, "GET /site/old.html HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        let response = request_with(&context, "GET /site/docs/a?b=1 HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 302 Found"));
+        assert!(response.contains("Location: /site/manual/a?b=1\r\n"));
+        let response = request_with(
+            &context,
+            "GET /site/app/settings HTTP/1.1\r\nHost: a\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("X-Frame-Options: DENY\r\n"));
+        assert!(response.ends_with("\r\n\r\napp"));
+        let response = request_with(&context, "GET /site/other HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 404 Not Found"));
+        assert!(response.ends_with("not found"));
+        let response = request_with(&context, "GET /site/_redirects HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 404 Not Found"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_handle_connection_security_headers() {
+        let mut context = get_context();
+        context.trusted_proxies = vec![Cidr::parse("10.0.0.0/8").unwrap()];
+        context.security_headers = vec![
+            SecurityHeaders::parse("strict").unwrap(),
+            SecurityHeaders::parse("basic,csp=default-src *,path=/src").unwrap(),
+        ];
+        let request = |input: &[u8]| {
+            let mut connection =
+                MockConnection::new(input).with_peer_addr("10.0.0.2:4000".parse().unwrap());
+            handle_connection(&mut connection, &context, "");
+            connection.output_as_string()
+        };
+
+        let response = request(b"GET /Cargo.toml HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.contains("X-Content-Type-Options: nosniff\r\n"));
+        assert!(response.contains("X-Frame-Options: DENY\r\n"));
+        assert!(!response.contains("Strict-Transport-Security"));
+        let response =
+            request(b"GET /src/lib.rs HTTP/1.1\r\nHost: a\r\nX-Forwarded-Proto: https\r\n\r\n");
+        assert!(response.contains("Strict-Transport-Security: max-age=31536000\r\n"));
+        assert!(
+            response.contains("Content-Security-Policy: default-src *; frame-ancestors 'self'\r\n")
+        );
+        let response = request(b"GET /src HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response
+            .contains("Content-Security-Policy: default-src 'none'; frame-ancestors 'none'\r\n"));
+    }
+
+    #[test]
+    fn test_handle_connection_cache() {
+        let mut context = get_context();
+        context.cache.mode = Option::from(CacheMode::Production);
+        context.cache.rules = vec![CacheRule::parse("no-store,path=/src/*").unwrap()];
+
+        let response = request_with(&context, "GET /Cargo.toml HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.contains("Cache-Control: public, max-age=3600\r\n"));
+        assert!(response.contains("Expires: "));
+        let response = request_with(&context, "GET /src/lib.rs HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.contains("Cache-Control: no-store\r\n"));
+        assert!(response.contains("Expires: Thu, 01 Jan 1970 00:00:00 GMT\r\n"));
+        // listings are HTML
+        let response = request_with(&context, "GET /src HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.contains("Cache-Control: no-cache\r\n"));
+        let response = request_with(&context, "GET /missing HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(!response.contains("Cache-Control"));
+    }
+
+    #[test]
+    fn test_handle_connection_basic_auth() {
+        let path = env::temp_dir().join(format!("http-mini-server-htpasswd-{}", process::id()));
+        fs::write(&path, "alice:{SHA}5en6G6MezRroT3XKqkdPOmY/BfQ=\n").unwrap();
+        let mut context = get_context();
+        context.basic_auth =
+            vec![
+                BasicAuth::parse(format!("{},realm=Sources,path=/src", path.display()).as_str())
+                    .unwrap(),
+            ];
+        context.cache.mode = Option::from(CacheMode::Production);
+
+        let response = request_with(&context, "GET /src/lib.rs HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+        assert!(
+            response.contains("WWW-Authenticate: Basic realm=\"Sources\", charset=\"UTF-8\"\r\n")
+        );
+        // alice:wrong
+        let response = request_with(
+            &context,
+            "GET /src/lib.rs HTTP/1.1\r\nHost: a\r\nAuthorization: Basic YWxpY2U6d3Jvbmc=\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+        // alice:secret
+        let response = request_with(
+            &context,
+            "GET /src/lib.rs HTTP/1.1\r\nHost: a\r\nAuthorization: Basic YWxpY2U6c2VjcmV0\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Cache-Control: private, max-age=3600\r\n"));
+        let response = request_with(&context, "GET /Cargo.toml HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Cache-Control: public, max-age=3600\r\n"));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_handle_connection_rewrite_protected() {
+        let root = env::temp_dir().join(format!("http-mini-server-rewrite-{}", process::id()));
+        fs::create_dir_all(root.join("private")).unwrap();
+        fs::write(root.join("private/secret.txt"), "secret").unwrap();
+        fs::write(root.join("_redirects"), "/public/* /private/:splat 200\n").unwrap();
+        let htpasswd = root.join("htpasswd");
+        fs::write(&htpasswd, "alice:{SHA}5en6G6MezRroT3XKqkdPOmY/BfQ=\n").unwrap();
+        let mut context = ServerContext::new(root.clone(), OsStr::new("http-mini"));
+        context.site_rules = Option::from(SiteRules::load(&root));
+        context.basic_auth =
+            vec![
+                BasicAuth::parse(format!("{},path=/private", htpasswd.display()).as_str()).unwrap(),
+            ];
+        context.access_rules = vec![AccessRule::parse("all,path=/private", false).unwrap()];
+
+        let response = request_with(
+            &context,
+            "GET /public/secret.txt HTTP/1.1\r\nHost: a\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+        context.access_rules = vec![];
+        let response = request_with(
+            &context,
+            "GET /public/secret.txt HTTP/1.1\r\nHost: a\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+        // alice:secret
+        let response = request_with(
+            &context,
+            "GET /public/secret.txt HTTP/1.1\r\nHost: a\r\nAuthorization: Basic YWxpY2U6c2VjcmV0\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("secret"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_handle_connection_token_and_signed_url() {
+        env::set_var("HTTP_MINI_SERVER_TEST_TOKEN", "abc123 ci");
+        env::set_var("HTTP_MINI_SERVER_TEST_SECRET", "secret");
+        let mut context = get_context();
+        context.token_auth = vec![TokenAuth::parse("env:HTTP_MINI_SERVER_TEST_TOKEN").unwrap()];
+        context.signed_urls =
+            vec![SignedUrls::parse("env:HTTP_MINI_SERVER_TEST_SECRET,path=/src").unwrap()];
+
+        let response = request_with(&context, "GET /Cargo.toml HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+        assert!(response.contains("WWW-Authenticate: Bearer\r\n"));
+        let response = request_with(
+            &context,
+            "GET /Cargo.toml HTTP/1.1\r\nHost: a\r\nAuthorization: Bearer abc\r\n\r\n",
+        );
+        assert!(response.contains("WWW-Authenticate: Bearer error=\"invalid_token\"\r\n"));
+        let response = request_with(
+            &context,
+            "GET /src/lib.rs HTTP/1.1\r\nHost: a\r\nAuthorization: Bearer abc123\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+        let link = signed_path(b"secret", "/src/lib.rs", date::now() + 60, None);
+        let response = request_with(
+            &context,
+            format!("GET {} HTTP/1.1\r\nHost: a\r\n\r\n", link).as_str(),
+        );
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        let link = signed_path(b"secret", "/src/lib.rs", date::now() - 60, None);
+        let response = request_with(
+            &context,
+            format!(
+                "GET {} HTTP/1.1\r\nHost: a\r\nAuthorization: Bearer abc123\r\n\r\n",
+                link
+            )
+            .as_str(),
+        );
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+        assert!(response.ends_with("Expired URL"));
+        // a link signed for another path
+        let response = request_with(
+            &context,
+            format!(
+                "GET /src/main.rs?{} HTTP/1.1\r\nHost: a\r\n\r\n",
+                &link[12..]
+            )
+            .as_str(),
+        );
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+    }
+
+    #[test]
+    fn test_redirect_to_https() {
+        let mut connection = MockConnection::new(b"GET /a?b HTTP/1.1\r\nHost: [::1]:8080\r\n\r\n");
+        redirect_to_https(&mut connection, 8443, &get_context(), "");
+        let response = connection.output_as_string();
+        assert!(response.starts_with("HTTP/1.1 301 Moved Permanently\r\n"));
+        assert!(response.contains("Location: https://[::1]:8443/a?b"));
+
+        let mut connection = MockConnection::new(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n");
+        redirect_to_https(&mut connection, 443, &get_context(), "");
+        assert!(connection
+            .output_as_string()
+            .contains("Location: https://example.com/\r\n"));
+
+        let mut context = get_context();
+        context.base_url = Option::from(BaseUrl::parse("https://tools.internal/share").unwrap());
+        let mut connection =
+            MockConnection::new(b"GET /share/a HTTP/1.1\r\nHost: localhost:8080\r\n\r\n");
+        redirect_to_https(&mut connection, 8443, &context, "");
+        assert!(connection
+            .output_as_string()
+            .contains("Location: https://tools.internal/share/a\r\n"));
+    }
+
+    #[test]
+    fn test_handle_connection_limits() {
+        let mut context = get_context();
+        context.request_limits.max_request_line = 32;
+        context.request_limits.max_headers = 1;
+        let response = request_with(
+            &context,
+            "GET /0123456789012345678901234567 HTTP/1.1\r\nHost: a\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 414 URI Too Long\r\n"));
+        let response = request_with(&context, "GET / HTTP/1.1\r\nHost: a\r\nA: b\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+        let response = request_with(&context, "GET / HTTP/1.1\r\nHost\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert_eq!(request_with(&context, ""), "");
+    }
+
+    #[test]
+    fn test_handle_connection_bad_request() {
+        let response = request("BREW /coffee HTCPCP/1.0\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(response.ends_with("Unknown request method"));
+    }
 }
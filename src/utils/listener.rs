@@ -0,0 +1,165 @@
+use crate::utils::app::{BindTarget, ListenerParams};
+#[cfg(feature = "tls")]
+use crate::utils::tls::TlsAcceptor;
+use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, TcpListener};
+#[cfg(unix)]
+use std::os::fd::{FromRawFd, IntoRawFd};
+#[cfg(unix)]
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::Path;
+
+/// # Listening socket
+pub enum ListenerSocket {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+/// # Bound listener
+pub struct Listener {
+    pub params: ListenerParams,
+    pub socket: ListenerSocket,
+    #[cfg(feature = "tls")]
+    pub tls: Option<TlsAcceptor>,
+}
+
+impl Listener {
+    /// # Bind a listener
+    pub fn bind(params: &ListenerParams) -> Result<Listener, Error> {
+        let socket = match &params.target {
+            BindTarget::Tcp(addr) => ListenerSocket::Tcp(TcpListener::bind(addr)?),
+            #[cfg(unix)]
+            BindTarget::Unix(path) => ListenerSocket::Unix(bind_unix(path, params.unix_mode)?),
+            #[cfg(unix)]
+            BindTarget::Inherited(fd) => from_inherited_fd(*fd)?,
+            #[cfg(not(unix))]
+            BindTarget::Unix(_) | BindTarget::Inherited(_) => {
+                return Err(Error::new(
+                    ErrorKind::Unsupported,
+                    "Unix sockets are not supported on this platform",
+                ));
+            }
+        };
+
+        #[cfg(feature = "tls")]
+        let tls = match &params.tls {
+            Some(tls_params) => Option::from(TlsAcceptor::new(tls_params)?),
+            None => None,
+        };
+
+        Ok(Listener {
+            params: params.clone(),
+            socket,
+            #[cfg(feature = "tls")]
+            tls,
+        })
+    }
+
+    /// # URL of the listener
+    ///
+    /// Unspecified addresses (`::`, `0.0.0.0`) are displayed as `localhost`.
+    pub fn url(&self) -> String {
+        match &self.socket {
+            ListenerSocket::Tcp(socket) => {
+                let addr = match (socket.local_addr(), &self.params.target) {
+                    (Ok(addr), _) => addr,
+                    (Err(_), BindTarget::Tcp(addr)) => *addr,
+                    (Err(_), _) => return String::new(),
+                };
+                let host = match addr.ip() {
+                    ip if ip.is_unspecified() => "localhost".to_string(),
+                    IpAddr::V6(ip) => format!("[{}]", ip),
+                    IpAddr::V4(ip) => ip.to_string(),
+                };
+
+                let scheme = if self.params.tls.is_some() {
+                    "https"
+                } else {
+                    "http"
+                };
+
+                format!("{}://{}:{}", scheme, host, addr.port())
+            }
+            #[cfg(unix)]
+            ListenerSocket::Unix(socket) => match socket.local_addr() {
+                Ok(addr) if addr.as_pathname().is_some() => {
+                    format!("unix:{}", addr.as_pathname().unwrap().display())
+                }
+                _ => match &self.params.target {
+                    BindTarget::Unix(path) => format!("unix:{}", path.display()),
+                    BindTarget::Inherited(fd) => format!("unix:fd={}", fd),
+                    BindTarget::Tcp(_) => String::new(),
+                },
+            },
+        }
+    }
+
+    /// # Prefix of the links generated for this listener
+    ///
+    /// Unix sockets have no meaningful origin so links are relative to the server root.
+    pub fn link_addr(&self) -> String {
+        match &self.socket {
+            ListenerSocket::Tcp(_) => self.url(),
+            #[cfg(unix)]
+            ListenerSocket::Unix(_) => String::new(),
+        }
+    }
+}
+
+/// # Take over a listening socket passed by the service manager
+///
+/// The socket family is detected from its local address.
+#[cfg(unix)]
+fn from_inherited_fd(fd: i32) -> Result<ListenerSocket, Error> {
+    // SAFETY: the service manager hands over ownership of the listening sockets, which are
+    // taken over only once
+    let tcp_listener = unsafe { TcpListener::from_raw_fd(fd) };
+    if tcp_listener.local_addr().is_ok() {
+        return Ok(ListenerSocket::Tcp(tcp_listener));
+    }
+
+    // SAFETY: ownership is moved from the TcpListener above
+    let unix_listener = unsafe { UnixListener::from_raw_fd(tcp_listener.into_raw_fd()) };
+    if unix_listener.local_addr().is_ok() {
+        return Ok(ListenerSocket::Unix(unix_listener));
+    }
+
+    Err(Error::new(
+        ErrorKind::InvalidInput,
+        format!("inherited file descriptor {} is not a listening socket", fd),
+    ))
+}
+
+/// # Bind a Unix socket
+///
+/// A stale socket file left behind by a previous instance is removed. A socket file
+/// which still accepts connections, or any other kind of file, is never removed.
+#[cfg(unix)]
+fn bind_unix(path: &Path, mode: Option<u32>) -> Result<UnixListener, Error> {
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ));
+        }
+        if UnixStream::connect(path).is_ok() {
+            return Err(Error::new(
+                ErrorKind::AddrInUse,
+                format!("{} is in use", path.display()),
+            ));
+        }
+        std::fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    if let Some(mode) = mode {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    }
+
+    Ok(listener)
+}
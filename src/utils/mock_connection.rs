@@ -0,0 +1,87 @@
+use crate::traits::connection::Connection;
+use crate::utils::x509::ClientIdentity;
+use std::io::{Cursor, Read, Write};
+use std::net::SocketAddr;
+
+/// # In-memory connection
+///
+/// Reads the request from a buffer and collects the response, which allows testing
+/// handlers end-to-end without sockets.
+///
+/// ```
+/// use http_mini_lib::utils::http_server::{handle_connection, ServerContext};
+/// use http_mini_lib::utils::mock_connection::MockConnection;
+/// use std::ffi::OsStr;
+/// use std::path::PathBuf;
+///
+/// let context = ServerContext::new(PathBuf::from(env!("CARGO_MANIFEST_DIR")), OsStr::new("http-mini"));
+/// let mut connection = MockConnection::new(b"GET /Cargo.toml HTTP/1.1\r\nHost: localhost\r\n\r\n");
+/// handle_connection(&mut connection, &context, "");
+///
+/// assert!(connection.output_as_string().starts_with("HTTP/1.1 200 OK"));
+/// ```
+pub struct MockConnection {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>,
+    peer_addr: Option<SocketAddr>,
+    client_identity: Option<ClientIdentity>,
+}
+
+impl MockConnection {
+    pub fn new(input: &[u8]) -> MockConnection {
+        MockConnection {
+            input: Cursor::new(input.to_vec()),
+            output: vec![],
+            peer_addr: None,
+            client_identity: None,
+        }
+    }
+
+    /// # Set the address reported for the remote peer
+    pub fn with_peer_addr(mut self, peer_addr: SocketAddr) -> MockConnection {
+        self.peer_addr = Option::from(peer_addr);
+        self
+    }
+
+    /// # Set the identity of the client certificate
+    pub fn with_client_identity(mut self, client_identity: ClientIdentity) -> MockConnection {
+        self.client_identity = Option::from(client_identity);
+        self
+    }
+
+    /// # Bytes written to the connection
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// # Bytes written to the connection, lossily converted to text
+    pub fn output_as_string(&self) -> String {
+        String::from_utf8_lossy(&self.output).to_string()
+    }
+}
+
+impl Read for MockConnection {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for MockConnection {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Connection for MockConnection {
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    fn client_identity(&self) -> Option<ClientIdentity> {
+        self.client_identity.clone()
+    }
+}
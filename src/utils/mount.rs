@@ -0,0 +1,172 @@
+use crate::errors::invalid_argument::InvalidArgumentError;
+use std::fs;
+use std::path::PathBuf;
+
+/// # Settings of a served directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServeOptions {
+    /// List directory contents
+    pub listing: bool,
+    /// Refuse methods other than GET, HEAD and OPTIONS
+    pub read_only: bool,
+    /// Headers added to every response
+    pub headers: Vec<(String, String)>,
+}
+
+impl Default for ServeOptions {
+    fn default() -> Self {
+        ServeOptions {
+            listing: true,
+            read_only: false,
+            headers: vec![],
+        }
+    }
+}
+
+impl ServeOptions {
+    /// # Parse a `NAME[=VALUE]` option shared by virtual hosts and mounts
+    ///
+    /// Returns `Ok(false)` for options which are not serve options.
+    ///
+    /// Options:
+    /// * `listing=on|off`: list directory contents, on by default
+    /// * `read-only`: refuse methods other than GET, HEAD and OPTIONS with 405
+    /// * `header=NAME:VALUE`: add a response header, may be repeated, the value cannot
+    ///   contain commas
+    pub fn parse_option(&mut self, name: &str, value: &str) -> Result<bool, &'static str> {
+        match name {
+            "listing" => match value {
+                "on" => self.listing = true,
+                "off" => self.listing = false,
+                _ => return Err("listing must be on or off"),
+            },
+            "read-only" => self.read_only = true,
+            "header" => match value.split_once(':') {
+                Some((name, value)) if !name.trim().is_empty() => self
+                    .headers
+                    .push((name.trim().to_string(), value.trim().to_string())),
+                _ => return Err("header expects NAME:VALUE"),
+            },
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+}
+
+/// # Directory mounted at a URL prefix
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mount {
+    /// URL path prefix without a trailing slash, i.e. `/assets`
+    pub prefix: String,
+    pub root: PathBuf,
+    pub options: ServeOptions,
+}
+
+impl Mount {
+    /// # Parse a mount specification
+    ///
+    /// Format: `PREFIX=DIRECTORY[,option[=value]...]`, i.e. `/assets=target/site,read-only`.
+    /// See [`ServeOptions::parse_option`] for the options.
+    pub fn parse(spec: &str) -> Result<Mount, InvalidArgumentError> {
+        let mut parts = spec.split(',');
+        let (prefix, root) = match parts.next().unwrap_or_default().split_once('=') {
+            Some((prefix, root)) if prefix.starts_with('/') && !root.is_empty() => (prefix, root),
+            _ => {
+                return Err(InvalidArgumentError::new(
+                    spec,
+                    "expected /PREFIX=DIRECTORY",
+                ));
+            }
+        };
+        let root = fs::canonicalize(root)
+            .map_err(|err| InvalidArgumentError::new(spec, err.to_string().as_str()))?;
+        if !root.is_dir() {
+            return Err(InvalidArgumentError::new(spec, "not a directory"));
+        }
+
+        let mut mount = Mount {
+            prefix: prefix.trim_end_matches('/').to_string(),
+            root,
+            options: ServeOptions::default(),
+        };
+        for option in parts {
+            let (name, value) = option.split_once('=').unwrap_or((option, ""));
+            match mount.options.parse_option(name, value) {
+                Ok(true) => {}
+                Ok(false) => {
+                    return Err(InvalidArgumentError::new(
+                        spec,
+                        format!("unknown mount option {}", option).as_str(),
+                    ));
+                }
+                Err(reason) => return Err(InvalidArgumentError::new(spec, reason)),
+            }
+        }
+
+        Ok(mount)
+    }
+}
+
+/// # Mount serving a URL path
+///
+/// The longest prefix matching whole path segments wins, so `/assets` serves `/assets` and
+/// `/assets/app.js` but not `/assets2`. Returns the mount and the rest of the path.
+pub fn select<'a, 'b>(mounts: &'a [Mount], path: &'b str) -> Option<(&'a Mount, &'b str)> {
+    mounts
+        .iter()
+        .filter_map(|mount| Some((mount, strip_path_prefix(path, &mount.prefix)?)))
+        .max_by_key(|(mount, _)| mount.prefix.len())
+}
+
+/// # URL path without a prefix of whole path segments
+///
+/// `prefix` has no trailing slash, an empty prefix matches any path.
+pub fn strip_path_prefix<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    let rest = path.strip_prefix(prefix)?;
+    if rest.is_empty() || rest.starts_with('/') {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::mount::{select, Mount};
+
+    #[test]
+    fn test_parse() {
+        let mount = Mount::parse("/assets/=src,read-only,listing=off,header=X-A:1").unwrap();
+        assert_eq!(mount.prefix, "/assets");
+        assert!(mount.root.ends_with("src"));
+        assert!(mount.options.read_only);
+        assert!(!mount.options.listing);
+        assert_eq!(
+            mount.options.headers,
+            vec![("X-A".to_string(), "1".to_string())]
+        );
+
+        assert!(Mount::parse("assets=src").is_err());
+        assert!(Mount::parse("/assets=src,unknown").is_err());
+    }
+
+    #[test]
+    fn test_select() {
+        let mounts = vec![
+            Mount::parse("/assets=src").unwrap(),
+            Mount::parse("/assets/img=src/utils").unwrap(),
+        ];
+        let selected =
+            |path| select(&mounts, path).map(|(mount, rest)| (mount.prefix.as_str(), rest));
+
+        assert_eq!(selected("/assets"), Some(("/assets", "")));
+        assert_eq!(selected("/assets/app.js"), Some(("/assets", "/app.js")));
+        assert_eq!(
+            selected("/assets/img/a.png"),
+            Some(("/assets/img", "/a.png"))
+        );
+        assert_eq!(selected("/assets2/app.js"), None);
+        assert_eq!(selected("/"), None);
+    }
+}
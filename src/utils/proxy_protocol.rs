@@ -0,0 +1,265 @@
+use crate::traits::connection::Connection;
+use crate::utils::x509::ClientIdentity;
+use std::io::{Cursor, Error, ErrorKind, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+const V1_PREFIX: &[u8] = b"PROXY";
+/// Longest v1 header, including `\r\n`
+const V1_MAX_LENGTH: usize = 107;
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// # Whether clients must send a PROXY protocol header
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProxyProtocol {
+    /// Connections without the header are refused
+    Required,
+    /// Connections without the header are served with the socket peer address
+    Optional,
+}
+
+/// # Connection behind a PROXY protocol header
+///
+/// Reports the client address of the header as the peer address. Bytes read while looking for
+/// an optional header are read again.
+pub struct ProxiedStream<C: Connection> {
+    inner: C,
+    prefix: Cursor<Vec<u8>>,
+    peer_addr: Option<SocketAddr>,
+}
+
+impl<C: Connection> Read for ProxiedStream<C> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.prefix.read(buf)? {
+            0 => self.inner.read(buf),
+            read => Ok(read),
+        }
+    }
+}
+
+impl<C: Connection> Write for ProxiedStream<C> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<C: Connection> Connection for ProxiedStream<C> {
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    fn client_identity(&self) -> Option<ClientIdentity> {
+        self.inner.client_identity()
+    }
+
+    fn alpn_protocol(&self) -> Option<Vec<u8>> {
+        self.inner.alpn_protocol()
+    }
+
+    fn is_tls(&self) -> bool {
+        self.inner.is_tls()
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+
+    fn abort(&self) {
+        self.inner.abort()
+    }
+}
+
+/// # Read the PROXY protocol header of a connection
+///
+/// Versions 1 (text) and 2 (binary) are detected. Health checks of the proxy (v1 `UNKNOWN`,
+/// v2 `LOCAL`) keep the socket peer address.
+pub fn accept<C: Connection>(
+    mut stream: C,
+    mode: ProxyProtocol,
+) -> Result<ProxiedStream<C>, Error> {
+    let socket_peer_addr = stream.peer_addr();
+    let mut start = [0u8; 5];
+    stream.read_exact(&mut start)?;
+
+    let peer_addr = if start == V1_PREFIX {
+        read_v1(&mut stream)?
+    } else if start == V2_SIGNATURE[..5] {
+        read_v2(&mut stream)?
+    } else if mode == ProxyProtocol::Optional {
+        return Ok(ProxiedStream {
+            inner: stream,
+            prefix: Cursor::new(start.to_vec()),
+            peer_addr: socket_peer_addr,
+        });
+    } else {
+        return Err(invalid("missing PROXY protocol header"));
+    };
+
+    Ok(ProxiedStream {
+        inner: stream,
+        prefix: Cursor::new(vec![]),
+        peer_addr: peer_addr.or(socket_peer_addr),
+    })
+}
+
+/// # Read a v1 header after `PROXY`, i.e. ` TCP4 192.0.2.1 192.0.2.2 56324 443\r\n`
+fn read_v1<R: Read>(stream: &mut R) -> Result<Option<SocketAddr>, Error> {
+    let mut line = vec![];
+    let mut byte = [0u8; 1];
+    while !line.ends_with(b"\r\n") {
+        if V1_PREFIX.len() + line.len() >= V1_MAX_LENGTH {
+            return Err(invalid("PROXY v1 header too long"));
+        }
+        stream.read_exact(&mut byte)?;
+        line.push(byte[0]);
+    }
+
+    let line = String::from_utf8_lossy(&line[..line.len() - 2]).to_string();
+    let fields: Vec<&str> = line.split(' ').collect();
+    match fields.as_slice() {
+        ["", "UNKNOWN", ..] => Ok(None),
+        ["", "TCP4" | "TCP6", source, _, source_port, _] => {
+            let ip = source
+                .parse::<IpAddr>()
+                .map_err(|_| invalid("invalid PROXY v1 source address"))?;
+            let port = source_port
+                .parse::<u16>()
+                .map_err(|_| invalid("invalid PROXY v1 source port"))?;
+            Ok(Option::from(SocketAddr::new(ip, port)))
+        }
+        _ => Err(invalid("invalid PROXY v1 header")),
+    }
+}
+
+/// # Read a v2 header after the first 5 bytes of the signature
+fn read_v2<R: Read>(stream: &mut R) -> Result<Option<SocketAddr>, Error> {
+    let mut header = [0u8; 11];
+    stream.read_exact(&mut header)?;
+    if header[..7] != V2_SIGNATURE[5..] {
+        return Err(invalid("invalid PROXY v2 signature"));
+    }
+    let (version_command, family) = (header[7], header[8]);
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+    let mut addresses = vec![0u8; u16::from_be_bytes([header[9], header[10]]) as usize];
+    stream.read_exact(&mut addresses)?;
+
+    // LOCAL connections are opened by the proxy itself
+    if version_command & 0x0f == 0 {
+        return Ok(None);
+    }
+    let source = match family >> 4 {
+        1 if addresses.len() >= 12 => SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::from(
+                <[u8; 4]>::try_from(&addresses[..4]).unwrap(),
+            )),
+            u16::from_be_bytes([addresses[8], addresses[9]]),
+        ),
+        2 if addresses.len() >= 36 => SocketAddr::new(
+            IpAddr::V6(Ipv6Addr::from(
+                <[u8; 16]>::try_from(&addresses[..16]).unwrap(),
+            )),
+            u16::from_be_bytes([addresses[32], addresses[33]]),
+        ),
+        // Unix sockets and unspecified families carry no client address
+        0 | 3 => return Ok(None),
+        _ => return Err(invalid("invalid PROXY v2 addresses")),
+    };
+
+    Ok(Option::from(source))
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::traits::connection::Connection;
+    use crate::utils::mock_connection::MockConnection;
+    use crate::utils::proxy_protocol::{accept, ProxyProtocol};
+    use std::io::Read;
+
+    fn accepted(input: &[u8], mode: ProxyProtocol) -> Option<(String, String)> {
+        let connection =
+            MockConnection::new(input).with_peer_addr("10.0.0.2:4000".parse().unwrap());
+        let mut stream = accept(connection, mode).ok()?;
+        let mut rest = String::new();
+        stream.read_to_string(&mut rest).unwrap();
+        Option::from((stream.peer_addr().unwrap().to_string(), rest))
+    }
+
+    #[test]
+    fn test_accept_v1() {
+        let input = b"PROXY TCP4 192.0.2.1 192.0.2.2 56324 443\r\nGET /";
+        assert_eq!(
+            accepted(input, ProxyProtocol::Required),
+            Some(("192.0.2.1:56324".to_string(), "GET /".to_string()))
+        );
+        let input = b"PROXY TCP6 2001:db8::1 2001:db8::2 4711 443\r\n";
+        assert_eq!(
+            accepted(input, ProxyProtocol::Required).unwrap().0,
+            "[2001:db8::1]:4711"
+        );
+        let input = b"PROXY UNKNOWN\r\nGET /";
+        assert_eq!(
+            accepted(input, ProxyProtocol::Required).unwrap().0,
+            "10.0.0.2:4000"
+        );
+
+        assert_eq!(
+            accepted(b"PROXY TCP4 192.0.2.1\r\n", ProxyProtocol::Required),
+            None
+        );
+        let too_long = format!("PROXY TCP4 {}\r\n", " ".repeat(200));
+        assert_eq!(accepted(too_long.as_bytes(), ProxyProtocol::Required), None);
+    }
+
+    #[test]
+    fn test_accept_v2() {
+        let mut input = b"\r\n\r\n\0\r\nQUIT\n\x21\x11\x00\x10".to_vec();
+        input.extend_from_slice(&[192, 0, 2, 1, 192, 0, 2, 2, 0xdc, 0x04, 0x01, 0xbb]);
+        // a TLV after the addresses
+        input.extend_from_slice(&[0x04, 0x00, 0x01, 0x00]);
+        input.extend_from_slice(b"GET /");
+        assert_eq!(
+            accepted(&input, ProxyProtocol::Required),
+            Some(("192.0.2.1:56324".to_string(), "GET /".to_string()))
+        );
+
+        let input = b"\r\n\r\n\0\r\nQUIT\n\x20\x00\x00\x00GET /";
+        assert_eq!(
+            accepted(input, ProxyProtocol::Required),
+            Some(("10.0.0.2:4000".to_string(), "GET /".to_string()))
+        );
+        assert_eq!(
+            accepted(
+                b"\r\n\r\n\0\r\nQUIT\n\x31\x11\x00\x00",
+                ProxyProtocol::Required
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_accept_missing() {
+        let input = b"GET / HTTP/1.1\r\n\r\n";
+        assert_eq!(accepted(input, ProxyProtocol::Required), None);
+        assert_eq!(
+            accepted(input, ProxyProtocol::Optional),
+            Some((
+                "10.0.0.2:4000".to_string(),
+                "GET / HTTP/1.1\r\n\r\n".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,291 @@
+use crate::errors::invalid_argument::InvalidArgumentError;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Buckets kept at most, the least recently used quarter is dropped beyond
+const MAX_TRACKED_CLIENTS: usize = 4096;
+
+/// # Requests per second with a burst allowance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RequestRate {
+    pub per_second: f64,
+    pub burst: f64,
+}
+
+impl RequestRate {
+    /// # Parse a request rate
+    ///
+    /// Format: `RATE[,burst=N]` in requests per second, i.e. `10,burst=50`. The burst defaults
+    /// to the rate, and at least one request.
+    pub fn parse(spec: &str) -> Result<RequestRate, InvalidArgumentError> {
+        let mut parts = spec.split(',');
+        let per_second = match parts.next().unwrap_or_default().parse::<f64>() {
+            Ok(rate) if rate.is_finite() && rate > 0.0 => rate,
+            _ => return Err(InvalidArgumentError::new(spec, "invalid request rate")),
+        };
+        let mut burst = per_second.max(1.0);
+        for option in parts {
+            match option.split_once('=') {
+                Some(("burst", value)) => match value.parse::<u32>() {
+                    Ok(value) if value > 0 => burst = f64::from(value),
+                    _ => return Err(InvalidArgumentError::new(spec, "invalid burst")),
+                },
+                _ => {
+                    return Err(InvalidArgumentError::new(
+                        spec,
+                        format!("invalid rate limit option {}", option).as_str(),
+                    ));
+                }
+            }
+        }
+
+        Ok(RequestRate { per_second, burst })
+    }
+}
+
+/// # Request rate and concurrent connection limits
+///
+/// Requests are limited per client address with a token bucket, connections per peer address
+/// and for the whole server.
+#[derive(Default)]
+pub struct RateLimiter {
+    pub request_rate: Option<RequestRate>,
+    pub max_connections: Option<usize>,
+    pub max_connections_per_ip: Option<usize>,
+    state: Mutex<RateLimiterState>,
+}
+
+#[derive(Default)]
+struct RateLimiterState {
+    buckets: HashMap<IpAddr, Bucket>,
+    connections: HashMap<IpAddr, usize>,
+    total_connections: usize,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// # Open connection, counted until dropped
+pub struct ConnectionSlot {
+    limiter: Arc<RateLimiter>,
+    peer: Option<IpAddr>,
+}
+
+impl RateLimiter {
+    pub fn new(
+        request_rate: Option<RequestRate>,
+        max_connections: Option<usize>,
+        max_connections_per_ip: Option<usize>,
+    ) -> RateLimiter {
+        RateLimiter {
+            request_rate,
+            max_connections,
+            max_connections_per_ip,
+            state: Mutex::default(),
+        }
+    }
+
+    /// # Take a request token of a client
+    ///
+    /// Returns the time until the next token when the bucket is empty. IPv6 clients share the
+    /// bucket of their /64 network, which a single host usually holds entirely.
+    pub fn check_request(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let Some(rate) = self.request_rate else {
+            return Ok(());
+        };
+        let key = bucket_key(client);
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        if state.buckets.len() >= MAX_TRACKED_CLIENTS && !state.buckets.contains_key(&key) {
+            evict_least_recent(&mut state.buckets);
+        }
+
+        let bucket = state.buckets.entry(key).or_insert(Bucket {
+            tokens: rate.burst,
+            updated: now,
+        });
+        bucket.tokens =
+            (bucket.tokens + elapsed(bucket.updated, now) * rate.per_second).min(rate.burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / rate.per_second,
+            ))
+        }
+    }
+
+    /// # Count a new connection
+    ///
+    /// `peer` is left out of the per address limit when it is a proxy. Returns `None` when a
+    /// limit is reached.
+    pub fn open_connection(
+        limiter: &Arc<RateLimiter>,
+        peer: Option<IpAddr>,
+    ) -> Option<ConnectionSlot> {
+        let peer = peer.map(|peer| peer.to_canonical());
+        let mut state = limiter.state.lock().unwrap_or_else(|err| err.into_inner());
+        if limiter
+            .max_connections
+            .is_some_and(|max| state.total_connections >= max)
+        {
+            return None;
+        }
+        if let (Some(max), Some(peer)) = (limiter.max_connections_per_ip, peer) {
+            let count = state.connections.entry(peer).or_insert(0);
+            if *count >= max {
+                return None;
+            }
+            *count += 1;
+        }
+        state.total_connections += 1;
+
+        Some(ConnectionSlot {
+            limiter: Arc::clone(limiter),
+            peer: peer.filter(|_| limiter.max_connections_per_ip.is_some()),
+        })
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let mut state = self
+            .limiter
+            .state
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        state.total_connections -= 1;
+        if let Some(peer) = self.peer {
+            if let Some(count) = state.connections.get_mut(&peer) {
+                *count -= 1;
+                if *count == 0 {
+                    state.connections.remove(&peer);
+                }
+            }
+        }
+    }
+}
+
+/// # Address of the bucket of a client, its /64 network for IPv6
+fn bucket_key(client: IpAddr) -> IpAddr {
+    match client.to_canonical() {
+        IpAddr::V6(address) => IpAddr::V6((address.to_bits() & !u128::from(u64::MAX)).into()),
+        address => address,
+    }
+}
+
+/// # Drop the least recently used quarter of the buckets
+///
+/// Runs once per quarter of the limit of new clients, which keeps the cost per request low.
+fn evict_least_recent(buckets: &mut HashMap<IpAddr, Bucket>) {
+    let mut ages: Vec<(Instant, IpAddr)> = buckets
+        .iter()
+        .map(|(key, bucket)| (bucket.updated, *key))
+        .collect();
+    let count = (ages.len() / 4).max(1);
+    ages.select_nth_unstable(count - 1);
+    for (_, key) in &ages[..count] {
+        buckets.remove(key);
+    }
+}
+
+fn elapsed(since: Instant, now: Instant) -> f64 {
+    now.saturating_duration_since(since).as_secs_f64()
+}
+
+/// # `Retry-After` seconds for a wait, rounded up
+pub fn retry_after(wait: Duration) -> u64 {
+    wait.as_secs() + u64::from(wait.subsec_nanos() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::rate_limit::{retry_after, RateLimiter, RequestRate, MAX_TRACKED_CLIENTS};
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            RequestRate::parse("10,burst=50").unwrap(),
+            RequestRate {
+                per_second: 10.0,
+                burst: 50.0
+            }
+        );
+        assert_eq!(RequestRate::parse("0.5").unwrap().burst, 1.0);
+        assert!(RequestRate::parse("0").is_err());
+        assert!(RequestRate::parse("10,burst=0").is_err());
+        assert!(RequestRate::parse("10,size=2").is_err());
+    }
+
+    #[test]
+    fn test_check_request() {
+        let limiter = RateLimiter::new(Option::from(RequestRate::parse("2").unwrap()), None, None);
+        let now = Instant::now();
+        assert!(limiter.check_request(ip("192.0.2.1"), now).is_ok());
+        assert!(limiter.check_request(ip("::ffff:192.0.2.1"), now).is_ok());
+        let wait = limiter.check_request(ip("192.0.2.1"), now).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+        assert_eq!(retry_after(wait), 1);
+        assert!(limiter.check_request(ip("192.0.2.2"), now).is_ok());
+        assert!(limiter
+            .check_request(ip("192.0.2.1"), now + Duration::from_millis(500))
+            .is_ok());
+
+        // one bucket per IPv6 /64
+        assert!(limiter.check_request(ip("2001:db8::1"), now).is_ok());
+        assert!(limiter.check_request(ip("2001:db8::2"), now).is_ok());
+        assert!(limiter.check_request(ip("2001:db8::3"), now).is_err());
+        assert!(limiter.check_request(ip("2001:db8:0:1::1"), now).is_ok());
+
+        let unlimited = RateLimiter::default();
+        for _ in 0..10 {
+            assert!(unlimited.check_request(ip("192.0.2.1"), now).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_check_request_max_clients() {
+        let limiter = RateLimiter::new(Option::from(RequestRate::parse("1").unwrap()), None, None);
+        let now = Instant::now();
+        assert!(limiter.check_request(ip("192.0.2.1"), now).is_ok());
+        for index in 0..2 * MAX_TRACKED_CLIENTS as u32 {
+            let client = IpAddr::V4(Ipv4Addr::from_bits(0x0a00_0000 + index));
+            let _ = limiter.check_request(client, now + Duration::from_millis(u64::from(index)));
+        }
+        let state = limiter.state.lock().unwrap();
+        assert!(state.buckets.len() <= MAX_TRACKED_CLIENTS);
+        // the oldest buckets are gone, the most recent one is kept
+        assert!(!state.buckets.contains_key(&ip("192.0.2.1")));
+        assert!(state.buckets.contains_key(&IpAddr::V4(Ipv4Addr::from_bits(
+            0x0a00_0000 + 2 * MAX_TRACKED_CLIENTS as u32 - 1
+        ))));
+    }
+
+    #[test]
+    fn test_open_connection() {
+        let limiter = Arc::new(RateLimiter::new(None, Option::from(3), Option::from(2)));
+        let first = RateLimiter::open_connection(&limiter, Option::from(ip("192.0.2.1")));
+        let second = RateLimiter::open_connection(&limiter, Option::from(ip("192.0.2.1")));
+        assert!(first.is_some() && second.is_some());
+        assert!(RateLimiter::open_connection(&limiter, Option::from(ip("192.0.2.1"))).is_none());
+        drop(first);
+        let third = RateLimiter::open_connection(&limiter, Option::from(ip("192.0.2.1")));
+        assert!(third.is_some());
+
+        let proxied = RateLimiter::open_connection(&limiter, None);
+        assert!(proxied.is_some());
+        assert!(RateLimiter::open_connection(&limiter, Option::from(ip("192.0.2.2"))).is_none());
+    }
+}
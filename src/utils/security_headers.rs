@@ -0,0 +1,285 @@
+use crate::errors::invalid_argument::InvalidArgumentError;
+use crate::utils::http_response::HttpResponse;
+use crate::utils::mount;
+
+/// Content security policy of directory listings, which need no scripts, styles or images
+const LISTING_CSP: &str = "default-src 'none'";
+const ONE_YEAR: u64 = 31_536_000;
+
+/// # Who may show responses in a frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameOptions {
+    Deny,
+    SameOrigin,
+}
+
+impl FrameOptions {
+    fn x_frame_options(self) -> &'static str {
+        match self {
+            FrameOptions::Deny => "DENY",
+            FrameOptions::SameOrigin => "SAMEORIGIN",
+        }
+    }
+
+    fn frame_ancestors(self) -> &'static str {
+        match self {
+            FrameOptions::Deny => "frame-ancestors 'none'",
+            FrameOptions::SameOrigin => "frame-ancestors 'self'",
+        }
+    }
+}
+
+/// # Security headers of a URL prefix
+#[derive(Debug, PartialEq)]
+pub struct SecurityHeaders {
+    /// URL path prefix without a trailing slash, empty for the whole site
+    pub prefix: String,
+    /// `Strict-Transport-Security` max-age in seconds, sent over HTTPS only
+    pub hsts: Option<u64>,
+    /// `X-Content-Type-Options: nosniff`
+    pub nosniff: bool,
+    pub referrer_policy: Option<String>,
+    /// `X-Frame-Options` and the `frame-ancestors` directive
+    pub frame: Option<FrameOptions>,
+    pub csp: Option<String>,
+}
+
+impl SecurityHeaders {
+    /// # Parse a `--security-headers` preset
+    ///
+    /// Format: `PRESET[,hsts=SECONDS][,referrer=POLICY][,frame=deny|sameorigin][,csp=POLICY]
+    /// [,nosniff=on|off][,path=/PREFIX]`, i.e. `strict,csp=default-src 'self'`. Presets are
+    /// `basic`, `strict` and `off`, every option accepts `off`.
+    pub fn parse(spec: &str) -> Result<SecurityHeaders, InvalidArgumentError> {
+        let mut parts = spec.split(',');
+        let mut headers = match parts.next().unwrap_or_default() {
+            "basic" => SecurityHeaders {
+                prefix: String::new(),
+                hsts: Option::from(ONE_YEAR),
+                nosniff: true,
+                referrer_policy: Option::from("strict-origin-when-cross-origin".to_string()),
+                frame: Option::from(FrameOptions::SameOrigin),
+                csp: None,
+            },
+            "strict" => SecurityHeaders {
+                prefix: String::new(),
+                hsts: Option::from(2 * ONE_YEAR),
+                nosniff: true,
+                referrer_policy: Option::from("no-referrer".to_string()),
+                frame: Option::from(FrameOptions::Deny),
+                csp: Option::from(
+                    "default-src 'self'; object-src 'none'; base-uri 'self'".to_string(),
+                ),
+            },
+            "off" => SecurityHeaders {
+                prefix: String::new(),
+                hsts: None,
+                nosniff: false,
+                referrer_policy: None,
+                frame: None,
+                csp: None,
+            },
+            preset => {
+                return Err(InvalidArgumentError::new(
+                    spec,
+                    format!("unknown preset {}", preset).as_str(),
+                ));
+            }
+        };
+
+        for option in parts {
+            let invalid = || {
+                InvalidArgumentError::new(
+                    spec,
+                    format!("invalid security header option {}", option).as_str(),
+                )
+            };
+            match option.split_once('=').ok_or_else(invalid)? {
+                ("path", value) if value.starts_with('/') => {
+                    headers.prefix = value.trim_end_matches('/').to_string()
+                }
+                ("hsts", "off") => headers.hsts = None,
+                ("hsts", value) => {
+                    headers.hsts = Option::from(value.parse::<u64>().map_err(|_| invalid())?)
+                }
+                ("nosniff", "on") => headers.nosniff = true,
+                ("nosniff", "off") => headers.nosniff = false,
+                ("referrer", "off") => headers.referrer_policy = None,
+                ("referrer", value) if !value.is_empty() => {
+                    headers.referrer_policy = Option::from(value.to_string())
+                }
+                ("frame", "off") => headers.frame = None,
+                ("frame", value) if value.eq_ignore_ascii_case("deny") => {
+                    headers.frame = Option::from(FrameOptions::Deny)
+                }
+                ("frame", value) if value.eq_ignore_ascii_case("sameorigin") => {
+                    headers.frame = Option::from(FrameOptions::SameOrigin)
+                }
+                ("csp", "off") => headers.csp = None,
+                ("csp", value) if !value.is_empty() => {
+                    headers.csp = Option::from(value.to_string())
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(headers)
+    }
+
+    /// # Whether no header is sent, as with the `off` preset
+    fn is_off(&self) -> bool {
+        self.hsts.is_none()
+            && !self.nosniff
+            && self.referrer_policy.is_none()
+            && self.frame.is_none()
+            && self.csp.is_none()
+    }
+
+    /// # Headers of a response
+    ///
+    /// Directory listings are never framed and load nothing.
+    fn headers(&self, https: bool, listing: bool) -> Vec<(String, String)> {
+        let mut headers = vec![];
+        if let Some(max_age) = self.hsts.filter(|_| https) {
+            headers.push((
+                "Strict-Transport-Security".to_string(),
+                format!("max-age={}", max_age),
+            ));
+        }
+        if self.nosniff {
+            headers.push(("X-Content-Type-Options".to_string(), "nosniff".to_string()));
+        }
+        if let Some(policy) = &self.referrer_policy {
+            headers.push(("Referrer-Policy".to_string(), policy.clone()));
+        }
+
+        let (frame, csp) = if listing {
+            (Option::from(FrameOptions::Deny), Some(LISTING_CSP))
+        } else {
+            (self.frame, self.csp.as_deref())
+        };
+        if let Some(frame) = frame {
+            headers.push((
+                "X-Frame-Options".to_string(),
+                frame.x_frame_options().to_string(),
+            ));
+        }
+        let csp = match (csp, frame) {
+            (Some(csp), Some(frame)) if !csp.contains("frame-ancestors") => {
+                Option::from(format!("{}; {}", csp, frame.frame_ancestors()))
+            }
+            (Some(csp), _) => Option::from(csp.to_string()),
+            (None, Some(frame)) => Option::from(frame.frame_ancestors().to_string()),
+            (None, None) => None,
+        };
+        if let Some(csp) = csp {
+            headers.push(("Content-Security-Policy".to_string(), csp));
+        }
+
+        headers
+    }
+}
+
+/// # Add the security headers of a URL path to a response
+///
+/// The longest prefix matching whole path segments wins. Headers already set, i.e. by rule
+/// files or host options, are kept. `https` enables `Strict-Transport-Security`.
+pub fn add_headers(
+    rules: &[SecurityHeaders],
+    path: &str,
+    https: bool,
+    listing: bool,
+    response: &mut HttpResponse,
+) {
+    let Some(rule) = rules
+        .iter()
+        .filter(|rule| mount::strip_path_prefix(path, &rule.prefix).is_some())
+        .max_by_key(|rule| rule.prefix.len())
+    else {
+        return;
+    };
+    if rule.is_off() {
+        return;
+    }
+
+    let headers = response.headers.get_or_insert_with(Vec::new);
+    for (name, value) in rule.headers(https, listing) {
+        if !headers
+            .iter()
+            .any(|(key, _)| key.eq_ignore_ascii_case(&name))
+        {
+            headers.push((name, value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::http_response::HttpResponse;
+    use crate::utils::security_headers::{add_headers, FrameOptions, SecurityHeaders};
+
+    fn header<'a>(response: &'a HttpResponse, name: &str) -> Option<&'a str> {
+        response
+            .headers
+            .as_ref()?
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn test_parse() {
+        let headers =
+            SecurityHeaders::parse("basic,hsts=600,frame=DENY,csp=default-src 'self',path=/app/")
+                .unwrap();
+        assert_eq!(headers.prefix, "/app");
+        assert_eq!(headers.hsts, Some(600));
+        assert_eq!(headers.frame, Some(FrameOptions::Deny));
+        assert_eq!(headers.csp.as_deref(), Some("default-src 'self'"));
+        assert!(SecurityHeaders::parse("strict,referrer=off")
+            .unwrap()
+            .referrer_policy
+            .is_none());
+        assert!(SecurityHeaders::parse("paranoid").is_err());
+        assert!(SecurityHeaders::parse("basic,frame=allow").is_err());
+        assert!(SecurityHeaders::parse("basic,hsts").is_err());
+    }
+
+    #[test]
+    fn test_add_headers() {
+        let rules = vec![
+            SecurityHeaders::parse("basic").unwrap(),
+            SecurityHeaders::parse("off,path=/embed").unwrap(),
+        ];
+        let mut response = HttpResponse::new(
+            200,
+            Option::from(vec![("Referrer-Policy".to_string(), "origin".to_string())]),
+            None,
+        );
+        add_headers(&rules, "/index.html", false, false, &mut response);
+        assert_eq!(header(&response, "Strict-Transport-Security"), None);
+        assert_eq!(header(&response, "X-Content-Type-Options"), Some("nosniff"));
+        assert_eq!(header(&response, "Referrer-Policy"), Some("origin"));
+        assert_eq!(header(&response, "X-Frame-Options"), Some("SAMEORIGIN"));
+        assert_eq!(
+            header(&response, "Content-Security-Policy"),
+            Some("frame-ancestors 'self'")
+        );
+
+        let mut response = HttpResponse::new(200, None, None);
+        add_headers(&rules, "/docs/", true, true, &mut response);
+        assert_eq!(
+            header(&response, "Strict-Transport-Security"),
+            Some("max-age=31536000")
+        );
+        assert_eq!(header(&response, "X-Frame-Options"), Some("DENY"));
+        assert_eq!(
+            header(&response, "Content-Security-Policy"),
+            Some("default-src 'none'; frame-ancestors 'none'")
+        );
+
+        let mut response = HttpResponse::new(200, None, None);
+        add_headers(&rules, "/embed/a", true, false, &mut response);
+        assert_eq!(response.headers, None);
+    }
+}
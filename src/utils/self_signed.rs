@@ -0,0 +1,259 @@
+use crate::utils::date;
+use crate::utils::date::SECONDS_PER_DAY;
+use rcgen::{
+    date_time_ymd, BasicConstraints, CertificateParams, DistinguishedName, DnType,
+    ExtendedKeyUsagePurpose, IsCa, KeyPair, KeyUsagePurpose, PKCS_ECDSA_P256_SHA256,
+};
+use std::env;
+use std::fs;
+use std::io::{Error, ErrorKind, Write};
+use std::net::{IpAddr, UdpSocket};
+use std::path::{Path, PathBuf};
+
+const CACHE_DIR_NAME: &str = "http-mini";
+const CA_NAME: &str = "http-mini local CA";
+const LEAF_VALIDITY_DAYS: i64 = 365;
+const CA_VALIDITY_DAYS: i64 = 3650;
+/// Certificates expiring sooner are regenerated
+const RENEW_BEFORE_DAYS: i64 = 30;
+
+/// # Generated development certificate
+pub struct SelfSignedCertificate {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    /// Certificate of the local CA which signed `cert`, if any
+    pub ca_cert: Option<PathBuf>,
+}
+
+/// # Get a cached development certificate, generating it when needed
+///
+/// The ECDSA P-256 certificate is valid for `localhost`, `127.0.0.1`, `::1`, the host name
+/// and the LAN addresses of this machine. It is regenerated when these names change or when
+/// it is about to expire.
+///
+/// With `local_ca` the certificate is signed by a local CA, also generated once and cached,
+/// which can be trusted by browsers instead of accepting every new certificate.
+pub fn get_certificate(local_ca: bool) -> Result<SelfSignedCertificate, Error> {
+    let dir = get_cache_dir();
+    fs::create_dir_all(&dir)?;
+
+    let names = get_local_names();
+    let to_io_error = |err: rcgen::Error| Error::new(ErrorKind::InvalidData, err.to_string());
+
+    if !local_ca {
+        let cert = dir.join("self-signed-cert.pem");
+        let key = dir.join("self-signed-key.pem");
+        if !is_fresh(&dir.join("self-signed.meta"), &names) {
+            let key_pair = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).map_err(to_io_error)?;
+            let certificate = get_leaf_params(&names)?
+                .self_signed(&key_pair)
+                .map_err(to_io_error)?;
+            write_private(&key, key_pair.serialize_pem().as_bytes())?;
+            fs::write(&cert, certificate.pem())?;
+            write_meta(&dir.join("self-signed.meta"), &names, LEAF_VALIDITY_DAYS)?;
+        }
+
+        return Ok(SelfSignedCertificate {
+            cert,
+            key,
+            ca_cert: None,
+        });
+    }
+
+    // local CA
+    let ca_cert = dir.join("local-ca-cert.pem");
+    let ca_key = dir.join("local-ca-key.pem");
+    let mut ca_renewed = false;
+    let ca_key_pair = if is_fresh(&dir.join("local-ca.meta"), &[]) {
+        KeyPair::from_pem(fs::read_to_string(&ca_key)?.as_str()).map_err(to_io_error)?
+    } else {
+        let key_pair = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).map_err(to_io_error)?;
+        let certificate = get_ca_params()
+            .self_signed(&key_pair)
+            .map_err(to_io_error)?;
+        write_private(&ca_key, key_pair.serialize_pem().as_bytes())?;
+        fs::write(&ca_cert, certificate.pem())?;
+        write_meta(&dir.join("local-ca.meta"), &[], CA_VALIDITY_DAYS)?;
+        ca_renewed = true;
+        key_pair
+    };
+
+    // the issuer is rebuilt from the same parameters and key, which results in the same
+    // subject and key identifier as the cached CA certificate
+    let cert = dir.join("local-ca-leaf-cert.pem");
+    let key = dir.join("local-ca-leaf-key.pem");
+    if ca_renewed || !is_fresh(&dir.join("local-ca-leaf.meta"), &names) {
+        let issuer = get_ca_params()
+            .self_signed(&ca_key_pair)
+            .map_err(to_io_error)?;
+        let key_pair = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).map_err(to_io_error)?;
+        let certificate = get_leaf_params(&names)?
+            .signed_by(&key_pair, &issuer, &ca_key_pair)
+            .map_err(to_io_error)?;
+        write_private(&key, key_pair.serialize_pem().as_bytes())?;
+        fs::write(&cert, certificate.pem())?;
+        write_meta(&dir.join("local-ca-leaf.meta"), &names, LEAF_VALIDITY_DAYS)?;
+    }
+
+    Ok(SelfSignedCertificate {
+        cert,
+        key,
+        ca_cert: Option::from(ca_cert),
+    })
+}
+
+/// # Directory of the cached certificates
+///
+/// `$XDG_CACHE_HOME/http-mini`, `$HOME/.cache/http-mini` or a temporary directory.
+fn get_cache_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        return PathBuf::from(dir).join(CACHE_DIR_NAME);
+    }
+    if let Some(dir) = env::var_os("HOME").filter(|dir| !dir.is_empty()) {
+        return PathBuf::from(dir).join(".cache").join(CACHE_DIR_NAME);
+    }
+
+    env::temp_dir().join(CACHE_DIR_NAME)
+}
+
+/// # Names and addresses of this machine
+fn get_local_names() -> Vec<String> {
+    let mut names = vec![
+        "localhost".to_string(),
+        "127.0.0.1".to_string(),
+        "::1".to_string(),
+    ];
+
+    if let Some(hostname) = get_hostname() {
+        names.push(hostname);
+    }
+
+    // connecting a UDP socket sends nothing, but selects the address of the outgoing interface
+    for (bind, remote) in [
+        ("0.0.0.0:0", "192.0.2.1:80"),
+        ("[::]:0", "[2001:db8::1]:80"),
+    ] {
+        let local_ip = UdpSocket::bind(bind)
+            .and_then(|socket| socket.connect(remote).map(|_| socket))
+            .and_then(|socket| socket.local_addr())
+            .map(|addr| addr.ip());
+        if let Ok(ip) = local_ip {
+            if !ip.is_unspecified() && !ip.is_loopback() && !is_link_local(&ip) {
+                names.push(ip.to_string());
+            }
+        }
+    }
+
+    names.dedup();
+    names
+}
+
+fn is_link_local(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_link_local(),
+        IpAddr::V6(ip) => (ip.segments()[0] & 0xffc0) == 0xfe80,
+    }
+}
+
+/// # Host name of this machine
+#[cfg(not(unix))]
+fn get_hostname() -> Option<String> {
+    None
+}
+
+/// # Host name of this machine
+#[cfg(unix)]
+fn get_hostname() -> Option<String> {
+    let mut buffer = [0u8; 256];
+    // SAFETY: the buffer is valid for its whole length
+    let result =
+        unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) };
+    if result != 0 {
+        return None;
+    }
+
+    let end = buffer.iter().position(|&byte| byte == 0)?;
+    let hostname = String::from_utf8(buffer[..end].to_vec()).ok()?;
+    if hostname.is_empty() || hostname == "localhost" {
+        return None;
+    }
+
+    Some(hostname.to_lowercase())
+}
+
+fn get_ca_params() -> CertificateParams {
+    let mut params = CertificateParams::default();
+    let mut distinguished_name = DistinguishedName::new();
+    distinguished_name.push(DnType::CommonName, CA_NAME);
+    params.distinguished_name = distinguished_name;
+    params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+    params.key_usages = vec![
+        KeyUsagePurpose::KeyCertSign,
+        KeyUsagePurpose::CrlSign,
+        KeyUsagePurpose::DigitalSignature,
+    ];
+    set_validity(&mut params, CA_VALIDITY_DAYS);
+    params
+}
+
+fn get_leaf_params(names: &[String]) -> Result<CertificateParams, Error> {
+    let mut params = CertificateParams::new(names.to_vec())
+        .map_err(|err| Error::new(ErrorKind::InvalidInput, err.to_string()))?;
+    let mut distinguished_name = DistinguishedName::new();
+    distinguished_name.push(DnType::CommonName, "localhost");
+    params.distinguished_name = distinguished_name;
+    params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+    set_validity(&mut params, LEAF_VALIDITY_DAYS);
+    Ok(params)
+}
+
+/// # Make a certificate valid from yesterday for `days` days
+fn set_validity(params: &mut CertificateParams, days: i64) {
+    let (year, month, day) = date::get_ymd(date::now() - SECONDS_PER_DAY);
+    params.not_before = date_time_ymd(year, month, day);
+    let (year, month, day) = date::get_ymd(date::now() + days * SECONDS_PER_DAY);
+    params.not_after = date_time_ymd(year, month, day);
+}
+
+/// # Check that cached files were generated for `names` and are not about to expire
+///
+/// The meta file records the expiry as seconds since epoch on the first line, followed
+/// by the names, one per line.
+fn is_fresh(meta: &Path, names: &[String]) -> bool {
+    let contents = match fs::read_to_string(meta) {
+        Ok(contents) => contents,
+        Err(_) => return false,
+    };
+    let mut lines = contents.lines();
+    let not_after = lines.next().and_then(|line| line.parse::<i64>().ok());
+    let cached_names: Vec<&str> = lines.collect();
+
+    match not_after {
+        Some(not_after) => {
+            not_after - RENEW_BEFORE_DAYS * SECONDS_PER_DAY > date::now()
+                && cached_names == names.iter().map(String::as_str).collect::<Vec<&str>>()
+        }
+        None => false,
+    }
+}
+
+fn write_meta(meta: &Path, names: &[String], validity_days: i64) -> Result<(), Error> {
+    let mut contents = format!("{}\n", date::now() + validity_days * SECONDS_PER_DAY);
+    for name in names {
+        contents = format!("{}{}\n", contents, name);
+    }
+    fs::write(meta, contents)
+}
+
+/// # Write a file readable only by the owner
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(contents)
+}
@@ -0,0 +1,224 @@
+use crate::utils::digest::{constant_time_eq, sha256, sha512};
+
+/// Alphabet of the crypt Base64 variant
+const CRYPT_ALPHABET: &[u8; 64] =
+    b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const DEFAULT_ROUNDS: u32 = 5000;
+const MIN_ROUNDS: u32 = 1000;
+const MAX_ROUNDS: u32 = 999_999_999;
+const MAX_SALT_LENGTH: usize = 16;
+
+/// Byte order of the encoded SHA-256 result, 3 bytes per 4 characters
+const SHA256_ORDER: [(usize, usize, usize); 11] = [
+    (0, 10, 20),
+    (21, 1, 11),
+    (12, 22, 2),
+    (3, 13, 23),
+    (24, 4, 14),
+    (15, 25, 5),
+    (6, 16, 26),
+    (27, 7, 17),
+    (18, 28, 8),
+    (9, 19, 29),
+    (0, 31, 30),
+];
+/// Byte order of the encoded SHA-512 result, 3 bytes per 4 characters
+const SHA512_ORDER: [(usize, usize, usize); 22] = [
+    (0, 21, 42),
+    (22, 43, 1),
+    (44, 2, 23),
+    (3, 24, 45),
+    (25, 46, 4),
+    (47, 5, 26),
+    (6, 27, 48),
+    (28, 49, 7),
+    (50, 8, 29),
+    (9, 30, 51),
+    (31, 52, 10),
+    (53, 11, 32),
+    (12, 33, 54),
+    (34, 55, 13),
+    (56, 14, 35),
+    (15, 36, 57),
+    (37, 58, 16),
+    (59, 17, 38),
+    (18, 39, 60),
+    (40, 61, 19),
+    (62, 20, 41),
+    (0, 0, 63),
+];
+
+/// # Verify a password against a SHA-crypt hash
+///
+/// Supports the `$5$` (SHA-256) and `$6$` (SHA-512) schemes of glibc `crypt`, with an optional
+/// `rounds=N$` parameter.
+pub fn verify(password: &[u8], hash: &str) -> bool {
+    match crypt(password, hash) {
+        Some(computed) => constant_time_eq(computed.as_bytes(), hash.as_bytes()),
+        None => false,
+    }
+}
+
+/// # Hash a password with the scheme, rounds and salt of `setting`
+///
+/// `setting` is either a complete hash or its `$5$[rounds=N$]SALT` prefix.
+pub fn crypt(password: &[u8], setting: &str) -> Option<String> {
+    let (id, rest) = if let Some(rest) = setting.strip_prefix("$5$") {
+        ("5", rest)
+    } else if let Some(rest) = setting.strip_prefix("$6$") {
+        ("6", rest)
+    } else {
+        return None;
+    };
+
+    let (rounds, rest) = match rest.strip_prefix("rounds=") {
+        Some(rest) => {
+            let (rounds, rest) = rest.split_once('$')?;
+            let rounds = rounds.parse::<u64>().ok()?;
+            (
+                Option::from(rounds.clamp(MIN_ROUNDS.into(), MAX_ROUNDS.into()) as u32),
+                rest,
+            )
+        }
+        None => (None, rest),
+    };
+    let salt = rest.split('$').next().unwrap_or_default();
+    let salt = &salt[..salt
+        .char_indices()
+        .nth(MAX_SALT_LENGTH)
+        .map_or(salt.len(), |(index, _)| index)];
+
+    let (digest, order): (Vec<u8>, &[(usize, usize, usize)]) = if id == "5" {
+        let digest = sha_crypt(
+            &|data| sha256(data).to_vec(),
+            password,
+            salt.as_bytes(),
+            rounds.unwrap_or(DEFAULT_ROUNDS),
+        );
+        (digest, &SHA256_ORDER)
+    } else {
+        let digest = sha_crypt(
+            &|data| sha512(data).to_vec(),
+            password,
+            salt.as_bytes(),
+            rounds.unwrap_or(DEFAULT_ROUNDS),
+        );
+        (digest, &SHA512_ORDER)
+    };
+
+    let mut result = format!("${}$", id);
+    if let Some(rounds) = rounds {
+        result.push_str(format!("rounds={}$", rounds).as_str());
+    }
+    result.push_str(salt);
+    result.push('$');
+    for (index, (b2, b1, b0)) in order.iter().enumerate() {
+        let last = index == order.len() - 1;
+        let (value, length) = match (last, id) {
+            (true, "5") => ((u32::from(digest[*b1]) << 8) | u32::from(digest[*b0]), 3),
+            (true, _) => (u32::from(digest[*b0]), 2),
+            _ => (
+                (u32::from(digest[*b2]) << 16)
+                    | (u32::from(digest[*b1]) << 8)
+                    | u32::from(digest[*b0]),
+                4,
+            ),
+        };
+        for i in 0..length {
+            result.push(CRYPT_ALPHABET[(value >> (6 * i) & 0x3f) as usize] as char);
+        }
+    }
+
+    Some(result)
+}
+
+/// # Digest of the SHA-crypt algorithm, for any SHA-2 hash function
+fn sha_crypt(
+    hash: &dyn Fn(&[u8]) -> Vec<u8>,
+    password: &[u8],
+    salt: &[u8],
+    rounds: u32,
+) -> Vec<u8> {
+    let repeated = |digest: &[u8], length: usize| -> Vec<u8> {
+        digest.iter().copied().cycle().take(length).collect()
+    };
+
+    let digest_b = hash(&[password, salt, password].concat());
+    let mut input_a = [password, salt].concat();
+    input_a.extend(repeated(&digest_b, password.len()));
+    let mut length = password.len();
+    while length > 0 {
+        if length & 1 == 1 {
+            input_a.extend_from_slice(&digest_b);
+        } else {
+            input_a.extend_from_slice(password);
+        }
+        length >>= 1;
+    }
+    let digest_a = hash(&input_a);
+
+    let digest_p = hash(&password.repeat(password.len()));
+    let sequence_p = repeated(&digest_p, password.len());
+    let digest_s = hash(&salt.repeat(16 + usize::from(digest_a[0])));
+    let sequence_s = repeated(&digest_s, salt.len());
+
+    let mut digest_c = digest_a;
+    for round in 0..rounds {
+        let mut input = vec![];
+        if round % 2 == 1 {
+            input.extend_from_slice(&sequence_p);
+        } else {
+            input.extend_from_slice(&digest_c);
+        }
+        if round % 3 != 0 {
+            input.extend_from_slice(&sequence_s);
+        }
+        if round % 7 != 0 {
+            input.extend_from_slice(&sequence_p);
+        }
+        if round % 2 == 1 {
+            input.extend_from_slice(&digest_c);
+        } else {
+            input.extend_from_slice(&sequence_p);
+        }
+        digest_c = hash(&input);
+    }
+
+    digest_c
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::sha_crypt::{crypt, verify};
+
+    #[test]
+    fn test_crypt() {
+        assert_eq!(
+            crypt(b"Hello world!", "$5$saltstring").unwrap(),
+            "$5$saltstring$5B8vYYiY.CVt1RlTTf8KbXBH3hsxY/GNooZaBBGWEc5"
+        );
+        assert_eq!(
+            crypt(b"Hello world!", "$6$saltstring").unwrap(),
+            "$6$saltstring$svn8UoSVapNtMuq1ukKS4tPQd8iKwSMHWjl/O817G3uBnIFNjnQJuesI68u4OTLiBFdcbYEdFCoEOfaS35inz1"
+        );
+        assert_eq!(crypt(b"Hello world!", "$1$saltstring"), None);
+    }
+
+    #[test]
+    fn test_crypt_rounds() {
+        assert_eq!(
+            crypt(b"Hello world!", "$5$rounds=10000$saltstringsaltstring").unwrap(),
+            "$5$rounds=10000$saltstringsaltst$3xv.VbSHBb41AL9AvLeujZkZRBAwqFMz2.opqey6IcA"
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let hash = "$5$ZbNBV2pm1ZvtRrUE$pLlrlSl2WYvk7YBXRz/subdBLpZHGmyc4m56CBr0xu3";
+        assert!(verify(b"secret", hash));
+        assert!(!verify(b"wrong", hash));
+        let hash = "$6$averyveryverylon$f439HdLUHMQI2HiP8aN4x5loZ1qvGb55n9kt4d2ZOJqlEooZ9ot8vw2.j1vNGA.rsXQGoMDwgMor7ArX.3X.9/";
+        assert!(verify(b"secret", hash));
+        assert!(!verify(b"secret", "$6$broken"));
+    }
+}
@@ -0,0 +1,217 @@
+use crate::errors::invalid_argument::InvalidArgumentError;
+use crate::utils::digest::{constant_time_eq, hmac_sha256};
+use crate::utils::token_auth::read_source;
+use crate::utils::{base64, mount};
+use std::net::IpAddr;
+
+/// # Expiring links signed with a server secret
+///
+/// A signed link carries `expires` (Unix time), an optional client `ip` and a `signature`
+/// query parameter. The signature is the URL safe Base64 of the HMAC-SHA256 of the path,
+/// the expiry and the IP address, separated by new lines.
+pub struct SignedUrls {
+    /// URL path prefix without a trailing slash, empty for the whole site
+    pub prefix: String,
+    secret: Vec<u8>,
+}
+
+/// # Reason a signed link is refused
+#[derive(Debug, PartialEq)]
+pub enum SignatureError {
+    Missing,
+    Invalid,
+    Expired,
+    WrongClient,
+}
+
+impl SignatureError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            SignatureError::Missing => "Missing URL signature",
+            SignatureError::Invalid => "Invalid URL signature",
+            SignatureError::Expired => "Expired URL",
+            SignatureError::WrongClient => "URL signed for another client",
+        }
+    }
+}
+
+impl SignedUrls {
+    /// # Parse a signed link specification
+    ///
+    /// Format: `SECRET[,path=/PREFIX]`, where `SECRET` is a file or `env:VARIABLE`, i.e.
+    /// `env:HTTP_MINI_SECRET,path=/downloads`.
+    pub fn parse(spec: &str) -> Result<SignedUrls, InvalidArgumentError> {
+        let mut parts = spec.split(',');
+        let source = parts.next().unwrap_or_default();
+        let mut prefix = String::new();
+        for option in parts {
+            match option.split_once('=') {
+                Some(("path", value)) if value.starts_with('/') => {
+                    prefix = value.trim_end_matches('/').to_string()
+                }
+                _ => {
+                    return Err(InvalidArgumentError::new(
+                        spec,
+                        format!("invalid signed URL option {}", option).as_str(),
+                    ));
+                }
+            }
+        }
+
+        Ok(SignedUrls {
+            prefix,
+            secret: read_secret(source)?,
+        })
+    }
+
+    /// # Check the signature of a link to `path`
+    ///
+    /// `query` is the query string of the request, `now` the current Unix time.
+    pub fn verify(
+        &self,
+        path: &str,
+        query: Option<&str>,
+        client: Option<IpAddr>,
+        now: i64,
+    ) -> Result<(), SignatureError> {
+        let (mut expires, mut ip, mut signature) = (None, None, None);
+        for pair in query.unwrap_or_default().split('&') {
+            match pair.split_once('=') {
+                Some(("expires", value)) => expires = Option::from(value),
+                Some(("ip", value)) => ip = Option::from(value),
+                Some(("signature", value)) => signature = Option::from(value),
+                _ => {}
+            }
+        }
+
+        let signature = signature.ok_or(SignatureError::Missing)?;
+        let expires = expires
+            .and_then(|expires| expires.parse::<i64>().ok())
+            .ok_or(SignatureError::Invalid)?;
+        let expected = sign(&self.secret, path, expires, ip);
+        if !constant_time_eq(expected.as_bytes(), signature.as_bytes()) {
+            return Err(SignatureError::Invalid);
+        }
+        if expires < now {
+            return Err(SignatureError::Expired);
+        }
+        if let Some(ip) = ip {
+            let ip = ip.parse::<IpAddr>().map_err(|_| SignatureError::Invalid)?;
+            if client.map(|client| client.to_canonical()) != Option::from(ip.to_canonical()) {
+                return Err(SignatureError::WrongClient);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// # Read a signing secret from a file or from an `env:VARIABLE`
+pub fn read_secret(source: &str) -> Result<Vec<u8>, InvalidArgumentError> {
+    let secret = read_source(source)?.trim().as_bytes().to_vec();
+    if secret.is_empty() {
+        return Err(InvalidArgumentError::new(source, "empty secret"));
+    }
+
+    Ok(secret)
+}
+
+/// # Signature of a link
+pub fn sign(secret: &[u8], path: &str, expires: i64, ip: Option<&str>) -> String {
+    let message = format!("{}\n{}\n{}", path, expires, ip.unwrap_or_default());
+    base64::encode_url(&hmac_sha256(secret, message.as_bytes()))
+}
+
+/// # Path with the query string of a signed link
+pub fn signed_path(secret: &[u8], path: &str, expires: i64, ip: Option<IpAddr>) -> String {
+    let ip = ip.map(|ip| ip.to_string());
+    let signature = sign(secret, path, expires, ip.as_deref());
+    match ip {
+        Some(ip) => format!(
+            "{}?expires={}&ip={}&signature={}",
+            path, expires, ip, signature
+        ),
+        None => format!("{}?expires={}&signature={}", path, expires, signature),
+    }
+}
+
+/// # Signed links protecting a URL path
+///
+/// The longest prefix matching whole path segments wins.
+pub fn select<'a>(rules: &'a [SignedUrls], path: &str) -> Option<&'a SignedUrls> {
+    rules
+        .iter()
+        .filter(|rule| mount::strip_path_prefix(path, &rule.prefix).is_some())
+        .max_by_key(|rule| rule.prefix.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::signed_url::{signed_path, SignatureError, SignedUrls};
+    use std::env;
+    use std::net::IpAddr;
+
+    fn ip(ip: &str) -> Option<IpAddr> {
+        Option::from(ip.parse::<IpAddr>().unwrap())
+    }
+
+    #[test]
+    fn test_signed_path() {
+        assert_eq!(
+            signed_path(b"secret", "/a.zip", 1700000000, None),
+            "/a.zip?expires=1700000000&signature=TplzziZqXBD94v4ce9465NwTISV-4XLZ_JjraFcDHJI"
+        );
+        assert!(
+            signed_path(b"secret", "/a.zip", 1700000000, ip("192.0.2.1"))
+                .starts_with("/a.zip?expires=1700000000&ip=192.0.2.1&signature=")
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        env::set_var("HTTP_MINI_TEST_SECRET", "secret\n");
+        let rule = SignedUrls::parse("env:HTTP_MINI_TEST_SECRET,path=/downloads").unwrap();
+        assert_eq!(rule.prefix, "/downloads");
+
+        let query = |path| {
+            let signed = signed_path(b"secret", path, 1000, None);
+            signed.split_once('?').unwrap().1.to_string()
+        };
+        let valid = query("/downloads/a.zip");
+        assert_eq!(
+            rule.verify("/downloads/a.zip", Some(&valid), None, 999),
+            Ok(())
+        );
+        assert_eq!(
+            rule.verify("/downloads/a.zip", Some(&valid), None, 1001),
+            Err(SignatureError::Expired)
+        );
+        assert_eq!(
+            rule.verify("/downloads/b.zip", Some(&valid), None, 999),
+            Err(SignatureError::Invalid)
+        );
+        let extended = valid.replace("expires=1000", "expires=9000");
+        assert_eq!(
+            rule.verify("/downloads/a.zip", Some(&extended), None, 999),
+            Err(SignatureError::Invalid)
+        );
+        assert_eq!(
+            rule.verify("/downloads/a.zip", None, None, 999),
+            Err(SignatureError::Missing)
+        );
+
+        let signed = signed_path(b"secret", "/downloads/a.zip", 1000, ip("192.0.2.1"));
+        let bound = signed.split_once('?').unwrap().1;
+        assert_eq!(
+            rule.verify("/downloads/a.zip", Some(bound), ip("::ffff:192.0.2.1"), 999),
+            Ok(())
+        );
+        assert_eq!(
+            rule.verify("/downloads/a.zip", Some(bound), ip("192.0.2.2"), 999),
+            Err(SignatureError::WrongClient)
+        );
+
+        assert!(SignedUrls::parse("").is_err());
+        assert!(SignedUrls::parse("env:HTTP_MINI_TEST_SECRET,path").is_err());
+    }
+}
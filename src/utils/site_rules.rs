@@ -0,0 +1,380 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// File of header rules, in the source directory
+pub const HEADERS_FILE: &str = "_headers";
+/// File of redirect and rewrite rules, in the source directory
+pub const REDIRECTS_FILE: &str = "_redirects";
+const DEFAULT_REDIRECT_STATUS: u16 = 301;
+
+/// # Headers added to the responses of the paths matching a pattern
+#[derive(Debug, PartialEq)]
+pub struct HeaderRule {
+    pub pattern: String,
+    pub headers: Vec<(String, String)>,
+}
+
+/// # Redirect, or rewrite, of the paths matching a pattern
+#[derive(Debug, PartialEq)]
+pub struct RedirectRule {
+    pub from: String,
+    /// Path or URL, with `:name` placeholders and `:splat`
+    pub to: String,
+    pub status: u16,
+    /// Applies even when a file exists at the path
+    pub force: bool,
+}
+
+/// # Outcome of the redirect rules for a request
+#[derive(Debug, PartialEq)]
+pub enum Redirect {
+    /// Answer with a `Location` header
+    Location(u16, String),
+    /// Serve another path of the site with a status
+    Rewrite(u16, String),
+}
+
+/// # Netlify-style `_headers` and `_redirects` files of a site
+///
+/// The files are read again when their modification time changes, a missing file has no
+/// rules.
+pub struct SiteRules {
+    root: PathBuf,
+    state: Mutex<SiteRulesState>,
+}
+
+#[derive(Default)]
+struct SiteRulesState {
+    headers_modified: Option<SystemTime>,
+    redirects_modified: Option<SystemTime>,
+    header_rules: Vec<HeaderRule>,
+    redirect_rules: Vec<RedirectRule>,
+}
+
+impl SiteRules {
+    /// # Rule files of a site root
+    pub fn load(root: &Path) -> SiteRules {
+        let rules = SiteRules {
+            root: root.to_path_buf(),
+            state: Mutex::default(),
+        };
+        rules.reload_if_changed(&mut rules.state.lock().unwrap_or_else(|err| err.into_inner()));
+        rules
+    }
+
+    /// # Whether a URL path names one of the rule files, which are not served
+    pub fn is_rule_file(path: &str) -> bool {
+        let name = path.trim_start_matches('/');
+        name == HEADERS_FILE || name == REDIRECTS_FILE
+    }
+
+    /// # Headers of the rules matching a URL path, in file order
+    pub fn headers(&self, path: &str) -> Vec<(String, String)> {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        self.reload_if_changed(&mut state);
+        state
+            .header_rules
+            .iter()
+            .filter(|rule| match_path(&rule.pattern, path).is_some())
+            .flat_map(|rule| rule.headers.iter().cloned())
+            .collect()
+    }
+
+    /// # Apply the first redirect rule matching a URL path
+    ///
+    /// Rules without `!` are skipped when `exists` finds a file at the path. The query string
+    /// is kept unless the target sets its own.
+    pub fn redirect(
+        &self,
+        path: &str,
+        query: Option<&str>,
+        exists: impl Fn(&str) -> bool,
+    ) -> Option<Redirect> {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        self.reload_if_changed(&mut state);
+        let exists = exists(path);
+        let (rule, mut parameters) = state
+            .redirect_rules
+            .iter()
+            .filter(|rule| rule.force || !exists)
+            .find_map(|rule| Some((rule, match_path(&rule.from, path)?)))?;
+
+        let mut target = rule.to.clone();
+        // longer names first, so that `:id` does not replace the start of `:identifier`
+        parameters.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+        for (name, value) in parameters {
+            target = target.replace(format!(":{}", name).as_str(), value.as_str());
+        }
+        if let Some(query) = query.filter(|_| !target.contains('?')) {
+            target = format!("{}?{}", target, query);
+        }
+
+        Option::from(match rule.status {
+            301 | 302 | 303 | 307 | 308 => Redirect::Location(rule.status, target),
+            status => Redirect::Rewrite(status, target),
+        })
+    }
+
+    fn reload_if_changed(&self, state: &mut SiteRulesState) {
+        let headers_path = self.root.join(HEADERS_FILE);
+        let modified = modified_time(&headers_path);
+        if modified != state.headers_modified {
+            state.header_rules = fs::read_to_string(&headers_path)
+                .map(|contents| parse_headers(&contents))
+                .unwrap_or_default();
+            state.headers_modified = modified;
+        }
+
+        let redirects_path = self.root.join(REDIRECTS_FILE);
+        let modified = modified_time(&redirects_path);
+        if modified != state.redirects_modified {
+            state.redirect_rules = fs::read_to_string(&redirects_path)
+                .map(|contents| parse_redirects(&contents))
+                .unwrap_or_default();
+            state.redirects_modified = modified;
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// # Parse a `_headers` file
+///
+/// A line starting with `/` holds a path pattern, the indented `Name: value` lines after it
+/// its headers. Empty lines and `#` comments are skipped.
+pub fn parse_headers(contents: &str) -> Vec<HeaderRule> {
+    let mut rules: Vec<HeaderRule> = vec![];
+    for (number, line) in contents.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if !line.starts_with(char::is_whitespace) && trimmed.starts_with('/') {
+            rules.push(HeaderRule {
+                pattern: trimmed.to_string(),
+                headers: vec![],
+            });
+            continue;
+        }
+        match (rules.last_mut(), trimmed.split_once(':')) {
+            (Some(rule), Some((name, value))) if !name.trim().is_empty() => rule
+                .headers
+                .push((name.trim().to_string(), value.trim().to_string())),
+            _ => eprintln!("{}: invalid line {}", HEADERS_FILE, number + 1),
+        }
+    }
+
+    rules
+}
+
+/// # Parse a `_redirects` file
+///
+/// Every line holds `FROM TO [STATUS[!]]`, the status defaulting to 301. `!` applies the rule
+/// even when a file exists at the path. Empty lines and `#` comments are skipped.
+pub fn parse_redirects(contents: &str) -> Vec<RedirectRule> {
+    let mut rules = vec![];
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let status = fields
+            .get(2)
+            .map_or(Some((DEFAULT_REDIRECT_STATUS, false)), |field| {
+                let (status, force) = match field.strip_suffix('!') {
+                    Some(status) => (status, true),
+                    None => (*field, false),
+                };
+                status
+                    .parse::<u16>()
+                    .ok()
+                    .filter(|status| (200..=599).contains(status))
+                    .map(|status| (status, force))
+            });
+        let rule = match (fields.as_slice(), status) {
+            ([from, to, ..], Some((status, force)))
+                if fields.len() <= 3 && from.starts_with('/') =>
+            {
+                RedirectRule {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                    status,
+                    force,
+                }
+            }
+            _ => {
+                eprintln!("{}: invalid line {}", REDIRECTS_FILE, number + 1);
+                continue;
+            }
+        };
+        let is_redirect = matches!(rule.status, 301 | 302 | 303 | 307 | 308);
+        if !is_redirect && !rule.to.starts_with('/') {
+            eprintln!(
+                "{}: line {}: only paths of the site can be rewritten",
+                REDIRECTS_FILE,
+                number + 1
+            );
+            continue;
+        }
+        rules.push(rule);
+    }
+
+    rules
+}
+
+/// # Match a URL path against a pattern
+///
+/// `:name` segments match any single segment, and a trailing `*` matches the rest of the path
+/// as `splat`. Trailing slashes are ignored. Returns the matched placeholders.
+pub fn match_path(pattern: &str, path: &str) -> Option<Vec<(String, String)>> {
+    let pattern = trim_slash(pattern);
+    let path = trim_slash(path);
+    let mut parameters = vec![];
+    let mut rest = path;
+    let mut segments = pattern.split('/').peekable();
+    while let Some(segment) = segments.next() {
+        let is_last = segments.peek().is_none();
+        if is_last {
+            if let Some(literal) = segment.strip_suffix('*') {
+                let splat = rest.strip_prefix(literal)?;
+                parameters.push(("splat".to_string(), splat.to_string()));
+                return Some(parameters);
+            }
+        }
+
+        let (current, remaining) = rest.split_once('/').unwrap_or((rest, ""));
+        match segment.strip_prefix(':') {
+            Some(name) if !current.is_empty() => {
+                parameters.push((name.to_string(), current.to_string()))
+            }
+            Some(_) => return None,
+            None if segment == current => {}
+            None => return None,
+        }
+        if (is_last && !remaining.is_empty()) || (!is_last && !rest.contains('/')) {
+            return None;
+        }
+        rest = remaining;
+    }
+
+    Some(parameters)
+}
+
+fn trim_slash(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => "/",
+        path => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::site_rules::{
+        match_path, parse_headers, parse_redirects, Redirect, RedirectRule, SiteRules,
+    };
+    use std::{env, fs, process};
+
+    #[test]
+    fn test_match_path() {
+        assert_eq!(match_path("/about", "/about/"), Some(vec![]));
+        assert_eq!(match_path("/about", "/about/team"), None);
+        assert_eq!(match_path("/", "/"), Some(vec![]));
+        assert_eq!(
+            match_path("/blog/*", "/blog/2024/post"),
+            Some(vec![("splat".to_string(), "2024/post".to_string())])
+        );
+        assert_eq!(match_path("/*.js", "/app.1a2b.js"), None);
+        assert_eq!(
+            match_path("/users/:id/posts", "/users/42/posts"),
+            Some(vec![("id".to_string(), "42".to_string())])
+        );
+        assert_eq!(match_path("/users/:id/posts", "/users/42"), None);
+        assert_eq!(match_path("/users/:id", "/users/"), None);
+        assert_eq!(
+            match_path("/*", "/a/b"),
+            Some(vec![("splat".to_string(), "a/b".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let rules = parse_headers(
+            "# comment\n/*\n  X-Frame-Options: DENY\n\n/assets/*\n  Cache-Control: max-age=31536000\n  X-Test: a:b\n",
+        );
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].pattern, "/*");
+        assert_eq!(
+            rules[1].headers,
+            vec![
+                ("Cache-Control".to_string(), "max-age=31536000".to_string()),
+                ("X-Test".to_string(), "a:b".to_string())
+            ]
+        );
+
+        let rules = parse_redirects(
+            "/old /new\n/app/* /index.html 200\n# forced\n/a /b 302!\n/api/* https://example.com/:splat 200\nbad\n",
+        );
+        assert_eq!(
+            rules,
+            vec![
+                RedirectRule {
+                    from: "/old".to_string(),
+                    to: "/new".to_string(),
+                    status: 301,
+                    force: false
+                },
+                RedirectRule {
+                    from: "/app/*".to_string(),
+                    to: "/index.html".to_string(),
+                    status: 200,
+                    force: false
+                },
+                RedirectRule {
+                    from: "/a".to_string(),
+                    to: "/b".to_string(),
+                    status: 302,
+                    force: true
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_site_rules() {
+        let root = env::temp_dir().join(format!("http-mini-site-rules-{}", process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let rules = SiteRules::load(&root);
+        assert!(rules.headers("/a").is_empty());
+
+        fs::write(
+            root.join("_redirects"),
+            "/news/:year/* /blog/:year/:splat 301\n/exists /elsewhere\n/app/* /app.html 200\n",
+        )
+        .unwrap();
+        fs::write(root.join("_headers"), "/app/*\n  X-App: 1\n").unwrap();
+        assert_eq!(
+            rules.redirect("/news/2024/a/b", Some("x=1"), |_| false),
+            Some(Redirect::Location(301, "/blog/2024/a/b?x=1".to_string()))
+        );
+        assert_eq!(rules.redirect("/exists", None, |_| true), None);
+        assert_eq!(
+            rules.redirect("/app/settings", None, |_| false),
+            Some(Redirect::Rewrite(200, "/app.html".to_string()))
+        );
+        assert_eq!(
+            rules.headers("/app/settings"),
+            vec![("X-App".to_string(), "1".to_string())]
+        );
+        assert!(SiteRules::is_rule_file("/_redirects"));
+
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(rules.redirect("/app/settings", None, |_| false), None);
+    }
+}
@@ -0,0 +1,43 @@
+use crate::traits::connection::Connection;
+use std::io::{stdin, stdout, Read, Stdin, Stdout, Write};
+
+/// # Connection over stdin/stdout
+///
+/// Used by inetd-style services where the connection is passed as the standard streams.
+pub struct StdioStream {
+    stdin: Stdin,
+    stdout: Stdout,
+}
+
+impl StdioStream {
+    pub fn new() -> StdioStream {
+        StdioStream {
+            stdin: stdin(),
+            stdout: stdout(),
+        }
+    }
+}
+
+impl Default for StdioStream {
+    fn default() -> Self {
+        StdioStream::new()
+    }
+}
+
+impl Read for StdioStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.stdin.read(buf)
+    }
+}
+
+impl Write for StdioStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.stdout.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stdout.flush()
+    }
+}
+
+impl Connection for StdioStream {}
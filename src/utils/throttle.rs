@@ -0,0 +1,333 @@
+use crate::errors::invalid_argument::InvalidArgumentError;
+use crate::utils::mount;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Largest write at once, in bytes
+const MAX_CHUNK_SIZE: u64 = 16384;
+/// Buckets kept before idle ones are dropped
+const MAX_TRACKED_CLIENTS: usize = 4096;
+
+/// # Response bandwidth limits, in bytes per second
+///
+/// Every response is limited by its own rate, the rate of its client address and the rate of
+/// the whole server.
+#[derive(Default)]
+pub struct Bandwidth {
+    /// Rate of a response, unless a path rule applies
+    pub response_rate: Option<u64>,
+    pub paths: Vec<PathRate>,
+    pub client_rate: Option<u64>,
+    pub total_rate: Option<u64>,
+    clients: Mutex<HashMap<IpAddr, Bucket>>,
+    total: Mutex<Option<Bucket>>,
+}
+
+/// # Response rate of a URL prefix, `None` for unlimited
+#[derive(Debug, PartialEq)]
+pub struct PathRate {
+    /// URL path prefix without a trailing slash
+    pub prefix: String,
+    pub rate: Option<u64>,
+}
+
+/// # Parse a `--throttle` specification
+///
+/// Format: `RATE[,path=/PREFIX]`, i.e. `64k,path=/videos` or `off,path=/docs`. Returns the rate
+/// and the prefix, if any.
+pub fn parse_throttle(spec: &str) -> Result<(Option<u64>, Option<String>), InvalidArgumentError> {
+    let mut parts = spec.split(',');
+    let rate = parse_rate(parts.next().unwrap_or_default())?;
+    let mut prefix = None;
+    for option in parts {
+        match option.split_once('=') {
+            Some(("path", value)) if value.starts_with('/') => {
+                prefix = Option::from(value.trim_end_matches('/').to_string())
+            }
+            _ => {
+                return Err(InvalidArgumentError::new(
+                    spec,
+                    format!("invalid throttle option {}", option).as_str(),
+                ));
+            }
+        }
+    }
+
+    Ok((rate, prefix))
+}
+
+/// # Parse a rate in bytes per second
+///
+/// Format: a number with an optional `k`, `m` or `g` binary suffix, i.e. `512k`. `off` is
+/// unlimited.
+pub fn parse_rate(value: &str) -> Result<Option<u64>, InvalidArgumentError> {
+    if value == "off" {
+        return Ok(None);
+    }
+    let (number, multiplier) = match value.to_ascii_lowercase().chars().last() {
+        Some('k') => (&value[..value.len() - 1], 1 << 10),
+        Some('m') => (&value[..value.len() - 1], 1 << 20),
+        Some('g') => (&value[..value.len() - 1], 1 << 30),
+        _ => (value, 1),
+    };
+    match number.parse::<u64>() {
+        Ok(rate) if rate > 0 => Ok(Option::from(rate.saturating_mul(multiplier))),
+        _ => Err(InvalidArgumentError::new(value, "invalid rate")),
+    }
+}
+
+/// # Token bucket of bytes
+///
+/// Sending may run into debt, which the next writes wait for.
+pub struct Bucket {
+    rate: u64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    pub fn new(rate: u64) -> Bucket {
+        Bucket {
+            rate,
+            tokens: chunk_size(rate) as f64,
+            updated: Instant::now(),
+        }
+    }
+
+    /// # Take `size` bytes, returns the time to wait before sending them
+    pub fn reserve(&mut self, size: usize, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(chunk_size(self.rate) as f64);
+        self.updated = now;
+        self.tokens -= size as f64;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate as f64)
+        }
+    }
+
+    /// # Whether the bucket is full again
+    fn is_idle(&self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens + elapsed * self.rate as f64 >= chunk_size(self.rate) as f64
+    }
+}
+
+/// # Bytes written at once for a rate, about an eighth of a second
+fn chunk_size(rate: u64) -> u64 {
+    (rate / 8).clamp(1, MAX_CHUNK_SIZE)
+}
+
+impl Bandwidth {
+    /// # Whether any limit is set
+    pub fn is_limited(&self) -> bool {
+        self.response_rate.is_some()
+            || self.paths.iter().any(|path| path.rate.is_some())
+            || self.client_rate.is_some()
+            || self.total_rate.is_some()
+    }
+
+    /// # Rate of a response to a URL path
+    ///
+    /// The longest prefix matching whole path segments wins.
+    pub fn response_rate(&self, path: &str) -> Option<u64> {
+        match self
+            .paths
+            .iter()
+            .filter(|rule| mount::strip_path_prefix(path, &rule.prefix).is_some())
+            .max_by_key(|rule| rule.prefix.len())
+        {
+            Some(rule) => rule.rate,
+            None => self.response_rate,
+        }
+    }
+
+    /// # Largest write at once for a response
+    pub fn chunk_size(&self, response_rate: Option<u64>) -> usize {
+        [response_rate, self.client_rate, self.total_rate]
+            .iter()
+            .flatten()
+            .map(|rate| chunk_size(*rate))
+            .min()
+            .unwrap_or(MAX_CHUNK_SIZE) as usize
+    }
+
+    /// # Take `size` bytes from the client and server buckets
+    pub fn reserve(&self, client: Option<IpAddr>, size: usize, now: Instant) -> Duration {
+        let mut wait = Duration::ZERO;
+        if let (Some(rate), Some(client)) = (self.client_rate, client) {
+            let mut clients = self.clients.lock().unwrap_or_else(|err| err.into_inner());
+            if clients.len() >= MAX_TRACKED_CLIENTS {
+                clients.retain(|_, bucket| !bucket.is_idle(now));
+            }
+            let bucket = clients
+                .entry(client.to_canonical())
+                .or_insert_with(|| Bucket::new(rate));
+            wait = wait.max(bucket.reserve(size, now));
+        }
+        if let Some(rate) = self.total_rate {
+            let mut total = self.total.lock().unwrap_or_else(|err| err.into_inner());
+            wait = wait.max(
+                total
+                    .get_or_insert_with(|| Bucket::new(rate))
+                    .reserve(size, now),
+            );
+        }
+
+        wait
+    }
+}
+
+/// # Bandwidth limits of a single response
+pub struct ResponseThrottle {
+    client: Option<IpAddr>,
+    bucket: Option<Bucket>,
+    /// Largest write at once
+    pub chunk_size: usize,
+}
+
+impl ResponseThrottle {
+    pub fn new(
+        bandwidth: &Bandwidth,
+        client: Option<IpAddr>,
+        response_rate: Option<u64>,
+    ) -> ResponseThrottle {
+        ResponseThrottle {
+            client,
+            bucket: response_rate.map(Bucket::new),
+            chunk_size: bandwidth.chunk_size(response_rate),
+        }
+    }
+
+    /// # Take `size` bytes from all buckets, returns the time to wait before sending them
+    pub fn reserve(&mut self, bandwidth: &Bandwidth, size: usize) -> Duration {
+        let now = Instant::now();
+        let wait = bandwidth.reserve(self.client, size, now);
+        match &mut self.bucket {
+            Some(bucket) => wait.max(bucket.reserve(size, now)),
+            None => wait,
+        }
+    }
+}
+
+/// # Writer keeping to the bandwidth limits of a response
+pub struct ThrottledWriter<'a, W: Write> {
+    inner: W,
+    bandwidth: &'a Bandwidth,
+    throttle: ResponseThrottle,
+}
+
+impl<'a, W: Write> ThrottledWriter<'a, W> {
+    pub fn new(
+        inner: W,
+        bandwidth: &'a Bandwidth,
+        client: Option<IpAddr>,
+        response_rate: Option<u64>,
+    ) -> ThrottledWriter<'a, W> {
+        ThrottledWriter {
+            inner,
+            bandwidth,
+            throttle: ResponseThrottle::new(bandwidth, client, response_rate),
+        }
+    }
+}
+
+impl<W: Write> Write for ThrottledWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.bandwidth.is_limited() {
+            return self.inner.write(buf);
+        }
+
+        let size = buf.len().min(self.throttle.chunk_size);
+        let wait = self.throttle.reserve(self.bandwidth, size);
+        if !wait.is_zero() {
+            // the data already written has to reach the client before waiting
+            self.inner.flush()?;
+            thread::sleep(wait);
+        }
+
+        self.inner.write(&buf[..size])
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::throttle::{
+        parse_rate, parse_throttle, Bandwidth, Bucket, PathRate, ThrottledWriter,
+    };
+    use std::io::Write;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("1000").unwrap(), Some(1000));
+        assert_eq!(parse_rate("512k").unwrap(), Some(512 * 1024));
+        assert_eq!(parse_rate("2M").unwrap(), Some(2 * 1024 * 1024));
+        assert_eq!(parse_rate("off").unwrap(), None);
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("fast").is_err());
+        assert_eq!(
+            parse_throttle("64k,path=/videos/").unwrap(),
+            (Some(65536), Some("/videos".to_string()))
+        );
+        assert!(parse_throttle("64k,path=videos").is_err());
+    }
+
+    #[test]
+    fn test_bucket() {
+        let now = Instant::now();
+        let mut bucket = Bucket::new(800);
+        assert_eq!(bucket.reserve(100, now), Duration::ZERO);
+        assert_eq!(bucket.reserve(400, now), Duration::from_millis(500));
+        assert_eq!(
+            bucket.reserve(100, now + Duration::from_millis(500)),
+            Duration::from_millis(125)
+        );
+    }
+
+    #[test]
+    fn test_response_rate() {
+        let bandwidth = Bandwidth {
+            response_rate: Option::from(1000),
+            paths: vec![
+                PathRate {
+                    prefix: "/videos".to_string(),
+                    rate: Option::from(64),
+                },
+                PathRate {
+                    prefix: "/videos/free".to_string(),
+                    rate: None,
+                },
+            ],
+            ..Bandwidth::default()
+        };
+        assert_eq!(bandwidth.response_rate("/index.html"), Some(1000));
+        assert_eq!(bandwidth.response_rate("/videos/a.mp4"), Some(64));
+        assert_eq!(bandwidth.response_rate("/videos/free/a.mp4"), None);
+        assert_eq!(bandwidth.chunk_size(Option::from(64)), 8);
+    }
+
+    #[test]
+    fn test_throttled_writer() {
+        let bandwidth = Bandwidth {
+            total_rate: Option::from(4000),
+            ..Bandwidth::default()
+        };
+        let mut output = vec![];
+        let started = Instant::now();
+        let mut writer = ThrottledWriter::new(&mut output, &bandwidth, None, None);
+        writer.write_all(&[0u8; 1500]).unwrap();
+        // 500 bytes right away, then 1000 bytes at 4000 bytes per second
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert_eq!(output.len(), 1500);
+    }
+}
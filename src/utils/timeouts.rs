@@ -0,0 +1,117 @@
+use crate::errors::invalid_argument::InvalidArgumentError;
+use crate::traits::connection::Connection;
+use std::io::{Error, ErrorKind, Read};
+use std::time::{Duration, Instant};
+
+/// # Connection timeouts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timeouts {
+    /// Wait for the first byte of a request, or between HTTP/2 frames
+    pub idle: Option<Duration>,
+    /// Receive the request line and headers, from their first byte
+    pub read: Option<Duration>,
+    /// Every blocked write of the response
+    pub write: Option<Duration>,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            idle: Option::from(Duration::from_secs(60)),
+            read: Option::from(Duration::from_secs(30)),
+            write: Option::from(Duration::from_secs(60)),
+        }
+    }
+}
+
+/// # Parse a timeout in seconds, `0` disables it
+pub fn parse_seconds(value: &str) -> Result<Option<Duration>, InvalidArgumentError> {
+    match value.parse::<u64>() {
+        Ok(0) => Ok(None),
+        Ok(seconds) => Ok(Option::from(Duration::from_secs(seconds))),
+        Err(_) => Err(InvalidArgumentError::new(
+            value,
+            "invalid number of seconds",
+        )),
+    }
+}
+
+/// # Reader of a request head
+///
+/// The first read waits up to the idle timeout. The whole request head must then arrive
+/// within the read timeout, however slowly the client sends it.
+pub struct DeadlineReader<'a, C: Connection> {
+    stream: &'a mut C,
+    timeouts: Timeouts,
+    deadline: Option<Instant>,
+    started: bool,
+}
+
+impl<'a, C: Connection> DeadlineReader<'a, C> {
+    pub fn new(stream: &'a mut C, timeouts: Timeouts) -> DeadlineReader<'a, C> {
+        DeadlineReader {
+            stream,
+            timeouts,
+            deadline: None,
+            started: false,
+        }
+    }
+}
+
+impl<C: Connection> Read for DeadlineReader<'_, C> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let timeout = match (self.started, self.deadline) {
+            (false, _) => self.timeouts.idle,
+            (true, Some(deadline)) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(Error::from(ErrorKind::TimedOut));
+                }
+                Option::from(remaining)
+            }
+            (true, None) => None,
+        };
+        self.stream.set_read_timeout(timeout)?;
+
+        let read = self.stream.read(buf)?;
+        if !self.started && read > 0 {
+            self.started = true;
+            self.deadline = self.timeouts.read.map(|timeout| Instant::now() + timeout);
+        }
+
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::mock_connection::MockConnection;
+    use crate::utils::timeouts::{parse_seconds, DeadlineReader, Timeouts};
+    use std::io::{ErrorKind, Read};
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_seconds() {
+        assert_eq!(parse_seconds("5").unwrap(), Some(Duration::from_secs(5)));
+        assert_eq!(parse_seconds("0").unwrap(), None);
+        assert!(parse_seconds("-1").is_err());
+    }
+
+    #[test]
+    fn test_deadline_reader() {
+        let mut connection = MockConnection::new(b"GET / HTTP/1.1\r\n");
+        let timeouts = Timeouts {
+            idle: None,
+            read: Option::from(Duration::from_millis(1)),
+            write: None,
+        };
+        let mut reader = DeadlineReader::new(&mut connection, timeouts);
+        let mut buf = [0u8; 4];
+        assert_eq!(reader.read(&mut buf).unwrap(), 4);
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(
+            reader.read(&mut buf).unwrap_err().kind(),
+            ErrorKind::TimedOut
+        );
+    }
+}
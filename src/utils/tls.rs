@@ -0,0 +1,506 @@
+use crate::traits::connection::Connection;
+use crate::utils::app::TlsParams;
+use crate::utils::digest::{sha256, to_fingerprint};
+use crate::utils::x509::{self, ClientIdentity};
+use crate::utils::{glob, self_signed};
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{RootCertStore, ServerConfig, ServerConnection, StreamOwned};
+use std::fs;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Incremented on every SIGHUP, certificates are reloaded when it changes
+static RELOAD_GENERATION: AtomicUsize = AtomicUsize::new(0);
+#[cfg(unix)]
+static RELOAD_HANDLER: std::sync::Once = std::sync::Once::new();
+
+/// # Reload certificates on SIGHUP
+#[cfg(unix)]
+pub fn install_reload_handler() {
+    extern "C" fn on_sighup(_: libc::c_int) {
+        RELOAD_GENERATION.fetch_add(1, Ordering::SeqCst);
+    }
+
+    RELOAD_HANDLER.call_once(|| {
+        // SAFETY: the handler only touches an atomic, which is async-signal-safe
+        unsafe {
+            libc::signal(
+                libc::SIGHUP,
+                on_sighup as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    });
+}
+
+/// # Convert a TLS error to an IO error
+fn to_io_error(err: rustls::Error) -> Error {
+    Error::new(ErrorKind::InvalidData, err)
+}
+
+/// # Load the PEM certificates of a file
+fn load_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, Error> {
+    let certificates = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| pem_error(path, err))?;
+    if certificates.is_empty() {
+        return Err(pem_error(path, rustls::pki_types::pem::Error::NoItemsFound));
+    }
+
+    Ok(certificates)
+}
+
+fn pem_error(path: &Path, err: rustls::pki_types::pem::Error) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), err),
+    )
+}
+
+/// # Load a PEM certificate chain and private key
+fn load_certified_key(
+    provider: &CryptoProvider,
+    cert_path: &Path,
+    key_path: &Path,
+) -> Result<CertifiedKey, Error> {
+    let cert_chain = load_certificates(cert_path)?;
+    let key = PrivateKeyDer::from_pem_file(key_path).map_err(|err| pem_error(key_path, err))?;
+    let signing_key = provider
+        .key_provider
+        .load_private_key(key)
+        .map_err(to_io_error)?;
+
+    Ok(CertifiedKey::new(cert_chain, signing_key))
+}
+
+/// # Certificate of a server name
+#[derive(Debug)]
+struct SniEntry {
+    /// Server name, `*` and `?` wildcards are supported
+    pattern: String,
+    certified_key: Arc<CertifiedKey>,
+}
+
+/// # Certificates of a listener
+#[derive(Debug)]
+struct CertificateSet {
+    default: Arc<CertifiedKey>,
+    sni: Vec<SniEntry>,
+}
+
+impl CertificateSet {
+    /// # Load the default certificate and the certificates of the SNI map
+    fn load(
+        provider: &CryptoProvider,
+        cert_path: &Path,
+        key_path: &Path,
+        sni_map: Option<&Path>,
+    ) -> Result<CertificateSet, Error> {
+        let default = Arc::new(load_certified_key(provider, cert_path, key_path)?);
+        let mut sni = vec![];
+        if let Some(sni_map) = sni_map {
+            let contents = fs::read_to_string(sni_map)
+                .map_err(|err| Error::new(err.kind(), format!("{}: {}", sni_map.display(), err)))?;
+            let base_dir = sni_map.parent().unwrap_or(Path::new(""));
+            for (pattern, cert_path, key_path) in parse_sni_map(&contents, base_dir)? {
+                sni.push(SniEntry {
+                    pattern,
+                    certified_key: Arc::new(load_certified_key(provider, &cert_path, &key_path)?),
+                });
+            }
+        }
+
+        Ok(CertificateSet { default, sni })
+    }
+
+    /// # Certificate for a server name
+    ///
+    /// An exact name has precedence over wildcard patterns, otherwise the first matching
+    /// pattern wins. Clients without a matching server name get the default certificate.
+    fn get(&self, server_name: Option<&str>) -> Arc<CertifiedKey> {
+        let server_name = match server_name {
+            Some(server_name) => server_name.to_lowercase(),
+            None => return Arc::clone(&self.default),
+        };
+
+        let entry = self
+            .sni
+            .iter()
+            .find(|entry| entry.pattern == server_name)
+            .or_else(|| {
+                self.sni
+                    .iter()
+                    .find(|entry| matches_server_name(&entry.pattern, &server_name))
+            });
+        match entry {
+            Some(entry) => Arc::clone(&entry.certified_key),
+            None => Arc::clone(&self.default),
+        }
+    }
+}
+
+/// # Whether a server name pattern matches a name
+///
+/// Wildcards only match within the leftmost DNS label, as in RFC 6125: `*.example.com` matches
+/// `a.example.com` but neither `example.com` nor `a.b.example.com`.
+fn matches_server_name(pattern: &str, server_name: &str) -> bool {
+    let (Some((pattern_label, pattern_rest)), Some((label, rest))) =
+        (pattern.split_once('.'), server_name.split_once('.'))
+    else {
+        return pattern == server_name;
+    };
+
+    pattern_rest == rest && glob::matches(pattern_label, label)
+}
+
+/// # Whether a server name pattern is valid
+///
+/// Wildcards are only allowed in the leftmost label, followed by at least two labels.
+fn is_valid_server_name(pattern: &str) -> bool {
+    match pattern.split_once('.') {
+        Some((label, rest)) if label.contains(['*', '?']) => {
+            !rest.contains(['*', '?']) && rest.contains('.')
+        }
+        _ => !pattern.contains(['*', '?']),
+    }
+}
+
+/// # Parse an SNI map
+///
+/// One `SERVER-NAME CERT KEY` entry per line, i.e. `*.example.com wildcard.pem wildcard.key`.
+/// Wildcards are only allowed in the leftmost label of the name. Empty lines and lines starting
+/// with `#` are ignored. Relative paths are relative to
+/// `base_dir`, the directory of the map file.
+fn parse_sni_map(
+    contents: &str,
+    base_dir: &Path,
+) -> Result<Vec<(String, PathBuf, PathBuf)>, Error> {
+    let mut entries = vec![];
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match line.split_whitespace().collect::<Vec<&str>>()[..] {
+            [pattern, _, _] if !is_valid_server_name(pattern) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "SNI map line {}: wildcards are only allowed in the leftmost label of a domain",
+                        index + 1
+                    ),
+                ));
+            }
+            [pattern, cert_path, key_path] => entries.push((
+                pattern.to_lowercase(),
+                base_dir.join(cert_path),
+                base_dir.join(key_path),
+            )),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("SNI map line {}: expected SERVER-NAME CERT KEY", index + 1),
+                ));
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+/// # Certificate resolver which reloads the certificates on SIGHUP
+///
+/// Certificates failing to load keep the previous ones in use.
+#[derive(Debug)]
+struct ReloadingCertResolver {
+    provider: Arc<CryptoProvider>,
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    sni_map: Option<PathBuf>,
+    generation: AtomicUsize,
+    current: RwLock<Arc<CertificateSet>>,
+}
+
+impl ReloadingCertResolver {
+    fn new(
+        provider: Arc<CryptoProvider>,
+        cert_path: &Path,
+        key_path: &Path,
+        sni_map: Option<&Path>,
+    ) -> Result<ReloadingCertResolver, Error> {
+        let certificates = CertificateSet::load(&provider, cert_path, key_path, sni_map)?;
+
+        Ok(ReloadingCertResolver {
+            provider,
+            cert_path: cert_path.to_path_buf(),
+            key_path: key_path.to_path_buf(),
+            sni_map: sni_map.map(Path::to_path_buf),
+            generation: AtomicUsize::new(RELOAD_GENERATION.load(Ordering::SeqCst)),
+            current: RwLock::new(Arc::new(certificates)),
+        })
+    }
+
+    fn reload_if_requested(&self) {
+        let generation = RELOAD_GENERATION.load(Ordering::SeqCst);
+        if self.generation.swap(generation, Ordering::SeqCst) == generation {
+            return;
+        }
+
+        match CertificateSet::load(
+            &self.provider,
+            &self.cert_path,
+            &self.key_path,
+            self.sni_map.as_deref(),
+        ) {
+            Ok(certificates) => {
+                *self.current.write().unwrap() = Arc::new(certificates);
+                println!("Reloaded TLS certificate {}", self.cert_path.display());
+            }
+            Err(err) => println!("TLS certificate reload failed: {}", err),
+        }
+    }
+}
+
+impl ResolvesServerCert for ReloadingCertResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.reload_if_requested();
+        Some(self.current.read().unwrap().get(client_hello.server_name()))
+    }
+}
+
+/// # TLS acceptor of a listener
+pub struct TlsAcceptor {
+    config: Arc<ServerConfig>,
+    /// SHA-256 fingerprint of the default certificate
+    pub fingerprint: String,
+    /// Certificate of the local CA to be trusted by clients, when one is used
+    pub ca_cert: Option<PathBuf>,
+    /// Allowed client certificate common names
+    client_subjects: Vec<String>,
+    /// Allowed client certificate alternative names
+    client_sans: Vec<String>,
+}
+
+impl TlsAcceptor {
+    /// # Build the TLS configuration of a listener
+    ///
+    /// Only TLS 1.3 and TLS 1.2 with forward secret AEAD cipher suites are offered.
+    /// With a client CA, client certificates are verified against it.
+    pub fn new(params: &TlsParams) -> Result<TlsAcceptor, Error> {
+        let (cert_path, key_path, ca_cert) = if params.self_signed {
+            let certificate = self_signed::get_certificate(params.local_ca)?;
+            (certificate.cert, certificate.key, certificate.ca_cert)
+        } else {
+            match (&params.cert, &params.key) {
+                (Some(cert_path), Some(key_path)) => (cert_path.clone(), key_path.clone(), None),
+                _ => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "missing TLS certificate or key",
+                    ));
+                }
+            }
+        };
+
+        let provider = Arc::new(ring::default_provider());
+        let versions: &[&rustls::SupportedProtocolVersion] = if params.tls13_only {
+            &[&rustls::version::TLS13]
+        } else {
+            &[&rustls::version::TLS13, &rustls::version::TLS12]
+        };
+        let resolver = ReloadingCertResolver::new(
+            Arc::clone(&provider),
+            &cert_path,
+            &key_path,
+            params.sni_map.as_deref(),
+        )?;
+        let fingerprint = to_fingerprint(&sha256(
+            resolver
+                .current
+                .read()
+                .unwrap()
+                .default
+                .end_entity_cert()
+                .map_err(to_io_error)?,
+        ));
+
+        let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+            .with_protocol_versions(versions)
+            .map_err(to_io_error)?;
+        let builder = match &params.client_ca {
+            Some(client_ca) => {
+                let mut roots = RootCertStore::empty();
+                for certificate in load_certificates(client_ca)? {
+                    roots.add(certificate).map_err(to_io_error)?;
+                }
+                let mut verifier =
+                    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+                if params.client_auth_optional {
+                    verifier = verifier.allow_unauthenticated();
+                }
+                let verifier = verifier
+                    .build()
+                    .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let mut config = builder.with_cert_resolver(Arc::new(resolver));
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Ok(TlsAcceptor {
+            config: Arc::new(config),
+            fingerprint,
+            ca_cert,
+            client_subjects: params.client_subjects.clone(),
+            client_sans: params.client_sans.clone(),
+        })
+    }
+
+    /// # Perform the TLS handshake
+    pub fn accept<C: Connection>(&self, mut stream: C) -> Result<TlsStream<C>, Error> {
+        let mut connection =
+            ServerConnection::new(Arc::clone(&self.config)).map_err(to_io_error)?;
+        while connection.is_handshaking() {
+            connection.complete_io(&mut stream)?;
+        }
+
+        let client_certificate = connection
+            .peer_certificates()
+            .and_then(|certificates| certificates.first());
+        let client_identity =
+            client_certificate.and_then(|certificate| x509::get_identity(certificate));
+        let client_certificate = client_certificate.is_some();
+
+        Ok(TlsStream {
+            inner: StreamOwned::new(connection, stream),
+            client_certificate,
+            client_identity,
+        })
+    }
+
+    /// # Check the client of a connection against the allow-lists
+    ///
+    /// Clients without a certificate are allowed, as they passed the handshake only when
+    /// client authentication is optional. Certificates which cannot be parsed are denied by
+    /// the allow-lists.
+    pub fn is_client_allowed<C: Connection>(&self, stream: &TlsStream<C>) -> bool {
+        !stream.client_certificate
+            || x509::is_certificate_allowed(
+                stream.client_identity.as_ref(),
+                &self.client_subjects,
+                &self.client_sans,
+            )
+    }
+}
+
+/// # Connection encrypted with TLS
+pub struct TlsStream<C: Connection> {
+    inner: StreamOwned<ServerConnection, C>,
+    /// Whether the client presented a verified certificate
+    client_certificate: bool,
+    /// Identity of the verified client certificate, `None` when it could not be parsed
+    client_identity: Option<ClientIdentity>,
+}
+
+impl<C: Connection> Read for TlsStream<C> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<C: Connection> Write for TlsStream<C> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<C: Connection> Connection for TlsStream<C> {
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.inner.sock.peer_addr()
+    }
+
+    fn client_identity(&self) -> Option<ClientIdentity> {
+        self.client_identity.clone()
+    }
+
+    fn alpn_protocol(&self) -> Option<Vec<u8>> {
+        self.inner.conn.alpn_protocol().map(<[u8]>::to_vec)
+    }
+
+    fn is_tls(&self) -> bool {
+        true
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.inner.sock.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.inner.sock.set_write_timeout(timeout)
+    }
+
+    fn abort(&self) {
+        self.inner.sock.abort()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::tls::{matches_server_name, parse_sni_map};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_parse_sni_map() {
+        let entries = parse_sni_map(
+            "# comment\n\nExample.com a.pem a.key\n*.example.org /etc/b.pem /etc/b.key\n",
+            Path::new("/srv/tls"),
+        )
+        .unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                (
+                    "example.com".to_string(),
+                    PathBuf::from("/srv/tls/a.pem"),
+                    PathBuf::from("/srv/tls/a.key")
+                ),
+                (
+                    "*.example.org".to_string(),
+                    PathBuf::from("/etc/b.pem"),
+                    PathBuf::from("/etc/b.key")
+                ),
+            ]
+        );
+
+        assert!(parse_sni_map("example.com a.pem", Path::new("")).is_err());
+        assert!(parse_sni_map("a.*.example.com a.pem a.key", Path::new("")).is_err());
+        assert!(parse_sni_map("*.com a.pem a.key", Path::new("")).is_err());
+        assert!(parse_sni_map("* a.pem a.key", Path::new("")).is_err());
+    }
+
+    #[test]
+    fn test_matches_server_name() {
+        assert!(matches_server_name("*.example.com", "a.example.com"));
+        assert!(matches_server_name("w*.example.com", "www.example.com"));
+        assert!(!matches_server_name("*.example.com", "a.b.example.com"));
+        assert!(!matches_server_name("*.example.com", "example.com"));
+        assert!(!matches_server_name(
+            "*.example.com",
+            "a.example.com.evil.org"
+        ));
+        assert!(matches_server_name("example.com", "example.com"));
+    }
+}
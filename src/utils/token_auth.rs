@@ -0,0 +1,182 @@
+use crate::errors::invalid_argument::InvalidArgumentError;
+use crate::utils::digest::constant_time_eq;
+use crate::utils::http_request::HttpRequest;
+use crate::utils::mount;
+use std::{env, fs};
+
+const ENV_PREFIX: &str = "env:";
+
+/// # Bearer token authentication of a URL prefix (RFC 6750)
+pub struct TokenAuth {
+    /// URL path prefix without a trailing slash, empty for the whole site
+    pub prefix: String,
+    /// Tokens with the name logged for them
+    tokens: Vec<(String, String)>,
+}
+
+impl TokenAuth {
+    /// # Parse a bearer token specification
+    ///
+    /// Format: `SOURCE[,path=/PREFIX]`, where `SOURCE` is a file or `env:VARIABLE`, i.e.
+    /// `/etc/http-mini/tokens,path=/builds`. Every line of the source holds a token, optionally
+    /// followed by a name for the access log.
+    pub fn parse(spec: &str) -> Result<TokenAuth, InvalidArgumentError> {
+        let mut parts = spec.split(',');
+        let source = parts.next().unwrap_or_default();
+        let mut prefix = String::new();
+        for option in parts {
+            match option.split_once('=') {
+                Some(("path", value)) if value.starts_with('/') => {
+                    prefix = value.trim_end_matches('/').to_string()
+                }
+                _ => {
+                    return Err(InvalidArgumentError::new(
+                        spec,
+                        format!("invalid bearer token option {}", option).as_str(),
+                    ));
+                }
+            }
+        }
+
+        let tokens = parse_tokens(&read_source(source)?);
+        if tokens.is_empty() {
+            return Err(InvalidArgumentError::new(spec, "no bearer tokens"));
+        }
+
+        Ok(TokenAuth { prefix, tokens })
+    }
+
+    /// # Name of the token of a request
+    ///
+    /// Every token is compared, in constant time.
+    pub fn authenticate(&self, request: &HttpRequest) -> Option<String> {
+        let token = bearer_token(request)?;
+        let mut name = None;
+        for (known, known_name) in &self.tokens {
+            if constant_time_eq(known.as_bytes(), token.as_bytes()) {
+                name = Option::from(known_name.clone());
+            }
+        }
+
+        name
+    }
+}
+
+/// # Read a secret from a file or from an `env:VARIABLE`
+pub fn read_source(source: &str) -> Result<String, InvalidArgumentError> {
+    if source.is_empty() {
+        return Err(InvalidArgumentError::new(source, "missing secret source"));
+    }
+    match source.strip_prefix(ENV_PREFIX) {
+        Some(variable) => env::var(variable)
+            .map_err(|err| InvalidArgumentError::new(source, err.to_string().as_str())),
+        None => fs::read_to_string(source)
+            .map_err(|err| InvalidArgumentError::new(source, err.to_string().as_str())),
+    }
+}
+
+/// # Parse `TOKEN [NAME]` lines
+///
+/// Empty lines and `#` comments are skipped. The name defaults to `token` followed by the line
+/// number.
+pub fn parse_tokens(contents: &str) -> Vec<(String, String)> {
+    contents
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let (token, name) = match line.split_once(char::is_whitespace) {
+                Some((token, name)) => (token, name.trim().to_string()),
+                None => (line, format!("token{}", index + 1)),
+            };
+            Some((token.to_string(), name))
+        })
+        .collect()
+}
+
+/// # Token authentication protecting a URL path
+///
+/// The longest prefix matching whole path segments wins.
+pub fn select<'a>(rules: &'a [TokenAuth], path: &str) -> Option<&'a TokenAuth> {
+    rules
+        .iter()
+        .filter(|rule| mount::strip_path_prefix(path, &rule.prefix).is_some())
+        .max_by_key(|rule| rule.prefix.len())
+}
+
+/// # Token of the `Authorization: Bearer` header
+pub fn bearer_token(request: &HttpRequest) -> Option<&str> {
+    let (scheme, token) = request.header("Authorization")?.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("Bearer") {
+        return None;
+    }
+
+    Some(token.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::traits::stream_trait::StreamTrait;
+    use crate::utils::http_request::HttpRequest;
+    use crate::utils::token_auth::{bearer_token, parse_tokens, select, TokenAuth};
+    use std::io::Cursor;
+    use std::{env, fs, process};
+
+    fn request(authorization: &str) -> HttpRequest {
+        Cursor::new(format!(
+            "GET / HTTP/1.1\r\nHost: a\r\nAuthorization: {}\r\n\r\n",
+            authorization
+        ))
+        .parse()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_tokens() {
+        assert_eq!(
+            parse_tokens("# ci\nabc123 deploy bot\n\n  xyz  \n"),
+            vec![
+                ("abc123".to_string(), "deploy bot".to_string()),
+                ("xyz".to_string(), "token4".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bearer_token() {
+        assert_eq!(bearer_token(&request("Bearer abc123")), Some("abc123"));
+        assert_eq!(bearer_token(&request("bearer  abc123 ")), Some("abc123"));
+        assert_eq!(bearer_token(&request("Basic YWxpY2U6c2VjcmV0")), None);
+    }
+
+    #[test]
+    fn test_parse_and_authenticate() {
+        let path = env::temp_dir().join(format!("http-mini-tokens-{}", process::id()));
+        fs::write(&path, "abc123 ci\n").unwrap();
+        let rules =
+            vec![TokenAuth::parse(format!("{},path=/builds/", path.display()).as_str()).unwrap()];
+        assert_eq!(rules[0].prefix, "/builds");
+        assert_eq!(
+            rules[0].authenticate(&request("Bearer abc123")),
+            Some("ci".to_string())
+        );
+        assert_eq!(rules[0].authenticate(&request("Bearer abc12")), None);
+        assert!(select(&rules, "/builds/a.zip").is_some());
+        assert!(select(&rules, "/").is_none());
+
+        env::set_var("HTTP_MINI_TEST_TOKEN", "from-env");
+        let rule = TokenAuth::parse("env:HTTP_MINI_TEST_TOKEN").unwrap();
+        assert_eq!(
+            rule.authenticate(&request("Bearer from-env")),
+            Some("token1".to_string())
+        );
+
+        assert!(TokenAuth::parse("").is_err());
+        assert!(TokenAuth::parse("env:HTTP_MINI_TEST_MISSING").is_err());
+        assert!(TokenAuth::parse(format!("{},path=builds", path.display()).as_str()).is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,131 @@
+use crate::errors::invalid_argument::InvalidArgumentError;
+use crate::utils::glob;
+use crate::utils::mount::ServeOptions;
+use std::fs;
+use std::path::PathBuf;
+
+/// # Name-based virtual host
+#[derive(Debug, Clone, PartialEq)]
+pub struct VirtualHost {
+    /// Host name, `*` and `?` wildcards are supported
+    pub pattern: String,
+    /// Directory served for this host
+    pub root: PathBuf,
+    pub options: ServeOptions,
+    /// Serve requests matching no other host
+    pub default: bool,
+}
+
+impl VirtualHost {
+    /// # Parse a virtual host specification
+    ///
+    /// Format: `PATTERN=DIRECTORY[,option[=value]...]`, i.e. `*.example.com=/srv/example`.
+    ///
+    /// Options are `default`, to serve requests matching no other host, and the options of
+    /// [`ServeOptions::parse_option`].
+    pub fn parse(spec: &str) -> Result<VirtualHost, InvalidArgumentError> {
+        let mut parts = spec.split(',');
+        let (pattern, root) = match parts.next().unwrap_or_default().split_once('=') {
+            Some((pattern, root)) if !pattern.is_empty() && !root.is_empty() => (pattern, root),
+            _ => {
+                return Err(InvalidArgumentError::new(spec, "expected HOST=DIRECTORY"));
+            }
+        };
+        let root = fs::canonicalize(root)
+            .map_err(|err| InvalidArgumentError::new(spec, err.to_string().as_str()))?;
+        if !root.is_dir() {
+            return Err(InvalidArgumentError::new(spec, "not a directory"));
+        }
+
+        let mut virtual_host = VirtualHost {
+            pattern: pattern.to_lowercase(),
+            root,
+            options: ServeOptions::default(),
+            default: false,
+        };
+        for option in parts {
+            let (name, value) = option.split_once('=').unwrap_or((option, ""));
+            if name == "default" {
+                virtual_host.default = true;
+                continue;
+            }
+            match virtual_host.options.parse_option(name, value) {
+                Ok(true) => {}
+                Ok(false) => {
+                    return Err(InvalidArgumentError::new(
+                        spec,
+                        format!("unknown virtual host option {}", option).as_str(),
+                    ));
+                }
+                Err(reason) => return Err(InvalidArgumentError::new(spec, reason)),
+            }
+        }
+
+        Ok(virtual_host)
+    }
+}
+
+/// # Virtual host serving a host name
+///
+/// `host` is the `Host` header value without the port. An exact name has precedence over
+/// wildcard patterns, otherwise the first matching pattern wins. Requests matching no
+/// pattern, or without `Host`, go to the default virtual host, if any.
+pub fn select<'a>(virtual_hosts: &'a [VirtualHost], host: Option<&str>) -> Option<&'a VirtualHost> {
+    let host = host.map(|host| host.trim_end_matches('.').to_lowercase());
+    let matched = host.and_then(|host| {
+        virtual_hosts
+            .iter()
+            .find(|virtual_host| virtual_host.pattern == host)
+            .or_else(|| {
+                virtual_hosts
+                    .iter()
+                    .find(|virtual_host| glob::matches(&virtual_host.pattern, &host))
+            })
+    });
+
+    matched.or_else(|| {
+        virtual_hosts
+            .iter()
+            .find(|virtual_host| virtual_host.default)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::virtual_host::{select, VirtualHost};
+
+    #[test]
+    fn test_parse() {
+        let virtual_host =
+            VirtualHost::parse("*.Example.com=src,listing=off,header=X-Frame-Options: DENY")
+                .unwrap();
+        assert_eq!(virtual_host.pattern, "*.example.com");
+        assert!(virtual_host.root.ends_with("src"));
+        assert!(!virtual_host.options.listing);
+        assert_eq!(
+            virtual_host.options.headers,
+            vec![("X-Frame-Options".to_string(), "DENY".to_string())]
+        );
+
+        assert!(VirtualHost::parse("example.com").is_err());
+        assert!(VirtualHost::parse("example.com=/nonexistent/directory").is_err());
+        assert!(VirtualHost::parse("example.com=src,listing=maybe").is_err());
+    }
+
+    #[test]
+    fn test_select() {
+        let virtual_hosts = vec![
+            VirtualHost::parse("*.example.com=src").unwrap(),
+            VirtualHost::parse("www.example.com=src/utils").unwrap(),
+            VirtualHost::parse("other.test=src/traits,default").unwrap(),
+        ];
+        let selected =
+            |host| select(&virtual_hosts, host).map(|virtual_host| virtual_host.pattern.as_str());
+
+        assert_eq!(selected(Some("WWW.example.com")), Some("www.example.com"));
+        assert_eq!(selected(Some("cdn.example.com.")), Some("*.example.com"));
+        assert_eq!(selected(Some("unknown.test")), Some("other.test"));
+        assert_eq!(selected(None), Some("other.test"));
+        assert_eq!(select(&virtual_hosts[..2], Some("unknown.test")), None);
+    }
+}
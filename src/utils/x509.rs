@@ -0,0 +1,272 @@
+use crate::utils::glob;
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_SET: u8 = 0x31;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_OID: u8 = 0x06;
+const TAG_VERSION: u8 = 0xa0;
+const TAG_EXTENSIONS: u8 = 0xa3;
+const TAG_SAN_EMAIL: u8 = 0x81;
+const TAG_SAN_DNS: u8 = 0x82;
+const TAG_SAN_URI: u8 = 0x86;
+const TAG_SAN_IP: u8 = 0x87;
+
+/// 2.5.4.3
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+/// 2.5.29.17
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+
+/// # Identity presented by a client certificate
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ClientIdentity {
+    /// Common name of the subject
+    pub common_name: Option<String>,
+    /// DNS names, email addresses, URIs and IP addresses of the subject alternative names
+    pub alt_names: Vec<String>,
+}
+
+impl ClientIdentity {
+    /// # Check the identity against allow-lists
+    ///
+    /// Allowed when both lists are empty, when the common name matches a pattern of
+    /// `subjects` or when any alternative name matches a pattern of `alt_names`.
+    /// Patterns may use `*` and `?` wildcards.
+    pub fn is_allowed(&self, subjects: &[String], alt_names: &[String]) -> bool {
+        if subjects.is_empty() && alt_names.is_empty() {
+            return true;
+        }
+
+        let subject_allowed = self.common_name.as_ref().is_some_and(|common_name| {
+            subjects
+                .iter()
+                .any(|pattern| glob::matches(pattern, common_name))
+        });
+
+        subject_allowed
+            || self.alt_names.iter().any(|alt_name| {
+                alt_names
+                    .iter()
+                    .any(|pattern| glob::matches(pattern, alt_name))
+            })
+    }
+}
+
+/// # Check a presented client certificate against allow-lists
+///
+/// `identity` is `None` when the certificate could not be parsed, which cannot match a
+/// pattern: such certificates are only allowed when both lists are empty.
+pub fn is_certificate_allowed(
+    identity: Option<&ClientIdentity>,
+    subjects: &[String],
+    alt_names: &[String],
+) -> bool {
+    match identity {
+        Some(identity) => identity.is_allowed(subjects, alt_names),
+        None => subjects.is_empty() && alt_names.is_empty(),
+    }
+}
+
+impl fmt::Display for ClientIdentity {
+    /// Common name, or else the first alternative name
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.common_name, self.alt_names.first()) {
+            (Some(common_name), _) => write!(f, "{}", common_name),
+            (None, Some(alt_name)) => write!(f, "{}", alt_name),
+            (None, None) => write!(f, "-"),
+        }
+    }
+}
+
+/// # DER reader
+struct Der<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Der<'a> {
+    /// # Read the next element as `(tag, contents)`
+    fn next(&mut self) -> Option<(u8, &'a [u8])> {
+        let tag = *self.data.first()?;
+        let first_length = *self.data.get(1)? as usize;
+        let (length, header) = if first_length < 0x80 {
+            (first_length, 2)
+        } else {
+            let count = first_length & 0x7f;
+            if count == 0 || count > 4 {
+                return None;
+            }
+            let mut length = 0usize;
+            for byte in self.data.get(2..2 + count)? {
+                length = (length << 8) | *byte as usize;
+            }
+            (length, 2 + count)
+        };
+
+        let contents = self.data.get(header..header.checked_add(length)?)?;
+        self.data = &self.data[header + length..];
+        Some((tag, contents))
+    }
+
+    /// # Read the next element, which must have `tag`
+    fn expect(&mut self, tag: u8) -> Option<&'a [u8]> {
+        match self.next()? {
+            (found, contents) if found == tag => Some(contents),
+            _ => None,
+        }
+    }
+}
+
+/// # Extract the identity from a DER encoded X.509 certificate
+pub fn get_identity(certificate: &[u8]) -> Option<ClientIdentity> {
+    let certificate = Der { data: certificate }.expect(TAG_SEQUENCE)?;
+    let mut tbs = Der {
+        data: Der { data: certificate }.expect(TAG_SEQUENCE)?,
+    };
+
+    // the version is optional and followed by the serial number
+    let (tag, _) = tbs.next()?;
+    if tag == TAG_VERSION {
+        tbs.next()?;
+    }
+    // signature algorithm, issuer and validity
+    for _ in 0..3 {
+        tbs.next()?;
+    }
+    let subject = tbs.expect(TAG_SEQUENCE)?;
+    tbs.expect(TAG_SEQUENCE)?;
+
+    let mut identity = ClientIdentity {
+        common_name: get_common_name(subject),
+        alt_names: vec![],
+    };
+
+    // issuer and subject unique identifiers are optional, extensions come last
+    while let Some((tag, contents)) = tbs.next() {
+        if tag == TAG_EXTENSIONS {
+            identity.alt_names = get_alt_names(contents).unwrap_or_default();
+        }
+    }
+
+    Some(identity)
+}
+
+/// # Common name of a distinguished name
+fn get_common_name(name: &[u8]) -> Option<String> {
+    let mut rdns = Der { data: name };
+    while let Some(rdn) = rdns.expect(TAG_SET) {
+        let mut attributes = Der { data: rdn };
+        while let Some(attribute) = attributes.expect(TAG_SEQUENCE) {
+            let mut attribute = Der { data: attribute };
+            if attribute.expect(TAG_OID)? == OID_COMMON_NAME {
+                let (_, value) = attribute.next()?;
+                return String::from_utf8(value.to_vec()).ok();
+            }
+        }
+    }
+
+    None
+}
+
+/// # Subject alternative names of the extensions
+fn get_alt_names(extensions: &[u8]) -> Option<Vec<String>> {
+    let mut extensions = Der {
+        data: Der { data: extensions }.expect(TAG_SEQUENCE)?,
+    };
+    while let Some(extension) = extensions.expect(TAG_SEQUENCE) {
+        let mut extension = Der { data: extension };
+        if extension.expect(TAG_OID)? != OID_SUBJECT_ALT_NAME {
+            continue;
+        }
+
+        // the critical flag is optional
+        let value = loop {
+            match extension.next()? {
+                (TAG_OCTET_STRING, value) => break value,
+                _ => continue,
+            }
+        };
+
+        let mut alt_names = vec![];
+        let mut general_names = Der {
+            data: Der { data: value }.expect(TAG_SEQUENCE)?,
+        };
+        while let Some((tag, contents)) = general_names.next() {
+            match tag {
+                TAG_SAN_EMAIL | TAG_SAN_DNS | TAG_SAN_URI => {
+                    if let Ok(alt_name) = String::from_utf8(contents.to_vec()) {
+                        alt_names.push(alt_name);
+                    }
+                }
+                TAG_SAN_IP => {
+                    if let Ok(octets) = <[u8; 4]>::try_from(contents) {
+                        alt_names.push(Ipv4Addr::from(octets).to_string());
+                    } else if let Ok(octets) = <[u8; 16]>::try_from(contents) {
+                        alt_names.push(Ipv6Addr::from(octets).to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+        return Some(alt_names);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::x509::{is_certificate_allowed, ClientIdentity};
+
+    #[test]
+    fn test_is_allowed() {
+        let identity = ClientIdentity {
+            common_name: Option::from("alice".to_string()),
+            alt_names: vec!["alice@example.com".to_string()],
+        };
+        assert!(identity.is_allowed(&[], &[]));
+        assert!(identity.is_allowed(&["al*".to_string()], &[]));
+        assert!(identity.is_allowed(&["bob".to_string()], &["*@example.com".to_string()]));
+        assert!(!identity.is_allowed(&["bob".to_string()], &["*@example.org".to_string()]));
+        assert_eq!(identity.to_string(), "alice");
+    }
+
+    #[test]
+    fn test_is_certificate_allowed() {
+        let identity = ClientIdentity {
+            common_name: Option::from("alice".to_string()),
+            alt_names: vec![],
+        };
+        assert!(is_certificate_allowed(
+            Some(&identity),
+            &["alice".to_string()],
+            &[]
+        ));
+        // unreadable certificates never match an allow-list
+        assert!(!is_certificate_allowed(None, &["alice".to_string()], &[]));
+        assert!(!is_certificate_allowed(None, &[], &["*".to_string()]));
+        assert!(is_certificate_allowed(None, &[], &[]));
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn test_get_identity() {
+        use crate::utils::x509::get_identity;
+        use rcgen::{CertificateParams, DnType, KeyPair};
+
+        let mut params = CertificateParams::new(vec![
+            "client.example.com".to_string(),
+            "10.0.0.1".to_string(),
+        ])
+        .unwrap();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "build-agent");
+        let certificate = params.self_signed(&KeyPair::generate().unwrap()).unwrap();
+
+        let identity = get_identity(certificate.der()).unwrap();
+        assert_eq!(identity.common_name.as_deref(), Some("build-agent"));
+        assert_eq!(identity.alt_names, vec!["client.example.com", "10.0.0.1"]);
+        assert_eq!(get_identity(b"not a certificate"), None);
+    }
+}